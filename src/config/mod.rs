@@ -0,0 +1,591 @@
+use crate::logging::LogFormat;
+use crate::utilities::{default_jobs, HookPolicy};
+use crate::webhook::events::parse_event_filter;
+use crate::watch::{format_interval, parse_interval};
+use crate::webhook::{parse_header, Endpoint, WebhookFormat, WebhookTarget, DEFAULT_EVENTS, DEFAULT_OUTBOX_TTL};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use console::style;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Settings a config section, the environment or the command line can provide.
+/// `None` leaves the value from the previous layer in place.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsLayer {
+    pub urls: Option<Vec<String>>,
+    pub parallel: Option<bool>,
+    pub jobs: Option<usize>,
+    pub atomic: Option<bool>,
+    pub webhook: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub webhook_retries: Option<u32>,
+    pub webhook_headers: Option<Vec<String>>,
+    pub webhook_format: Option<String>,
+    pub webhook_template: Option<String>,
+    pub webhook_events: Option<Vec<String>>,
+    /// How long undelivered webhooks stay in the outbox, e.g. `7d`.
+    pub webhook_outbox_ttl: Option<String>,
+    /// Additional endpoints, only settable in the config file.
+    pub webhooks: Option<Vec<EndpointConfig>>,
+    pub hook_policy: Option<String>,
+    /// Prometheus textfile written after each run.
+    pub metrics_file: Option<String>,
+    /// File that receives log records, at debug level or above.
+    pub log_file: Option<String>,
+    pub log_format: Option<String>,
+    pub categories: Option<Vec<String>>,
+    pub allowed_categories: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// One `[[webhooks]]` entry: an endpoint with its own delivery settings and event filter.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointConfig {
+    pub url: String,
+    pub events: Option<Vec<String>>,
+    pub secret: Option<String>,
+    pub retries: Option<u32>,
+    pub headers: Option<Vec<String>>,
+    pub format: Option<String>,
+    pub template: Option<String>,
+}
+
+impl EndpointConfig {
+    fn endpoint(&self) -> Result<Endpoint, String> {
+        let format = match self.format {
+            Some(ref format) => WebhookFormat::parse(format).ok_or_else(|| {
+                format!("Invalid format '{}' for webhook {}: use generic, slack, discord or teams", format, self.url)
+            })?,
+            None => WebhookFormat::Generic,
+        };
+
+        Ok(Endpoint {
+            target: WebhookTarget {
+                url: self.url.clone(),
+                secret: self.secret.clone().filter(|s| !s.is_empty()),
+                retries: self.retries.unwrap_or(DEFAULT_WEBHOOK_RETRIES),
+                headers: self
+                    .headers
+                    .iter()
+                    .flatten()
+                    .map(|h| parse_header(h))
+                    .collect::<Result<_, _>>()?,
+                format,
+                template: self.template.as_ref().map(PathBuf::from),
+            },
+            events: event_filter(self.events.as_deref())?,
+        })
+    }
+}
+
+const DEFAULT_WEBHOOK_RETRIES: u32 = 3;
+
+/// The given event patterns, or `DEFAULT_EVENTS` when there are none.
+fn event_filter(events: Option<&[String]>) -> Result<Vec<String>, String> {
+    match events {
+        Some(events) if !events.is_empty() => parse_event_filter(events),
+        _ => Ok(DEFAULT_EVENTS.iter().map(|e| e.to_string()).collect()),
+    }
+}
+
+/// Contents of `config.toml`: a `[defaults]` table and any number of `[profiles.<name>]` tables.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub defaults: SettingsLayer,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, SettingsLayer>,
+}
+
+/// Where an effective setting came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    Default,
+    ConfigFile,
+    Profile,
+    Environment,
+    CommandLine,
+}
+
+impl Origin {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Origin::Default => "default",
+            Origin::ConfigFile => "config file",
+            Origin::Profile => "profile",
+            Origin::Environment => "environment",
+            Origin::CommandLine => "command line",
+        }
+    }
+}
+
+/// Effective settings after layering defaults, config file, profile,
+/// `BRIM_*` environment variables and command-line flags.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub profile: Option<String>,
+    pub urls: Vec<String>,
+    pub parallel: bool,
+    pub jobs: usize,
+    pub atomic: bool,
+    pub webhook: Option<String>,
+    /// Shared secret for signing webhook payloads.
+    pub webhook_secret: Option<String>,
+    pub webhook_retries: u32,
+    /// Extra webhook request headers as name and value.
+    pub webhook_headers: Vec<(String, String)>,
+    pub webhook_format: WebhookFormat,
+    /// Payload template file; overrides `webhook_format`.
+    pub webhook_template: Option<PathBuf>,
+    /// Event patterns the `webhook` URL receives.
+    pub webhook_events: Vec<String>,
+    /// How long a failed delivery is retried before it is dropped from the outbox.
+    pub webhook_outbox_ttl: Duration,
+    /// Endpoints from `[[webhooks]]` tables, in addition to `webhook`.
+    pub webhooks: Vec<Endpoint>,
+    pub hook_policy: HookPolicy,
+    /// Prometheus textfile for node_exporter, rewritten after each run.
+    pub metrics_file: Option<PathBuf>,
+    /// Log file written alongside the terminal output.
+    pub log_file: Option<PathBuf>,
+    pub log_format: LogFormat,
+    pub categories: Vec<String>,
+    /// Categories `brim lint` accepts; empty allows any.
+    pub allowed_categories: Vec<String>,
+    /// Machine tags for recipe `when` expressions.
+    pub tags: Vec<String>,
+    origins: BTreeMap<&'static str, Origin>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            profile: None,
+            urls: vec![],
+            parallel: false,
+            jobs: default_jobs(),
+            atomic: false,
+            webhook: None,
+            webhook_secret: None,
+            webhook_retries: DEFAULT_WEBHOOK_RETRIES,
+            webhook_headers: vec![],
+            webhook_format: WebhookFormat::Generic,
+            webhook_template: None,
+            webhook_events: DEFAULT_EVENTS.iter().map(|e| e.to_string()).collect(),
+            webhook_outbox_ttl: DEFAULT_OUTBOX_TTL,
+            webhooks: vec![],
+            hook_policy: HookPolicy::Prompt,
+            metrics_file: None,
+            log_file: None,
+            log_format: LogFormat::Text,
+            categories: vec![],
+            allowed_categories: vec![],
+            tags: vec![],
+            origins: BTreeMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn origin(&self, key: &str) -> Origin {
+        self.origins.get(key).copied().unwrap_or(Origin::Default)
+    }
+
+    /// Every endpoint lifecycle events go to: `webhook`, then any `[[webhooks]]`.
+    pub fn webhook_endpoints(&self) -> Vec<Endpoint> {
+        let primary = self.webhook_target().map(|target| Endpoint {
+            target,
+            events: self.webhook_events.clone(),
+        });
+        primary.into_iter().chain(self.webhooks.iter().cloned()).collect()
+    }
+
+    /// The `webhook` URL with its delivery settings, if one is configured.
+    pub fn webhook_target(&self) -> Option<WebhookTarget> {
+        self.webhook.as_ref().map(|url| WebhookTarget {
+            url: url.clone(),
+            secret: self.webhook_secret.clone(),
+            retries: self.webhook_retries,
+            headers: self.webhook_headers.clone(),
+            format: self.webhook_format,
+            template: self.webhook_template.clone(),
+        })
+    }
+
+    fn apply(&mut self, layer: &SettingsLayer, origin: Origin) -> Result<(), String> {
+        if let Some(ref urls) = layer.urls {
+            self.urls = urls.clone();
+            self.origins.insert("urls", origin);
+        }
+        if let Some(parallel) = layer.parallel {
+            self.parallel = parallel;
+            self.origins.insert("parallel", origin);
+        }
+        if let Some(jobs) = layer.jobs {
+            if jobs == 0 {
+                return Err(format!("jobs must be at least 1 ({})", origin.label()));
+            }
+            self.jobs = jobs;
+            self.origins.insert("jobs", origin);
+        }
+        if let Some(atomic) = layer.atomic {
+            self.atomic = atomic;
+            self.origins.insert("atomic", origin);
+        }
+        if let Some(ref webhook) = layer.webhook {
+            self.webhook = Some(webhook.clone()).filter(|w| !w.is_empty());
+            self.origins.insert("webhook", origin);
+        }
+        if let Some(ref secret) = layer.webhook_secret {
+            self.webhook_secret = Some(secret.clone()).filter(|s| !s.is_empty());
+            self.origins.insert("webhook_secret", origin);
+        }
+        if let Some(retries) = layer.webhook_retries {
+            self.webhook_retries = retries;
+            self.origins.insert("webhook_retries", origin);
+        }
+        if let Some(ref headers) = layer.webhook_headers {
+            self.webhook_headers = headers
+                .iter()
+                .map(|h| parse_header(h).map_err(|e| format!("{} ({})", e, origin.label())))
+                .collect::<Result<_, _>>()?;
+            self.origins.insert("webhook_headers", origin);
+        }
+        if let Some(ref format) = layer.webhook_format {
+            self.webhook_format = WebhookFormat::parse(format).ok_or_else(|| {
+                format!(
+                    "Invalid webhook_format '{}' ({}): use generic, slack, discord or teams",
+                    format,
+                    origin.label()
+                )
+            })?;
+            self.origins.insert("webhook_format", origin);
+        }
+        if let Some(ref template) = layer.webhook_template {
+            self.webhook_template = Some(PathBuf::from(template)).filter(|t| !t.as_os_str().is_empty());
+            self.origins.insert("webhook_template", origin);
+        }
+        if let Some(ref events) = layer.webhook_events {
+            self.webhook_events = event_filter(Some(events)).map_err(|e| format!("{} ({})", e, origin.label()))?;
+            self.origins.insert("webhook_events", origin);
+        }
+        if let Some(ref ttl) = layer.webhook_outbox_ttl {
+            self.webhook_outbox_ttl =
+                parse_interval(ttl).map_err(|e| format!("Invalid webhook_outbox_ttl: {} ({})", e, origin.label()))?;
+            self.origins.insert("webhook_outbox_ttl", origin);
+        }
+        if let Some(ref webhooks) = layer.webhooks {
+            self.webhooks = webhooks
+                .iter()
+                .map(|w| w.endpoint().map_err(|e| format!("{} ({})", e, origin.label())))
+                .collect::<Result<_, _>>()?;
+            self.origins.insert("webhooks", origin);
+        }
+        if let Some(ref policy) = layer.hook_policy {
+            self.hook_policy = HookPolicy::parse(policy).ok_or_else(|| {
+                format!("Invalid hook_policy '{}' ({}): use prompt, trust, local or skip", policy, origin.label())
+            })?;
+            self.origins.insert("hook_policy", origin);
+        }
+        if let Some(ref path) = layer.metrics_file {
+            self.metrics_file = Some(PathBuf::from(path)).filter(|p| !p.as_os_str().is_empty());
+            self.origins.insert("metrics_file", origin);
+        }
+        if let Some(ref path) = layer.log_file {
+            self.log_file = Some(PathBuf::from(path)).filter(|p| !p.as_os_str().is_empty());
+            self.origins.insert("log_file", origin);
+        }
+        if let Some(ref format) = layer.log_format {
+            self.log_format = LogFormat::parse(format)
+                .ok_or_else(|| format!("Invalid log_format '{}' ({}): use text or json", format, origin.label()))?;
+            self.origins.insert("log_format", origin);
+        }
+        if let Some(ref categories) = layer.categories {
+            self.categories = categories.clone();
+            self.origins.insert("categories", origin);
+        }
+        if let Some(ref allowed) = layer.allowed_categories {
+            self.allowed_categories = allowed.clone();
+            self.origins.insert("allowed_categories", origin);
+        }
+        if let Some(ref tags) = layer.tags {
+            self.tags = tags.clone();
+            self.origins.insert("tags", origin);
+        }
+        Ok(())
+    }
+}
+
+/// Path of the config file: `$BRIM_CONFIG`, else `$XDG_CONFIG_HOME/brim/config.toml`,
+/// else `~/.config/brim/config.toml`.
+pub fn config_path() -> PathBuf {
+    if let Some(path) = env::var_os("BRIM_CONFIG").filter(|p| !p.is_empty()) {
+        return PathBuf::from(path);
+    }
+
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME").map(PathBuf::from).unwrap_or_else(env::temp_dir).join(".config"),
+    };
+    base.join("brim").join("config.toml")
+}
+
+pub fn parse_config(content: &str) -> Result<ConfigFile, String> {
+    toml::from_str(content).map_err(|e| e.to_string())
+}
+
+fn load_config_file() -> Result<Option<ConfigFile>, String> {
+    let path = config_path();
+    match fs::read_to_string(&path) {
+        Ok(content) => parse_config(&content)
+            .map(Some)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Could not read config file {}: {}", path.display(), e)),
+    }
+}
+
+/// Comma-separated list, with blanks dropped.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Reads the `BRIM_*` variables through `var`.
+pub fn env_layer(var: impl Fn(&str) -> Option<String>) -> Result<SettingsLayer, String> {
+    let flag = |name: &str| -> Result<Option<bool>, String> {
+        match var(name).as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("") => Ok(None),
+            Some("1") | Some("true") | Some("yes") => Ok(Some(true)),
+            Some("0") | Some("false") | Some("no") => Ok(Some(false)),
+            Some(other) => Err(format!("Invalid {}='{}': use true or false", name, other)),
+        }
+    };
+
+    let jobs = match var("BRIM_JOBS").filter(|v| !v.is_empty()) {
+        Some(value) => Some(
+            value
+                .parse()
+                .map_err(|_| format!("Invalid BRIM_JOBS='{}': expected a number", value))?,
+        ),
+        None => None,
+    };
+
+    let webhook_retries = match var("BRIM_WEBHOOK_RETRIES").filter(|v| !v.is_empty()) {
+        Some(value) => Some(
+            value
+                .parse()
+                .map_err(|_| format!("Invalid BRIM_WEBHOOK_RETRIES='{}': expected a number", value))?,
+        ),
+        None => None,
+    };
+
+    Ok(SettingsLayer {
+        urls: var("BRIM_URL").map(|v| split_list(&v)),
+        parallel: flag("BRIM_PARALLEL")?,
+        jobs,
+        atomic: flag("BRIM_ATOMIC")?,
+        webhook: var("BRIM_WEBHOOK"),
+        webhook_secret: var("BRIM_WEBHOOK_SECRET"),
+        webhook_retries,
+        // Header values may contain commas, so headers are separated by newlines.
+        webhook_headers: var("BRIM_WEBHOOK_HEADERS")
+            .map(|v| v.lines().map(str::trim).filter(|h| !h.is_empty()).map(String::from).collect()),
+        webhook_format: var("BRIM_WEBHOOK_FORMAT").filter(|v| !v.is_empty()),
+        webhook_template: var("BRIM_WEBHOOK_TEMPLATE"),
+        webhook_events: var("BRIM_WEBHOOK_EVENTS").map(|v| split_list(&v)),
+        webhook_outbox_ttl: var("BRIM_WEBHOOK_OUTBOX_TTL").filter(|v| !v.is_empty()),
+        webhooks: None,
+        hook_policy: var("BRIM_HOOK_POLICY").filter(|v| !v.is_empty()),
+        metrics_file: var("BRIM_METRICS_FILE"),
+        log_file: var("BRIM_LOG_FILE"),
+        log_format: var("BRIM_LOG_FORMAT").filter(|v| !v.is_empty()),
+        categories: var("BRIM_CATEGORIES").map(|v| split_list(&v)),
+        allowed_categories: var("BRIM_ALLOWED_CATEGORIES").map(|v| split_list(&v)),
+        tags: var("BRIM_TAGS").map(|v| split_list(&v)),
+    })
+}
+
+/// Recipe sources from every `--url` flag, with comma-separated values split out.
+fn recipe_urls(matches: &ArgMatches) -> Vec<String> {
+    matches
+        .get_many::<String>("url")
+        .map(|urls| urls.flat_map(|u| split_list(u)).collect())
+        .unwrap_or_default()
+}
+
+/// Only values given explicitly on the command line; clap defaults don't count.
+fn cli_layer(matches: &ArgMatches) -> SettingsLayer {
+    let explicit = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let flag = |id: &str| Some(true).filter(|_| explicit(id) && matches.get_flag(id));
+
+    SettingsLayer {
+        urls: Some(recipe_urls(matches)).filter(|u| !u.is_empty()),
+        parallel: flag("parallel"),
+        jobs: matches.get_one::<usize>("jobs").copied(),
+        atomic: flag("atomic"),
+        webhook: matches.get_one::<String>("webhook").cloned(),
+        webhook_secret: None,
+        webhook_retries: matches.get_one::<u32>("webhook-retries").copied(),
+        webhook_headers: matches
+            .get_many::<String>("webhook-header")
+            .map(|h| h.cloned().collect()),
+        webhook_format: matches.get_one::<String>("webhook-format").cloned(),
+        webhook_template: matches.get_one::<String>("webhook-template").cloned(),
+        webhook_events: matches
+            .get_many::<String>("webhook-events")
+            .map(|e| e.flat_map(|v| split_list(v)).collect()),
+        webhook_outbox_ttl: None,
+        webhooks: None,
+        hook_policy: matches.get_one::<String>("hook-policy").filter(|_| explicit("hook-policy")).cloned(),
+        metrics_file: matches.get_one::<String>("metrics-file").cloned(),
+        log_file: matches.get_one::<String>("log-file").cloned(),
+        log_format: matches.get_one::<String>("log-format").cloned(),
+        categories: matches
+            .get_many::<String>("category")
+            .map(|c| c.flat_map(|v| split_list(v)).collect()),
+        allowed_categories: matches
+            .subcommand_matches("lint")
+            .and_then(|lint| lint.get_many::<String>("allowed-categories"))
+            .map(|c| c.flat_map(|v| split_list(v)).collect()),
+        tags: matches
+            .get_many::<String>("tag")
+            .map(|t| t.flat_map(|v| split_list(v)).collect()),
+    }
+}
+
+/// Layers the given sources over the built-in defaults.
+pub fn resolve_layers(
+    file: Option<&ConfigFile>,
+    profile: Option<&str>,
+    env: &SettingsLayer,
+    cli: &SettingsLayer,
+) -> Result<Settings, String> {
+    let mut settings = Settings::default();
+
+    if let Some(file) = file {
+        settings.apply(&file.defaults, Origin::ConfigFile)?;
+    }
+
+    if let Some(name) = profile {
+        let section = file
+            .and_then(|f| f.profiles.get(name))
+            .ok_or_else(|| format!("Profile '{}' is not defined in {}", name, config_path().display()))?;
+        settings.apply(section, Origin::Profile)?;
+        settings.profile = Some(name.to_string());
+    }
+
+    settings.apply(env, Origin::Environment)?;
+    settings.apply(cli, Origin::CommandLine)?;
+    Ok(settings)
+}
+
+/// Effective settings for this invocation. `--profile` wins over `BRIM_PROFILE`.
+pub fn resolve_settings(matches: &ArgMatches) -> Result<Settings, String> {
+    let file = load_config_file()?;
+    let profile = matches
+        .get_one::<String>("profile")
+        .cloned()
+        .or_else(|| env::var("BRIM_PROFILE").ok().filter(|p| !p.is_empty()));
+    let env = env_layer(|name| env::var(name).ok())?;
+
+    resolve_layers(file.as_ref(), profile.as_deref(), &env, &cli_layer(matches))
+}
+
+pub fn print_settings(settings: &Settings) {
+    let path = config_path();
+    let state = if path.exists() { "" } else { " (not found)" };
+    println!("\n  {} {}{}", style("Config file:").bold(), path.display(), style(state).dim());
+    println!(
+        "  {} {}",
+        style("Profile:").bold(),
+        settings.profile.as_deref().unwrap_or("(none)")
+    );
+    println!();
+
+    let list = |values: &[String]| {
+        if values.is_empty() {
+            "(none)".to_string()
+        } else {
+            values.join(", ")
+        }
+    };
+    let rows = [
+        ("urls", list(&settings.urls)),
+        ("parallel", settings.parallel.to_string()),
+        ("jobs", settings.jobs.to_string()),
+        ("atomic", settings.atomic.to_string()),
+        ("webhook", settings.webhook.clone().unwrap_or_else(|| "(none)".to_string())),
+        ("webhook_secret", if settings.webhook_secret.is_some() { "(set)" } else { "(none)" }.to_string()),
+        ("webhook_retries", settings.webhook_retries.to_string()),
+        // Header values often carry credentials, so only names are shown.
+        (
+            "webhook_headers",
+            list(&settings.webhook_headers.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>()),
+        ),
+        ("webhook_events", list(&settings.webhook_events)),
+        ("webhook_format", settings.webhook_format.label().to_string()),
+        (
+            "webhook_template",
+            settings
+                .webhook_template
+                .as_ref()
+                .map(|t| t.display().to_string())
+                .unwrap_or_else(|| "(none)".to_string()),
+        ),
+        ("webhook_outbox_ttl", format_interval(settings.webhook_outbox_ttl)),
+        (
+            "webhooks",
+            list(
+                &settings
+                    .webhooks
+                    .iter()
+                    .map(|w| format!("{} ({})", w.target.url, w.events.join(" ")))
+                    .collect::<Vec<_>>(),
+            ),
+        ),
+        ("hook_policy", settings.hook_policy.label().to_string()),
+        (
+            "metrics_file",
+            settings
+                .metrics_file
+                .as_ref()
+                .map(|m| m.display().to_string())
+                .unwrap_or_else(|| "(none)".to_string()),
+        ),
+        (
+            "log_file",
+            settings
+                .log_file
+                .as_ref()
+                .map(|l| l.display().to_string())
+                .unwrap_or_else(|| "(none)".to_string()),
+        ),
+        ("log_format", settings.log_format.label().to_string()),
+        ("categories", list(&settings.categories)),
+        ("allowed_categories", list(&settings.allowed_categories)),
+        ("tags", list(&settings.tags)),
+    ];
+
+    for (key, value) in rows {
+        println!(
+            "  {:<18} {} {}",
+            style(key).cyan(),
+            value,
+            style(format!("[{}]", settings.origin(key).label())).dim()
+        );
+    }
+    println!();
+}
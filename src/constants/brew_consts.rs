@@ -1 +1,2 @@
-pub const PROGRAM: &str = "brew";
\ No newline at end of file
+pub const PROGRAM: &str = "brew";
+pub const DEFAULT_FETCH_JOBS: usize = 4;
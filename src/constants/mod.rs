@@ -1,3 +1,3 @@
 pub mod brew_consts;
 
-pub use brew_consts::PROGRAM;
\ No newline at end of file
+pub use brew_consts::{DEFAULT_FETCH_JOBS, PROGRAM};
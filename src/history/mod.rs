@@ -0,0 +1,154 @@
+use crate::models::BrewPackage;
+use crate::utilities::brim_state::state_dir;
+use crate::webhook::PackageResult;
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// One brim invocation as stored in the local run history.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: String,
+    /// `install`, `upgrade`, `remove`, `sync`, `watch` or `undo`.
+    pub operation: String,
+    /// RFC 3339 start time.
+    pub timestamp: String,
+    pub sources: Vec<String>,
+    pub selected: Vec<String>,
+    pub packages: Vec<PackageResult>,
+    /// Packages that were not installed before this run and are now.
+    #[serde(default)]
+    pub newly_installed: Vec<String>,
+}
+
+impl RunRecord {
+    pub fn new(id: &str, operation: &str, timestamp: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            operation: operation.to_string(),
+            timestamp: timestamp.to_string(),
+            sources: vec![],
+            selected: vec![],
+            packages: vec![],
+            newly_installed: vec![],
+        }
+    }
+
+    /// `name` as this run worked on it. Casks keep their flag, since they need
+    /// `--cask` to be uninstalled.
+    pub fn package(&self, name: &str) -> BrewPackage {
+        BrewPackage {
+            cask: self.packages.iter().any(|p| p.name == name && p.cask).then_some(true),
+            ..BrewPackage::named(name)
+        }
+    }
+
+    fn count(&self, status: &str) -> usize {
+        self.packages.iter().filter(|p| p.status == status).count()
+    }
+}
+
+fn history_dir() -> PathBuf {
+    state_dir().join("history")
+}
+
+pub fn record_run(record: &RunRecord) -> io::Result<()> {
+    let dir = history_dir();
+    fs::create_dir_all(&dir)?;
+
+    let json = serde_json::to_string_pretty(record).map_err(io::Error::other)?;
+    fs::write(dir.join(format!("{}.json", record.id)), json)
+}
+
+/// Names of packages the run installed that were not installed before it,
+/// including those whose `post_install` hook failed afterwards.
+pub fn newly_installed(results: &[PackageResult], installed_before: &[BrewPackage]) -> Vec<String> {
+    results
+        .iter()
+        .filter(|r| r.installed)
+        .filter(|r| !installed_before.iter().any(|p| p.name == r.name))
+        .map(|r| r.name.clone())
+        .collect()
+}
+
+/// Records `record`, warning instead of failing when the history cannot be written.
+pub fn save_history(record: &RunRecord) {
+    if let Err(e) = record_run(record) {
+        log::warn!(target: "history", "Failed to record run history: {}", e);
+    }
+}
+
+/// All recorded runs, oldest first. Unreadable entries are skipped.
+pub fn load_runs() -> Vec<RunRecord> {
+    let Ok(entries) = fs::read_dir(history_dir()) else {
+        return vec![];
+    };
+
+    let mut runs: Vec<RunRecord> = entries
+        .filter_map(Result::ok)
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| fs::read_to_string(e.path()).ok())
+        .filter_map(|content| serde_json::from_str(&content).ok())
+        .collect();
+
+    runs.sort_by(|a, b| a.id.cmp(&b.id));
+    runs
+}
+
+/// Finds a run by exact id, unique id prefix, or `last`.
+pub fn find_run(query: &str) -> Result<RunRecord, String> {
+    let mut runs = load_runs();
+
+    if query == "last" {
+        return runs.pop().ok_or_else(|| "No runs recorded yet".to_string());
+    }
+
+    if let Some(position) = runs.iter().position(|r| r.id == query) {
+        return Ok(runs.swap_remove(position));
+    }
+
+    let mut matches: Vec<RunRecord> = runs.into_iter().filter(|r| r.id.starts_with(query)).collect();
+    match matches.len() {
+        0 => Err(format!("No run matches '{}'", query)),
+        1 => Ok(matches.remove(0)),
+        n => Err(format!("'{}' matches {} runs; use a longer id", query, n)),
+    }
+}
+
+pub fn print_history(runs: &[RunRecord]) {
+    if runs.is_empty() {
+        println!("\n{} No runs recorded yet.", style("ℹ").cyan().bold());
+        println!("  History is stored in {}", style(history_dir().display()).dim());
+        return;
+    }
+
+    println!("\n{}", style(format!("Total: {} runs", runs.len())).yellow().bold());
+    println!();
+
+    for run in runs.iter().rev() {
+        let counts = if run.operation == "sync" {
+            format!("{} missing, {} extra", run.count("missing"), run.count("extra"))
+        } else {
+            format!(
+                "{} completed, {} failed, {} newly installed",
+                run.count("completed"),
+                run.count("failed"),
+                run.newly_installed.len()
+            )
+        };
+
+        println!(
+            "  {} {} {} {}",
+            style(&run.id).cyan().bold(),
+            style(&run.timestamp).dim(),
+            style(format!("{:<7}", run.operation)).yellow(),
+            counts
+        );
+        if !run.sources.is_empty() {
+            println!("      {}", style(run.sources.join(", ")).dim());
+        }
+    }
+    println!();
+}
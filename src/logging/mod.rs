@@ -0,0 +1,297 @@
+use console::style;
+use log::kv::{Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// How log records are written, both to the terminal and to `--log-file`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// `LEVEL target: message key=value ...`, one record per line.
+    Text,
+    /// One JSON object per line with `ts`, `level`, `target`, `message` and the record's fields.
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+/// Terminal log level for `-v` repeated `verbose` times, or `-q`.
+/// Warnings and errors show by default; `-v` adds info, `-vv` debug and `-vvv` trace.
+pub fn level_for(verbose: u8, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::Error;
+    }
+    match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+struct Logger {
+    level: LevelFilter,
+    format: LogFormat,
+    file: Option<Mutex<File>>,
+    file_level: LevelFilter,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Terminal lines held back while the progress TUI owns the screen; `None` when nothing is held.
+static HELD: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Installs the logger. The log file records at least debug detail, whatever the terminal level.
+pub fn init(level: LevelFilter, format: LogFormat, file: Option<&Path>) -> Result<(), String> {
+    let file = match file {
+        Some(path) => {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create log directory {}: {}", parent.display(), e))?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open log file {}: {}", path.display(), e))?;
+            Some(Mutex::new(file))
+        }
+        None => None,
+    };
+    let file_level = if file.is_some() { level.max(LevelFilter::Debug) } else { LevelFilter::Off };
+
+    let logger = LOGGER.get_or_init(|| Logger {
+        level,
+        format,
+        file,
+        file_level,
+    });
+    log::set_logger(logger).map_err(|e| format!("Failed to set up logging: {}", e))?;
+    log::set_max_level(logger.level.max(logger.file_level));
+    Ok(())
+}
+
+impl Logger {
+    /// Dependencies such as reqwest log too, but their records only show at trace level.
+    fn accepts(&self, record: &Record, level: LevelFilter) -> bool {
+        let ours = record
+            .module_path()
+            .is_none_or(|module| module.starts_with(env!("CARGO_CRATE_NAME")));
+        record.level() <= level && (ours || level == LevelFilter::Trace)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level.max(self.file_level)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.file.is_none() && !self.accepts(record, self.level) {
+            return;
+        }
+        let timestamp = chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false);
+
+        if let Some(ref file) = self.file {
+            if self.accepts(record, self.file_level) {
+                if let Ok(mut file) = file.lock() {
+                    let _ = writeln!(file, "{}", format_record(record, self.format, Some(&timestamp)));
+                }
+            }
+        }
+
+        if self.accepts(record, self.level) {
+            let line = match self.format {
+                LogFormat::Text => terminal_line(record),
+                LogFormat::Json => format_record(record, self.format, Some(&timestamp)),
+            };
+            write_terminal(line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref file) = self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+fn write_terminal(line: String) {
+    if let Ok(mut held) = HELD.lock() {
+        if let Some(held) = held.as_mut() {
+            held.push(line);
+            return;
+        }
+    }
+    eprintln!("{}", line);
+}
+
+/// Holds terminal log lines until [`release_terminal`], so nothing is drawn
+/// over the ratatui alternate screen. The log file is still written as records arrive.
+pub fn hold_terminal() {
+    if let Ok(mut held) = HELD.lock() {
+        held.get_or_insert_with(Vec::new);
+    }
+}
+
+/// Prints the lines held since [`hold_terminal`] and writes straight to the terminal again.
+pub fn release_terminal() {
+    let lines = HELD.lock().ok().and_then(|mut held| held.take()).unwrap_or_default();
+    for line in lines {
+        eprintln!("{}", line);
+    }
+}
+
+/// Renders a record as a line of `format`; text lines start with the timestamp when one is given.
+pub fn format_record(record: &Record, format: LogFormat, timestamp: Option<&str>) -> String {
+    match format {
+        LogFormat::Text => {
+            let mut line = String::new();
+            if let Some(timestamp) = timestamp {
+                line.push_str(timestamp);
+                line.push(' ');
+            }
+            line.push_str(&format!("{:<5} {}: {}", record.level(), record.target(), record.args()));
+            line.push_str(&text_fields(record));
+            line
+        }
+        LogFormat::Json => {
+            // Built by hand so the standard keys come first, followed by the fields in record order.
+            let mut fields = JsonFields(Vec::new());
+            if let Some(timestamp) = timestamp {
+                fields.0.push(("ts".to_string(), timestamp.into()));
+            }
+            fields.0.push(("level".to_string(), record.level().as_str().to_lowercase().into()));
+            fields.0.push(("target".to_string(), record.target().into()));
+            fields.0.push(("message".to_string(), record.args().to_string().into()));
+            let _ = record.key_values().visit(&mut fields);
+
+            let pairs: Vec<String> = fields
+                .0
+                .iter()
+                .map(|(key, value)| format!("{}:{}", serde_json::Value::from(key.as_str()), value))
+                .collect();
+            format!("{{{}}}", pairs.join(","))
+        }
+    }
+}
+
+/// A text record for stderr, with the level coloured and no timestamp.
+fn terminal_line(record: &Record) -> String {
+    let level = format!("{:<5}", record.level());
+    let level = match record.level() {
+        Level::Error => style(level).for_stderr().red().bold(),
+        Level::Warn => style(level).for_stderr().yellow().bold(),
+        Level::Info => style(level).for_stderr().cyan(),
+        Level::Debug | Level::Trace => style(level).for_stderr().dim(),
+    };
+    format!(
+        "{} {} {}{}",
+        level,
+        style(format!("{}:", record.target())).for_stderr().dim(),
+        record.args(),
+        style(text_fields(record)).for_stderr().dim()
+    )
+}
+
+fn text_fields(record: &Record) -> String {
+    let mut fields = TextFields(String::new());
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
+
+struct TextFields(String);
+
+impl<'kvs> VisitSource<'kvs> for TextFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = value.to_string();
+        // Quote values that would otherwise be ambiguous in `key=value` form.
+        if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+            self.0.push_str(&format!(" {}={:?}", key, value));
+        } else {
+            self.0.push_str(&format!(" {}={}", key, value));
+        }
+        Ok(())
+    }
+}
+
+struct JsonFields(Vec<(String, serde_json::Value)>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let json = if let Some(flag) = value.to_bool() {
+            flag.into()
+        } else if let Some(number) = value.to_u64() {
+            number.into()
+        } else if let Some(number) = value.to_i64() {
+            number.into()
+        } else if let Some(number) = value.to_f64() {
+            serde_json::Number::from_f64(number).map_or(serde_json::Value::Null, serde_json::Value::Number)
+        } else {
+            value.to_string().into()
+        };
+        self.0.push((key.to_string(), json));
+        Ok(())
+    }
+}
+
+/// The program and its arguments as one line, e.g. `brew install --cask firefox`.
+pub fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Runs `command` to completion like [`Command::output`], logging the command,
+/// its exit status and how long it took.
+pub fn output(command: &mut Command) -> io::Result<Output> {
+    let line = command_line(command);
+    log::debug!(target: "command", command = line.as_str(); "Running {}", line);
+
+    let started = Instant::now();
+    let result = command.output();
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(ref output) => log::debug!(
+            target: "command",
+            command = line.as_str(),
+            code = output.status.code().unwrap_or(-1),
+            duration_ms = duration_ms;
+            "Finished {}",
+            line
+        ),
+        Err(ref e) => log::warn!(
+            target: "command",
+            command = line.as_str(),
+            error:% = e,
+            duration_ms = duration_ms;
+            "Failed to run {}",
+            line
+        ),
+    }
+    result
+}
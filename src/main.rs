@@ -1,18 +1,39 @@
 use std::collections::HashMap;
+use std::path::Path;
 use std::time::Instant;
 
 use clap::{Arg, Command};
 use console::{style, Color, StyledObject};
-use dialoguer::MultiSelect;
+use dialoguer::{Confirm, MultiSelect};
 
+use config::{print_settings, resolve_settings, Settings};
+use history::{find_run, load_runs, newly_installed, print_history, save_history, RunRecord};
 use models::BrewPackage;
-use utilities::{fetch_packages, install_packages, list_installed_packages, remove_packages};
-use webhook::{post_webhook, WebhookPayload};
+use utilities::{
+    create_run_log_dir, fetch_packages, fetch_recipe_text, install_packages, list_installed_packages,
+    list_outdated_packages, new_run_id, pin_allows, remove_packages, apply_hook_policy, print_package_hooks,
+    error_at, fetch_metadata, lint_recipe, merge_sources, renamed_formula, validate_recipe, HomebrewIndex,
+    HookPolicy, LintFinding, LintSeverity, InstallOperation, InstallOptions, OutdatedPackage, Platform,
+    print_skipped_packages, SkippedPackage, diff_recipes, print_recipe_diff, build_recipe, list_requested_packages, parse_category_map, ExportOptions,
+    compute_sync_diff, failed_source, fetch_and_merge, SyncDiff,
+};
+use metrics::{Metrics, MetricsState};
+use report::{Report, ReportFormat};
+use webhook::{
+    exit_code_for, flush_outbox, outbox_dir, EventDispatcher, FlushOptions, FlushReport, PackageResult, RunContext,
+    WebhookPayload,
+};
 
+mod config;
 mod constants;
+mod history;
+mod logging;
+mod metrics;
 mod models;
+mod report;
 mod tui;
 mod utilities;
+mod watch;
 mod webhook;
 
 #[cfg(test)]
@@ -27,11 +48,15 @@ fn print_header(title: &str, color: Color) {
 #[tokio::main]
 async fn main() {
     let start_time = Instant::now();
+    let started_at = chrono::Local::now().to_rfc3339();
+    let run_id = new_run_id();
+    let mut exit_code = 0;
 
     let matches = Command::new("BRIM")
         .arg(
             Arg::new("url")
                 .long("url")
+                .global(true)
                 .value_name("URL")
                 .action(clap::ArgAction::Append)
                 .help("Recipe file(s): separate multiple with commas or repeat flag"),
@@ -57,35 +82,380 @@ async fn main() {
                 .long("parallel")
                 .action(clap::ArgAction::SetTrue)
                 .help("Parallel download + sequential install (faster, safe)"))
+        .arg(
+            Arg::new("jobs")
+                .long("jobs")
+                .global(true)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Maximum concurrent downloads in --parallel mode (default: CPU count)"))
+        .arg(
+            Arg::new("atomic")
+                .long("atomic")
+                .action(clap::ArgAction::SetTrue)
+                .help("Roll back every newly installed package if any package fails"))
+        .arg(
+            Arg::new("hook-policy")
+                .long("hook-policy")
+                .value_name("POLICY")
+                .value_parser(["prompt", "trust", "local", "skip"])
+                .default_value("prompt")
+                .help("Which recipe pre/post-install hooks to run: prompt (ask for remote recipes), trust, local or skip"))
+        .arg(
+            Arg::new("metrics-file")
+                .long("metrics-file")
+                .value_name("PATH")
+                .global(true)
+                .help("Prometheus textfile to update after each run, for node_exporter's textfile collector"))
         .arg(
             Arg::new("webhook")
                 .long("webhook")
+                .global(true)
                 .value_name("URL")
                 .help("Webhook URL to post installation summary (optional)"))
+        .arg(
+            Arg::new("webhook-header")
+                .long("webhook-header")
+                .global(true)
+                .value_name("HEADER")
+                .action(clap::ArgAction::Append)
+                .help("Extra webhook request header as 'Name: value' (repeat flag)"))
+        .arg(
+            Arg::new("webhook-retries")
+                .long("webhook-retries")
+                .global(true)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32))
+                .help("Retries on webhook network errors and 5xx responses (default: 3)"))
+        .arg(
+            Arg::new("webhook-format")
+                .long("webhook-format")
+                .global(true)
+                .value_name("FORMAT")
+                .value_parser(["generic", "slack", "discord", "teams"])
+                .help("Webhook payload shape: generic JSON or a Slack, Discord or Teams message (default: generic)"))
+        .arg(
+            Arg::new("webhook-template")
+                .long("webhook-template")
+                .global(true)
+                .value_name("FILE")
+                .help("Webhook payload template with {{placeholder}}s; overrides --webhook-format"))
+        .arg(
+            Arg::new("webhook-events")
+                .long("webhook-events")
+                .global(true)
+                .value_name("EVENTS")
+                .action(clap::ArgAction::Append)
+                .help("Events to post to --webhook, e.g. run.*,package.failed (default: run.finished,drift.detected)"))
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .global(true)
+                .value_name("NAME")
+                .help("Use a named profile from the config file"))
+        .arg(
+            Arg::new("category")
+                .long("category")
+                .global(true)
+                .value_name("CATEGORY")
+                .action(clap::ArgAction::Append)
+                .help("Only use recipe packages in these categories (comma-separated or repeat flag)"))
+        .arg(
+            Arg::new("tag")
+                .long("tag")
+                .global(true)
+                .value_name("TAG")
+                .action(clap::ArgAction::Append)
+                .help("Machine tags for recipe `when` conditions (comma-separated or repeat flag)"))
+        .arg(
+            Arg::new("check-exists")
+                .long("check-exists")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .help("Check every recipe package against Homebrew's formula and cask lists first"))
         .arg(
             Arg::new("dry-run")
                 .long("dry-run")
+                .global(true)
                 .action(clap::ArgAction::SetTrue)
                 .help("Preview changes without installing or removing packages"))
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_name("PATH")
+                .global(true)
+                .help("Write a per-package report of the run: JUnit XML, or HTML for .html paths"))
+        .arg(
+            Arg::new("report-format")
+                .long("report-format")
+                .value_name("FORMAT")
+                .global(true)
+                .value_parser(["junit", "html"])
+                .help("Report format (default: from the --report file extension)"))
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .action(clap::ArgAction::Count)
+                .help("Log more detail: -v info, -vv debug (commands and timings), -vvv trace"))
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .global(true)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("verbose")
+                .help("Only log errors"))
+        .arg(
+            Arg::new("log-file")
+                .long("log-file")
+                .value_name("PATH")
+                .global(true)
+                .help("Also append log records, at debug level or above, to this file"))
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .global(true)
+                .value_parser(["text", "json"])
+                .help("Log record format: text or one JSON object per line (default: text)"))
+        .subcommand(
+            Command::new("upgrade")
+                .about("Upgrade outdated recipe packages (requires --url)"))
+        .subcommand(
+            Command::new("watch")
+                .about("Re-check recipes on a schedule and report or apply drift (requires --url)")
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .value_name("DURATION")
+                        .default_value("30m")
+                        .help("Time between checks, e.g. 45s, 30m, 2h"))
+                .arg(
+                    Arg::new("policy")
+                        .long("policy")
+                        .value_name("POLICY")
+                        .value_parser(["report", "apply"])
+                        .default_value("report")
+                        .help("On drift: report it, or apply it by installing missing packages")))
+        .subcommand(
+            Command::new("validate")
+                .about("Check recipe files against the recipe schema, reporting every problem")
+                .arg(
+                    Arg::new("files")
+                        .value_name("FILE")
+                        .num_args(0..)
+                        .help("Recipe files or URLs to check (default: the configured --url sources)")))
+        .subcommand(
+            Command::new("lint")
+                .about("Check the merged recipe for deprecated, disabled, conflicting and redundant packages")
+                .arg(
+                    Arg::new("allowed-categories")
+                        .long("allowed-categories")
+                        .value_name("LIST")
+                        .action(clap::ArgAction::Append)
+                        .help("Categories recipe entries may use (comma-separated or repeat flag)"))
+                .arg(
+                    Arg::new("strict")
+                        .long("strict")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Exit with an error on warnings too")))
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two recipe chains package by package")
+                .arg(
+                    Arg::new("before")
+                        .value_name("SOURCE_A")
+                        .required(true)
+                        .help("Recipe file(s) to compare from (comma-separated chain)"))
+                .arg(
+                    Arg::new("after")
+                        .value_name("SOURCE_B")
+                        .required(true)
+                        .help("Recipe file(s) to compare to (comma-separated chain)"))
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .value_parser(["text", "json"])
+                        .default_value("text")
+                        .help("Output format")))
+        .subcommand(
+            Command::new("export")
+                .about("Write a recipe from the packages installed on request")
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_name("FILE")
+                        .help("Recipe file to write (default: standard output)"))
+                .arg(
+                    Arg::new("with-versions")
+                        .long("with-versions")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Record installed versions as recipe version pins"))
+                .arg(
+                    Arg::new("category-map")
+                        .long("category-map")
+                        .value_name("FILE")
+                        .help("JSON object mapping package names to categories")))
+        .subcommand(
+            Command::new("config")
+                .about("Inspect brim configuration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show")
+                        .about("Print the effective settings and where each one comes from")))
+        .subcommand(
+            Command::new("history")
+                .about("List past install, remove and sync runs"))
+        .subcommand(
+            Command::new("webhook")
+                .about("Manage webhook deliveries")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("flush")
+                        .about("Retry webhooks queued after failed deliveries (--dry-run lists them)")))
+        .subcommand(
+            Command::new("undo")
+                .about("Remove the packages a past run newly installed")
+                .arg(
+                    Arg::new("run")
+                        .value_name("RUN")
+                        .required(true)
+                        .help("Run id (or unique prefix) from `brim history`, or `last`")))
         .get_matches();
 
-    let installed_packages = list_installed_packages();
+    let settings = match resolve_settings(&matches) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("\n{} {}", style("✗").red().bold(), err);
+            std::process::exit(1);
+        }
+    };
 
-    if let Some(urls) = matches.get_many::<String>("url") {
-        let mut url_list: Vec<String> = Vec::new();
-        for url_arg in urls {
-            for url in url_arg.split(',') {
-                let trimmed = url.trim();
-                if !trimmed.is_empty() {
-                    url_list.push(trimmed.to_string());
-                }
+    let level = logging::level_for(matches.get_count("verbose"), matches.get_flag("quiet"));
+    if let Err(err) = logging::init(level, settings.log_format, settings.log_file.as_deref()) {
+        eprintln!("\n{} {}", style("✗").red().bold(), err);
+        std::process::exit(1);
+    }
+    log::debug!(
+        target: "config",
+        path:% = config::config_path().display(),
+        profile = settings.profile.as_deref().unwrap_or("");
+        "Resolved settings for run {}",
+        run_id
+    );
+
+    let outputs = RunOutputs::new(&matches, &settings);
+
+    match matches.subcommand() {
+        Some(("config", _)) => {
+            print_header("Configuration", Color::Cyan);
+            print_settings(&settings);
+            return;
+        }
+        Some(("history", _)) => {
+            print_header("Run History", Color::Cyan);
+            print_history(&load_runs());
+            return;
+        }
+        Some(("webhook", _)) => {
+            print_header("Webhook Outbox", Color::Cyan);
+            std::process::exit(flush_webhook_outbox(&settings, matches.get_flag("dry-run")).await);
+        }
+        Some(("validate", sub_matches)) => {
+            let sources: Vec<String> = match sub_matches.get_many::<String>("files") {
+                Some(files) => files.cloned().collect(),
+                None => settings.urls.clone(),
+            };
+            std::process::exit(validate_recipes(&sources, matches.get_flag("check-exists")).await);
+        }
+        Some(("diff", sub_matches)) => {
+            let source = |id: &str| sub_matches.get_one::<String>(id).cloned().unwrap_or_default();
+            let json = sub_matches.get_one::<String>("format").is_some_and(|f| f == "json");
+            std::process::exit(diff_recipe_chains(&source("before"), &source("after"), json).await);
+        }
+        Some(("export", sub_matches)) => {
+            std::process::exit(export_recipe(
+                sub_matches.get_one::<String>("output").map(String::as_str),
+                sub_matches.get_flag("with-versions"),
+                sub_matches.get_one::<String>("category-map").map(String::as_str),
+            ));
+        }
+        Some(("lint", sub_matches)) => {
+            std::process::exit(lint_recipes(&settings, sub_matches.get_flag("strict")).await);
+        }
+        Some(("upgrade", _)) => {
+            retry_outbox(&settings).await;
+            let code = upgrade_recipe_packages(
+                &settings,
+                matches.get_flag("dry-run"),
+                &outputs,
+                &run_id,
+                &started_at,
+                start_time,
+            )
+            .await;
+            std::process::exit(code);
+        }
+        Some(("watch", sub_matches)) => {
+            if settings.urls.is_empty() {
+                eprintln!("\n{} {}", style("✗").red().bold(), style("Watch requires --url flag").red().bold());
+                eprintln!("  Example: brim watch --url=\"packages.json\" --interval 30m");
+                std::process::exit(1);
             }
+
+            let interval = sub_matches.get_one::<String>("interval").map(String::as_str).unwrap_or("30m");
+            let interval = match watch::parse_interval(interval) {
+                Ok(interval) => interval,
+                Err(err) => {
+                    eprintln!("\n{} {}", style("✗").red().bold(), err);
+                    std::process::exit(1);
+                }
+            };
+
+            print_header("Watch Recipes", Color::Cyan);
+            watch::watch_recipes(watch::WatchOptions {
+                urls: settings.urls.clone(),
+                categories: settings.categories.clone(),
+                tags: settings.tags.clone(),
+                interval,
+                policy: sub_matches
+                    .get_one::<String>("policy")
+                    .and_then(|p| watch::WatchPolicy::parse(p))
+                    .unwrap_or(watch::WatchPolicy::Report),
+                webhooks: settings.webhook_endpoints(),
+                outbox_ttl: settings.webhook_outbox_ttl,
+                metrics: settings.metrics_file.as_deref().map(Metrics::new),
+            })
+            .await;
+            return;
         }
-        
-        let url_refs: Vec<&String> = url_list.iter().collect();
-        
-        match fetch_and_merge_packages(&url_refs).await {
-            Ok(packages) => {
+        Some(("undo", sub_matches)) => {
+            let query = sub_matches.get_one::<String>("run").map(String::as_str).unwrap_or("last");
+            retry_outbox(&settings).await;
+            let code = undo_run(query, matches.get_flag("dry-run"), &outputs, &run_id, &started_at, start_time);
+            std::process::exit(code);
+        }
+        _ => {}
+    }
+
+    retry_outbox(&settings).await;
+    let installed_packages = list_installed_packages();
+
+    let url_list = settings.urls.clone();
+    let install_requested = !matches.get_flag("list") && !matches.get_flag("remove") && !matches.get_flag("sync");
+
+    if install_requested && !url_list.is_empty() {
+        let events = start_events(&settings, &run_id, "install", &started_at, &url_list);
+        match load_recipes(&settings).await {
+            Ok((packages, skipped)) => {
+                if matches.get_flag("check-exists") && !check_packages_exist(&packages) {
+                    std::process::exit(1);
+                }
+
                 print_header("Brew Remote Install Manager", Color::Cyan);
                 
                 println!("\n{}", style("Legend:").yellow().bold());
@@ -181,46 +551,67 @@ async fn main() {
                 }
 
                 if !selected_packages.is_empty() {
-                    let parallel = matches.get_flag("parallel");
                     let dry_run = matches.get_flag("dry-run");
-                    let webhook_url = matches.get_one::<String>("webhook").cloned();
                     
                     if dry_run {
-                        print_dry_run_preview(&selected_packages, "install");
+                        print_dry_run_preview(&selected_packages, &skipped, "install", settings.hook_policy);
+                        end_run(events, "dry_run", planned(&selected_packages), None, start_time);
                         return;
                     }
                     
-                    let results = install_packages(&selected_packages, parallel);
-                    
-                    if results.is_empty() && !selected_packages.is_empty() {
+                    let selected_names: Vec<String> = selected_packages.iter().map(|p| p.name.clone()).collect();
+                    events.emit(WebhookPayload::run_started(events.run(), &selected_names));
+                    let log_dir = create_run_log_dir(&run_id).ok();
+                    apply_hook_policy(&mut selected_packages, settings.hook_policy);
+
+                    let options = InstallOptions {
+                        operation: InstallOperation::Install,
+                        parallel: settings.parallel,
+                        jobs: settings.jobs,
+                        log_dir: log_dir.clone(),
+                        atomic: settings.atomic,
+                        preinstalled: installed_packages.iter().map(|p| p.name.clone()).collect(),
+                        events: Some(events.sink()),
+                    };
+                    let outcome = install_packages(&selected_packages, &options);
+                    let results = outcome.results;
+                    print_log_location(log_dir.as_deref());
+
+                    let mut record = RunRecord::new(&run_id, "install", &started_at);
+                    record.sources = url_list.clone();
+                    record.selected = selected_packages.iter().map(|p| p.name.clone()).collect();
+                    record.newly_installed = newly_installed(&results, &installed_packages);
+                    record.packages = results.clone();
+                    // A cancelled run is still recorded, so `brim undo` sees what it installed.
+                    if !results.is_empty() {
+                        save_history(&record);
+                    }
+
+                    if outcome.cancelled {
                         eprintln!("\n{} Operation cancelled by user", style("✗").yellow().bold());
+                        end_run(events, "cancelled", results, None, start_time);
                         std::process::exit(130);
                     }
-                    
-                    if let Some(url) = webhook_url {
-                        let completed = results.iter().filter(|r| r.status == "completed").count();
-                        let failed = results.iter().filter(|r| r.status == "failed").count();
-                        
-                        let payload = WebhookPayload {
-                            status: if failed > 0 { "partial".to_string() } else { "success".to_string() },
-                            total: results.len(),
-                            completed,
-                            failed,
-                            packages: results,
-                            elapsed_seconds: start_time.elapsed().as_secs(),
-                        };
-                        
-                        match post_webhook(&url, payload).await {
-                            Ok(_) => eprintln!("Webhook notification sent successfully"),
-                            Err(e) => eprintln!("Warning: Failed to send webhook: {}", e),
-                        }
-                    }
+
+                    exit_code = exit_code_for(&results);
+                    let rolled_back = outcome.rolled_back > 0;
+                    let payload = WebhookPayload::from_results(
+                        RunContext::from_record(&record),
+                        results,
+                        start_time.elapsed().as_secs(),
+                        rolled_back,
+                    );
+                    outputs.write(&payload);
+                    events.emit(payload);
+                    finish_events(events);
                 }
             }
             Err(err) => {
                 eprintln!("\n{} {}", style("✗").red().bold(), style("Error fetching packages").red().bold());
                 eprintln!("  {}", err);
                 eprintln!("\n{} Make sure your URL or file path is correct.", style("→").yellow());
+                outputs.fetch_failed(&url_list, &err);
+                fetch_failed(events, &err, start_time);
             }
         }
     }
@@ -272,65 +663,81 @@ async fn main() {
         }
 
         if !selected_packages.is_empty() {
-            let parallel = matches.get_flag("parallel");
+            let parallel = settings.parallel;
             let dry_run = matches.get_flag("dry-run");
-            let webhook_url = matches.get_one::<String>("webhook").cloned();
+            let events = start_events(&settings, &run_id, "remove", &started_at, &[]);
             
             if dry_run {
-                print_dry_run_preview(&selected_packages, "remove");
+                print_dry_run_preview(&selected_packages, &[], "remove", HookPolicy::Skip);
+                end_run(events, "dry_run", planned(&selected_packages), None, start_time);
                 return;
             }
             
-            let results = remove_packages(&selected_packages, parallel);
+            let selected_names: Vec<String> = selected_packages.iter().map(|p| p.name.clone()).collect();
+            events.emit(WebhookPayload::run_started(events.run(), &selected_names));
+            let log_dir = create_run_log_dir(&run_id).ok();
+            let results = remove_packages(&selected_packages, parallel, log_dir.as_deref(), Some(&events.sink()));
+            print_log_location(log_dir.as_deref());
             
             if results.is_empty() && !selected_packages.is_empty() {
                 eprintln!("\n{} Operation cancelled by user", style("✗").yellow().bold());
+                end_run(events, "cancelled", vec![], None, start_time);
                 std::process::exit(130);
             }
+
+            exit_code = exit_code_for(&results);
+
+            let mut record = RunRecord::new(&run_id, "remove", &started_at);
+            record.selected = selected_packages.iter().map(|p| p.name.clone()).collect();
+            record.packages = results.clone();
+            save_history(&record);
             
-            if let Some(url) = webhook_url {
-                let completed = results.iter().filter(|r| r.status == "completed").count();
-                let failed = results.iter().filter(|r| r.status == "failed").count();
-                
-                let payload = WebhookPayload {
-                    status: if failed > 0 { "partial".to_string() } else { "success".to_string() },
-                    total: results.len(),
-                    completed,
-                    failed,
-                    packages: results,
-                    elapsed_seconds: start_time.elapsed().as_secs(),
-                };
-                
-                match post_webhook(&url, payload).await {
-                    Ok(_) => eprintln!("Webhook notification sent successfully"),
-                    Err(e) => eprintln!("Warning: Failed to send webhook: {}", e),
-                }
-            }
+            let payload = WebhookPayload::from_results(
+                RunContext::from_record(&record),
+                results,
+                start_time.elapsed().as_secs(),
+                false,
+            );
+            outputs.write(&payload);
+            events.emit(payload);
+            finish_events(events);
         }
     }
 
     if matches.get_flag("sync") {
-        if let Some(urls) = matches.get_many::<String>("url") {
-            let mut url_list: Vec<String> = Vec::new();
-            for url_arg in urls {
-                for url in url_arg.split(',') {
-                    let trimmed = url.trim();
-                    if !trimmed.is_empty() {
-                        url_list.push(trimmed.to_string());
-                    }
-                }
-            }
-            
-            let url_refs: Vec<&String> = url_list.iter().collect();
-            
-            match fetch_and_merge_packages(&url_refs).await {
-                Ok(recipe_packages) => {
+        if !url_list.is_empty() {
+            let events = start_events(&settings, &run_id, "sync", &started_at, &url_list);
+            match load_recipes(&settings).await {
+                Ok((recipe_packages, skipped)) => {
                     let dry_run = matches.get_flag("dry-run");
-                    sync_packages(&installed_packages, &recipe_packages, dry_run);
+                    // A skipped entry is still in the recipe, so its installed package is not extra.
+                    let installed: Vec<BrewPackage> = installed_packages
+                        .iter()
+                        .filter(|p| !skipped.iter().any(|s| s.package.name == p.name))
+                        .cloned()
+                        .collect();
+                    let diff = sync_packages(&installed, &recipe_packages, &skipped, dry_run);
+
+                    let mut record = RunRecord::new(&run_id, "sync", &started_at);
+                    record.sources = url_list.clone();
+                    record.packages = diff.as_results();
+                    save_history(&record);
+
+                    let run = RunContext::from_record(&record);
+                    let elapsed = start_time.elapsed().as_secs();
+                    if !diff.to_install.is_empty() || !diff.to_remove.is_empty() {
+                        events.emit(WebhookPayload::drift(run.clone(), record.packages.clone(), elapsed));
+                    }
+                    let payload = WebhookPayload::from_results(run, record.packages.clone(), elapsed, false);
+                    outputs.write(&payload);
+                    events.emit(payload);
+                    finish_events(events);
                 }
                 Err(err) => {
                     eprintln!("\n{} {}", style("✗").red().bold(), style("Error fetching packages").red().bold());
                     eprintln!("  {}", err);
+                    outputs.fetch_failed(&url_list, &err);
+                    fetch_failed(events, &err, start_time);
                 }
             }
         } else {
@@ -340,32 +747,32 @@ async fn main() {
     }
 
     eprintln!("Elapsed time: {:?} seconds", start_time.elapsed().as_secs());
+
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
 }
 
-fn sync_packages(installed: &[BrewPackage], recipe: &[BrewPackage], dry_run: bool) {
+fn sync_packages<'a>(
+    installed: &'a [BrewPackage],
+    recipe: &'a [BrewPackage],
+    skipped: &[SkippedPackage],
+    dry_run: bool,
+) -> SyncDiff<'a> {
     println!("\n{}", style("╔═══════════════════════════════════════════════════════════════════╗").cyan().bold());
     println!("{}", style("║         BRIM - Sync Analysis                                      ║").cyan().bold());
     println!("{}", style("╚═══════════════════════════════════════════════════════════════════╝").cyan().bold());
     
-    let to_install: Vec<&BrewPackage> = recipe
-        .iter()
-        .filter(|pkg| !installed.iter().any(|inst| inst.name == pkg.name))
-        .collect();
-    
-    let to_remove: Vec<&BrewPackage> = installed
-        .iter()
-        .filter(|inst| !recipe.iter().any(|pkg| pkg.name == inst.name))
-        .collect();
-    
-    let in_sync: Vec<&BrewPackage> = recipe
-        .iter()
-        .filter(|pkg| installed.iter().any(|inst| inst.name == pkg.name))
-        .collect();
+    let diff = compute_sync_diff(installed, recipe);
+    let SyncDiff { ref to_install, ref to_remove, ref in_sync } = diff;
     
     println!("\n{}", style("═══ Summary ═══").yellow().bold());
     println!("  {} In sync: {}", style("✓").green(), style(in_sync.len()).cyan().bold());
     println!("  {} To install: {}", style("+").green(), style(to_install.len()).cyan().bold());
     println!("  {} Extra (not in recipe): {}", style("-").red(), style(to_remove.len()).cyan().bold());
+    if !skipped.is_empty() {
+        println!("  {} Skipped on this machine: {}", style("○").dim(), style(skipped.len()).cyan().bold());
+    }
     
     if !to_install.is_empty() {
         println!("\n{}", style("═══ Packages to Install ═══").green().bold());
@@ -398,6 +805,18 @@ fn sync_packages(installed: &[BrewPackage], recipe: &[BrewPackage], dry_run: boo
         }
     }
     
+    if !skipped.is_empty() {
+        println!("\n{}", style("═══ Skipped on This Machine ═══").dim().bold());
+        for (i, entry) in skipped.iter().enumerate() {
+            println!("  {} {} {} {}",
+                style(format!("{:2}.", i + 1)).dim(),
+                style("○").dim(),
+                style(&entry.package.name).dim(),
+                style(format!("- {}", entry.reason)).dim()
+            );
+        }
+    }
+    
     if to_install.is_empty() && to_remove.is_empty() {
         println!("\n{} All packages are in sync!", style("✓").green().bold());
         println!("  {} packages match your recipe file.", in_sync.len());
@@ -416,66 +835,800 @@ fn sync_packages(installed: &[BrewPackage], recipe: &[BrewPackage], dry_run: boo
         }
     }
     println!();
+
+    diff
 }
 
-async fn fetch_and_merge_packages(urls: &Vec<&String>) -> Result<Vec<BrewPackage>, String> {
-    if urls.is_empty() {
-        return Err("No URLs provided".to_string());
+/// Compares two recipe chains and prints the result as text or JSON.
+///
+/// Exits like `diff`: 0 when the recipes match, 1 when they differ, 2 on errors.
+async fn diff_recipe_chains(before: &str, after: &str, json: bool) -> i32 {
+    let chain = |source: &str| -> Vec<String> {
+        source.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+    };
+
+    let mut recipes: Vec<Vec<BrewPackage>> = vec![];
+    for source in [before, after] {
+        let urls = chain(source);
+        let url_refs: Vec<&String> = urls.iter().collect();
+        match fetch_and_merge(&url_refs, false).await {
+            Ok(packages) => recipes.push(packages),
+            Err(err) => {
+                eprintln!("\n{} {}", style("✗").red().bold(), style("Error fetching packages").red().bold());
+                eprintln!("  {}", err);
+                return 2;
+            }
+        }
     }
-    
-    println!("\n{} Fetching recipe files...", style("→").cyan().bold());
-    
-    let mut all_packages: HashMap<String, BrewPackage> = HashMap::new();
-    let mut fetch_count = 0;
-    
-    for (index, url) in urls.iter().enumerate() {
-        println!("  {} {}", 
-            style(format!("{}/{}:", index + 1, urls.len())).dim(),
-            style(url).cyan()
+
+    let diff = diff_recipes(&recipes[0], &recipes[1]);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff).expect("recipe diff serializes"));
+    } else {
+        print_recipe_diff(&diff, before, after);
+    }
+
+    if diff.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+/// Fetches and merges the configured recipes, keeping only packages that
+/// apply to this platform and are in the configured categories. Entries
+/// skipped for the platform are returned separately.
+async fn load_recipes(settings: &Settings) -> Result<(Vec<BrewPackage>, Vec<SkippedPackage>), String> {
+    let url_refs: Vec<&String> = settings.urls.iter().collect();
+    let merged = fetch_and_merge(&url_refs, true).await?;
+
+    let platform = Platform::current().with_tags(&settings.tags);
+    let (packages, skipped) = platform.partition(merged);
+    if !skipped.is_empty() {
+        println!(
+            "{} {} packages skipped on {} (see --sync or --dry-run)",
+            style("ℹ").cyan().bold(),
+            skipped.len(),
+            platform.label()
         );
-        
-        match fetch_packages(url).await {
-            Ok(packages) => {
-                fetch_count += 1;
-                for package in packages {
-                    all_packages.insert(package.name.clone(), package);
+    }
+
+    if settings.categories.is_empty() {
+        return Ok((packages, skipped));
+    }
+
+    let filtered: Vec<BrewPackage> = packages
+        .into_iter()
+        .filter(|p| p.in_categories(&settings.categories))
+        .collect();
+    println!(
+        "{} {} packages in categories: {}",
+        style("✓").green().bold(),
+        filtered.len(),
+        settings.categories.join(", ")
+    );
+    Ok((filtered, skipped))
+}
+
+/// Validates each recipe source and prints every problem as `source:line:column: message`.
+///
+/// With `check_exists`, schema-valid recipes are also resolved against Homebrew.
+async fn validate_recipes(sources: &[String], check_exists: bool) -> i32 {
+    if sources.is_empty() {
+        eprintln!("\n{} {}", style("✗").red().bold(), style("No recipe files to validate").red().bold());
+        eprintln!("  Example: brim validate packages.json extras.json");
+        return 2;
+    }
+
+    let index = if check_exists {
+        match HomebrewIndex::load() {
+            Ok(index) => Some(index),
+            Err(err) => {
+                eprintln!("\n{} Could not load Homebrew metadata: {}", style("✗").red().bold(), err);
+                return 1;
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut invalid = 0;
+    for source in sources {
+        let text = match fetch_recipe_text(source).await {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("{}: {}", source, err);
+                invalid += 1;
+                continue;
+            }
+        };
+
+        let mut errors = validate_recipe(source, &text);
+        if let (true, Some(index)) = (errors.is_empty(), &index) {
+            let packages: Vec<BrewPackage> = serde_json::from_str(&text).unwrap_or_default();
+            for issue in index.check(&packages, renamed_formula) {
+                let pointer = format!("/{}/name", issue.index);
+                if issue.is_error() {
+                    errors.push(error_at(source, &text, &pointer, issue.message()));
+                } else {
+                    println!("{}", error_at(source, &text, &pointer, format!("note: {}", issue.message())));
                 }
-                println!("    {} Loaded {} packages", 
-                    style("✓").green(),
-                    all_packages.len()
-                );
             }
+        }
+
+        if errors.is_empty() {
+            println!("{} {}", style("✓").green().bold(), source);
+        } else {
+            invalid += 1;
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+        }
+    }
+
+    if invalid > 0 {
+        eprintln!(
+            "\n{} {} of {} recipe file(s) invalid",
+            style("✗").red().bold(),
+            invalid,
+            sources.len()
+        );
+        1
+    } else {
+        0
+    }
+}
+
+/// Writes the packages installed on request as a recipe to `output`, or stdout.
+///
+/// Progress goes to stderr so the recipe can be piped.
+fn export_recipe(output: Option<&str>, with_versions: bool, category_map: Option<&str>) -> i32 {
+    let categories = match category_map {
+        Some(path) => match std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|t| parse_category_map(&t)) {
+            Ok(categories) => categories,
             Err(err) => {
-                eprintln!("    {} Error: {}", style("✗").red(), err);
-                return Err(format!("Failed to fetch from {}: {}", url, err));
+                eprintln!("\n{} Invalid category map {}: {}", style("✗").red().bold(), path, err);
+                return 1;
             }
+        },
+        None => HashMap::new(),
+    };
+
+    let installed = match list_requested_packages() {
+        Ok(installed) => installed,
+        Err(err) => {
+            eprintln!("\n{} {}", style("✗").red().bold(), style("Error listing installed packages").red().bold());
+            eprintln!("  {}", err);
+            return 1;
         }
+    };
+
+    if installed.is_empty() {
+        eprintln!("\n{} No packages installed on request; nothing to export", style("✗").red().bold());
+        return 1;
     }
-    
-    if fetch_count == 0 {
-        return Err("Failed to fetch any recipe files".to_string());
+
+    let recipe = build_recipe(&installed, &ExportOptions { with_versions, categories });
+    let mut text = serde_json::to_string_pretty(&recipe).expect("recipe entries serialize");
+    text.push('\n');
+
+    // The recipe should load with `brim --url` as-is.
+    let problems = validate_recipe(output.unwrap_or("<stdout>"), &text);
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        eprintln!("\n{} Exported recipe would be invalid; nothing written", style("✗").red().bold());
+        return 1;
     }
-    
-    let merged: Vec<BrewPackage> = all_packages.into_values().collect();
-    
-    println!("\n{} Merged {} unique packages from {} recipe file(s)", 
+
+    match output {
+        Some(path) => {
+            if let Err(err) = std::fs::write(path, &text) {
+                eprintln!("\n{} Could not write {}: {}", style("✗").red().bold(), path, err);
+                return 1;
+            }
+        }
+        None => print!("{}", text),
+    }
+
+    let casks = recipe.iter().filter(|e| e.cask.is_some()).count();
+    let pinned = recipe.iter().filter(|e| e.version.is_some()).count();
+    eprintln!(
+        "{} Exported {} formulae and {} casks{}{}",
         style("✓").green().bold(),
-        style(merged.len()).cyan().bold(),
-        style(fetch_count).cyan().bold()
+        recipe.len() - casks,
+        casks,
+        if with_versions { format!(" ({} with versions)", pinned) } else { String::new() },
+        output.map(|p| format!(" to {}", p)).unwrap_or_default()
     );
-    
-    Ok(merged)
+    0
+}
+
+/// Lints the configured recipe chain and prints the findings.
+///
+/// Returns 1 if there are errors, or warnings with `strict`.
+async fn lint_recipes(settings: &Settings, strict: bool) -> i32 {
+    if settings.urls.is_empty() {
+        eprintln!("\n{} {}", style("✗").red().bold(), style("Lint requires --url flag").red().bold());
+        eprintln!("  Example: brim lint --url=\"base.json,team.json\"");
+        return 1;
+    }
+
+    print_header("Recipe Lint", Color::Cyan);
+
+    let mut sources: Vec<(String, Vec<BrewPackage>)> = vec![];
+    for url in &settings.urls {
+        match fetch_packages(url).await {
+            Ok(packages) => sources.push((url.clone(), packages)),
+            Err(err) => {
+                eprintln!("\n{} {}: {}", style("✗").red().bold(), url, err);
+                return 1;
+            }
+        }
+    }
+
+    let index = match HomebrewIndex::load() {
+        Ok(index) => index,
+        Err(err) => {
+            eprintln!("\n{} Could not load Homebrew metadata: {}", style("✗").red().bold(), err);
+            return 1;
+        }
+    };
+
+    let merged = merge_sources(&sources);
+    let merged_packages: Vec<BrewPackage> = merged.iter().map(|p| (*p).clone()).collect();
+    let mut findings: Vec<LintFinding> = index
+        .check(&merged_packages, renamed_formula)
+        .into_iter()
+        .map(|issue| LintFinding {
+            severity: if issue.is_error() { LintSeverity::Error } else { LintSeverity::Warning },
+            package: issue.name.clone(),
+            message: issue.message(),
+        })
+        .collect();
+
+    // `brew info` fails on unknown names, so only ask about the ones that resolved.
+    let formulae: Vec<&str> = merged
+        .iter()
+        .filter(|p| p.cask.is_none() && index.formulae.contains(&p.name))
+        .map(|p| p.name.as_str())
+        .collect();
+    let casks: Vec<&str> = merged
+        .iter()
+        .filter(|p| p.cask.is_some() && index.casks.contains(&p.name))
+        .map(|p| p.name.as_str())
+        .collect();
+    let metadata = match fetch_metadata(&formulae, &casks) {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            eprintln!("\n{} Could not load package metadata: {}", style("✗").red().bold(), err);
+            return 1;
+        }
+    };
+
+    findings.extend(lint_recipe(&sources, &metadata, &settings.allowed_categories));
+    findings.sort_by(|a, b| (a.severity, &a.package).cmp(&(b.severity, &b.package)));
+
+    for finding in &findings {
+        let label = match finding.severity {
+            LintSeverity::Error => style(finding.severity.label()).red().bold(),
+            LintSeverity::Warning => style(finding.severity.label()).yellow().bold(),
+        };
+        println!("  {:<7} {} {}", label, style(&finding.package).cyan(), finding.message);
+    }
+
+    let errors = findings.iter().filter(|f| f.severity == LintSeverity::Error).count();
+    let warnings = findings.len() - errors;
+    println!(
+        "\n{} {} package(s) checked: {} error(s), {} warning(s)",
+        if findings.is_empty() { style("✓").green().bold() } else { style("ℹ").cyan().bold() },
+        merged.len(),
+        errors,
+        warnings
+    );
+
+    if errors > 0 || (strict && warnings > 0) {
+        1
+    } else {
+        0
+    }
+}
+
+/// Resolves every package against Homebrew's metadata and prints the problems.
+/// Returns false if any package would fail to install.
+fn check_packages_exist(packages: &[BrewPackage]) -> bool {
+    println!("\n{} Checking packages against Homebrew...", style("→").cyan().bold());
+
+    let index = match HomebrewIndex::load() {
+        Ok(index) => index,
+        Err(err) => {
+            eprintln!("  {} Could not load Homebrew metadata: {}", style("✗").red(), err);
+            return false;
+        }
+    };
+
+    let issues = index.check(packages, renamed_formula);
+    for issue in &issues {
+        if issue.is_error() {
+            eprintln!("  {} {}", style("✗").red(), issue.message());
+        } else {
+            println!("  {} {}", style("ℹ").cyan(), issue.message());
+        }
+    }
+
+    let errors = issues.iter().filter(|i| i.is_error()).count();
+    if errors > 0 {
+        eprintln!("\n{} {} package(s) would fail to install", style("✗").red().bold(), errors);
+        return false;
+    }
+
+    println!("  {} All {} packages found", style("✓").green(), packages.len());
+    true
+}
+
+/// Starts delivering this run's lifecycle events to the configured webhook endpoints.
+fn start_events(settings: &Settings, run_id: &str, operation: &str, started_at: &str, sources: &[String]) -> EventDispatcher {
+    EventDispatcher::start(settings.webhook_endpoints(), RunContext::new(run_id, operation, started_at, sources))
+}
+
+/// Waits for queued webhook events to go out, then reports any that failed.
+fn finish_events(events: EventDispatcher) {
+    let report = events.finish();
+    for error in &report.errors {
+        log::warn!(target: "webhook", "Failed to send webhook: {}", error);
+    }
+    if report.sent > 0 {
+        eprintln!("Webhook notification sent successfully");
+    }
+}
+
+fn outbox_options(settings: &Settings, retries: Option<u32>, stop_on_failure: bool) -> FlushOptions {
+    FlushOptions {
+        ttl: settings.webhook_outbox_ttl,
+        retries,
+        stop_on_failure,
+    }
+}
+
+/// Sends webhooks earlier runs could not deliver, before this run adds its own.
+/// One attempt each, stopping at the first failure so an offline machine isn't held up.
+async fn retry_outbox(settings: &Settings) {
+    let options = outbox_options(settings, Some(0), true);
+    let report = flush_outbox(&outbox_dir(), &settings.webhook_endpoints(), &options).await;
+    if report.is_empty() {
+        return;
+    }
+
+    print_flush_report(&report);
+    if !report.remaining.is_empty() {
+        eprintln!(
+            "{} {} webhook(s) still queued; run `brim webhook flush` to retry",
+            style("ℹ").cyan().bold(),
+            report.remaining.len()
+        );
+    }
+}
+
+fn print_flush_report(report: &FlushReport) {
+    if report.delivered > 0 {
+        eprintln!("{} Delivered {} queued webhook(s)", style("✓").green().bold(), report.delivered);
+    }
+    if report.expired > 0 {
+        eprintln!(
+            "{} Dropped {} queued webhook(s) older than webhook_outbox_ttl",
+            style("⚠").yellow().bold(),
+            report.expired
+        );
+    }
+    for entry in &report.rejected {
+        eprintln!(
+            "{} Dropped {} for run {}: {}",
+            style("✗").red().bold(),
+            entry.event,
+            entry.run_id,
+            entry.last_error
+        );
+    }
+}
+
+/// `brim webhook flush`: retries every queued webhook with the configured retries.
+/// Exits non-zero while anything is left in the outbox.
+async fn flush_webhook_outbox(settings: &Settings, dry_run: bool) -> i32 {
+    let dir = outbox_dir();
+    let entries = webhook::outbox::load_entries(&dir);
+    if entries.is_empty() {
+        println!("\n{} No webhooks queued.", style("ℹ").cyan().bold());
+        println!("  The outbox is {}", style(dir.display()).dim());
+        return 0;
+    }
+
+    if dry_run {
+        println!("\n{}", style(format!("Queued: {} webhooks", entries.len())).yellow().bold());
+        println!();
+        for entry in &entries {
+            println!(
+                "  {} {} {} {}",
+                style(&entry.run_id).cyan().bold(),
+                style(&entry.queued_at).dim(),
+                style(format!("{:<17}", entry.event)).yellow(),
+                entry.url
+            );
+            println!("      {}", style(format!("{} attempt(s): {}", entry.attempts, entry.last_error)).dim());
+        }
+        println!();
+        return 0;
+    }
+
+    let options = outbox_options(settings, None, false);
+    let report = flush_outbox(&dir, &settings.webhook_endpoints(), &options).await;
+    println!();
+    print_flush_report(&report);
+    for entry in &report.remaining {
+        eprintln!(
+            "{} {} for run {} is still queued after {} attempt(s): {}",
+            style("✗").red().bold(),
+            entry.event,
+            entry.run_id,
+            entry.attempts,
+            entry.last_error
+        );
+    }
+    for entry in &report.unconfigured {
+        eprintln!(
+            "{} {} for run {} was not sent: no configured webhook has URL {}",
+            style("⚠").yellow().bold(),
+            entry.event,
+            entry.run_id,
+            entry.url
+        );
+    }
+
+    if report.remaining.is_empty() && report.rejected.is_empty() && report.unconfigured.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+/// Sends `run.finished` for a run that ended before producing per-package results.
+fn end_run(events: EventDispatcher, status: &str, packages: Vec<PackageResult>, error: Option<String>, start_time: Instant) {
+    let run = events.run().finished();
+    events.emit(WebhookPayload::ended(run, status, packages, error, start_time.elapsed().as_secs()));
+    finish_events(events);
+}
+
+fn fetch_failed(events: EventDispatcher, error: &str, start_time: Instant) {
+    events.emit(WebhookPayload::fetch_failed(events.run(), error));
+    end_run(events, "failed", vec![], Some(error.to_string()), start_time);
+}
+
+/// Packages a dry run would have worked on, with status `planned`.
+fn planned(packages: &[BrewPackage]) -> Vec<PackageResult> {
+    packages
+        .iter()
+        .map(|p| PackageResult {
+            name: p.name.clone(),
+            status: "planned".to_string(),
+            cask: p.cask.is_some(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Upgrades the outdated packages that appear in the recipes, leaving
+/// everything else Homebrew reports as outdated alone.
+async fn upgrade_recipe_packages(
+    settings: &Settings,
+    dry_run: bool,
+    outputs: &RunOutputs,
+    run_id: &str,
+    started_at: &str,
+    start_time: Instant,
+) -> i32 {
+    if settings.urls.is_empty() {
+        eprintln!("\n{} {}", style("✗").red().bold(), style("Upgrade requires --url flag").red().bold());
+        eprintln!("  Example: brim upgrade --url=\"packages.json\"");
+        return 1;
+    }
+
+    let events = start_events(settings, run_id, "upgrade", started_at, &settings.urls);
+    let recipe = match load_recipes(settings).await {
+        Ok((packages, _)) => packages,
+        Err(err) => {
+            eprintln!("\n{} {}", style("✗").red().bold(), style("Error fetching packages").red().bold());
+            eprintln!("  {}", err);
+            outputs.fetch_failed(&settings.urls, &err);
+            fetch_failed(events, &err, start_time);
+            return 1;
+        }
+    };
+
+    let outdated = match list_outdated_packages() {
+        Ok(outdated) => outdated,
+        Err(err) => {
+            eprintln!("\n{} {}", style("✗").red().bold(), style("Error checking for upgrades").red().bold());
+            eprintln!("  {}", err);
+            end_run(events, "failed", vec![], Some(err), start_time);
+            return 1;
+        }
+    };
+
+    print_header("Upgrade Recipe Packages", Color::Cyan);
+
+    let mut candidates: Vec<(BrewPackage, OutdatedPackage)> = vec![];
+    for entry in outdated {
+        let Some(package) = recipe.iter().find(|p| p.name == entry.name && p.cask.is_some() == entry.cask) else {
+            continue;
+        };
+
+        let held_by = if entry.pinned {
+            Some("pinned with brew pin".to_string())
+        } else {
+            package
+                .version
+                .as_deref()
+                .filter(|pin| !pin_allows(pin, &entry.available_version))
+                .map(|pin| format!("recipe pins {}", pin))
+        };
+
+        if let Some(reason) = held_by {
+            println!(
+                "  {} {} {} → {} ({})",
+                style("⏸").dim(),
+                style(&entry.name).dim(),
+                style(&entry.installed_version).dim(),
+                style(&entry.available_version).dim(),
+                style(reason).yellow()
+            );
+            continue;
+        }
+
+        candidates.push((package.clone(), entry));
+    }
+
+    if candidates.is_empty() {
+        println!("\n{} All recipe packages are up to date.", style("✓").green().bold());
+        return 0;
+    }
+
+    let items: Vec<_> = candidates
+        .iter()
+        .map(|(package, entry)| {
+            let formatted = format!(
+                "{} {} → {}",
+                package.name, entry.installed_version, entry.available_version
+            );
+            if entry.cask {
+                style(formatted).magenta()
+            } else {
+                style(formatted).green()
+            }
+        })
+        .collect();
+    let defaults = vec![true; items.len()];
+
+    let prompt = format!(
+        "\n{} Select packages to upgrade (Space to toggle, Enter to confirm):",
+        style("→").cyan().bold()
+    );
+    let selections = MultiSelect::new()
+        .with_prompt(prompt)
+        .items(&items)
+        .defaults(&defaults)
+        .interact()
+        .unwrap();
+
+    let mut selected_packages: Vec<BrewPackage> = selections.iter().map(|i| candidates[*i].0.clone()).collect();
+    if selected_packages.is_empty() {
+        return 0;
+    }
+
+    if dry_run {
+        print_dry_run_preview(&selected_packages, &[], "upgrade", HookPolicy::Skip);
+        end_run(events, "dry_run", planned(&selected_packages), None, start_time);
+        return 0;
+    }
+
+    // Hooks describe first-time setup, so upgrades never run them.
+    apply_hook_policy(&mut selected_packages, HookPolicy::Skip);
+
+    let selected_names: Vec<String> = selected_packages.iter().map(|p| p.name.clone()).collect();
+    events.emit(WebhookPayload::run_started(events.run(), &selected_names));
+    let log_dir = create_run_log_dir(run_id).ok();
+    let options = InstallOptions {
+        operation: InstallOperation::Upgrade,
+        parallel: true,
+        jobs: settings.jobs,
+        log_dir: log_dir.clone(),
+        atomic: false,
+        preinstalled: vec![],
+        events: Some(events.sink()),
+    };
+    let outcome = install_packages(&selected_packages, &options);
+    let results = outcome.results;
+    print_log_location(log_dir.as_deref());
+
+    let mut record = RunRecord::new(run_id, "upgrade", started_at);
+    record.sources = settings.urls.clone();
+    record.selected = selected_packages.iter().map(|p| p.name.clone()).collect();
+    record.packages = results.clone();
+    if !results.is_empty() {
+        save_history(&record);
+    }
+
+    if outcome.cancelled {
+        eprintln!("\n{} Operation cancelled by user", style("✗").yellow().bold());
+        end_run(events, "cancelled", results, None, start_time);
+        return 130;
+    }
+
+    let code = exit_code_for(&results);
+    let payload = WebhookPayload::from_results(RunContext::from_record(&record), results, start_time.elapsed().as_secs(), false);
+    outputs.write(&payload);
+    events.emit(payload);
+    finish_events(events);
+    code
 }
 
-fn print_dry_run_preview(packages: &[BrewPackage], operation: &str) {
+fn undo_run(
+    query: &str,
+    dry_run: bool,
+    outputs: &RunOutputs,
+    run_id: &str,
+    started_at: &str,
+    start_time: Instant,
+) -> i32 {
+    let run = match find_run(query) {
+        Ok(run) => run,
+        Err(err) => {
+            eprintln!("\n{} {}", style("✗").red().bold(), style(err).red().bold());
+            eprintln!("  Run {} to list recorded runs.", style("brim history").cyan());
+            return 1;
+        }
+    };
+
+    print_header(&format!("Undo {}", run.id), Color::Red);
+
+    if run.newly_installed.is_empty() {
+        println!("\n{} Run {} did not newly install any packages.", style("ℹ").cyan().bold(), run.id);
+        return 0;
+    }
+
+    let installed = list_installed_packages();
+    let (still_installed, gone): (Vec<&String>, Vec<&String>) = run
+        .newly_installed
+        .iter()
+        .partition(|name| installed.iter().any(|p| &p.name == *name));
+
+    for name in &gone {
+        println!("  {} {} is no longer installed", style("-").dim(), style(name).dim());
+    }
+
+    let packages: Vec<BrewPackage> = still_installed.iter().map(|name| run.package(name)).collect();
+    if packages.is_empty() {
+        println!("\n{} Nothing left to undo.", style("✓").green().bold());
+        return 0;
+    }
+
+    if dry_run {
+        print_dry_run_preview(&packages, &[], "remove", HookPolicy::Skip);
+        return 0;
+    }
+
+    println!("\n{}", style(format!("The following {} packages will be removed:", packages.len())).yellow().bold());
+    for package in &packages {
+        println!("  {} {}", style("✗").red(), package.name);
+    }
+
+    let confirmed = Confirm::new()
+        .with_prompt("Proceed?")
+        .default(false)
+        .interact()
+        .unwrap_or(false);
+    if !confirmed {
+        return 130;
+    }
+
+    let log_dir = create_run_log_dir(run_id).ok();
+    let results = remove_packages(&packages, false, log_dir.as_deref(), None);
+    print_log_location(log_dir.as_deref());
+
+    if results.is_empty() {
+        eprintln!("\n{} Operation cancelled by user", style("✗").yellow().bold());
+        return 130;
+    }
+
+    let mut record = RunRecord::new(run_id, "undo", started_at);
+    record.sources = vec![run.id.clone()];
+    record.selected = packages.iter().map(|p| p.name.clone()).collect();
+    record.packages = results;
+    save_history(&record);
+    outputs.write(
+        &WebhookPayload::from_results(
+            RunContext::from_record(&record),
+            record.packages.clone(),
+            start_time.elapsed().as_secs(),
+            false,
+        ),
+    );
+
+    exit_code_for(&record.packages)
+}
+
+/// Files written from a run's results besides history: the `--report` and the metrics textfile.
+/// Failing to write them is a warning, like a failed webhook.
+struct RunOutputs {
+    report: Option<Report>,
+    metrics: Option<Metrics>,
+}
+
+impl RunOutputs {
+    fn new(matches: &clap::ArgMatches, settings: &Settings) -> Self {
+        // The format comes from `--report-format` or the file extension.
+        let report = matches.get_one::<String>("report").map(|path| {
+            let path = std::path::PathBuf::from(path);
+            let format = matches
+                .get_one::<String>("report-format")
+                .and_then(|f| ReportFormat::parse(f))
+                .unwrap_or_else(|| ReportFormat::for_path(&path));
+            Report { path, format }
+        });
+
+        Self {
+            report,
+            metrics: settings.metrics_file.as_deref().map(Metrics::new),
+        }
+    }
+
+    /// Writes the report and metrics for a finished run from its `run.finished` payload.
+    /// Sync runs only update the drift metrics.
+    fn write(&self, payload: &WebhookPayload) {
+        if let Some(report) = self.report.as_ref().filter(|_| payload.run.operation != "sync") {
+            match report.write(payload) {
+                Ok(()) => {
+                    println!("{} Report written to {}", style("✓").green().bold(), style(report.path.display()).cyan())
+                }
+                Err(e) => log::warn!(target: "report", "{}", e),
+            }
+        }
+        self.update_metrics(|m| m.record_run(payload));
+    }
+
+    /// Counts a recipe fetch error against the source it came from.
+    fn fetch_failed(&self, sources: &[String], error: &str) {
+        if let Some(source) = failed_source(sources, error) {
+            self.update_metrics(|m| m.record_fetch_error(source));
+        }
+    }
+
+    fn update_metrics(&self, change: impl FnOnce(&mut MetricsState)) {
+        if let Some(ref metrics) = self.metrics {
+            match metrics.update(change) {
+                Ok(()) => log::debug!(target: "metrics", path:% = metrics.textfile.display(); "Updated metrics textfile"),
+                Err(e) => log::warn!(target: "metrics", "Failed to update metrics: {}", e),
+            }
+        }
+    }
+}
+
+fn print_log_location(log_dir: Option<&Path>) {
+    if let Some(dir) = log_dir {
+        println!("\n{} Package logs: {}", style("ℹ").cyan().bold(), style(dir.display()).dim());
+    }
+}
+
+/// `hook_policy` decides which install hooks the preview lists; a `Prompt`
+/// policy is not asked here, so remote hooks are listed as pending confirmation.
+fn print_dry_run_preview(packages: &[BrewPackage], skipped: &[SkippedPackage], operation: &str, hook_policy: HookPolicy) {
     println!("\n{}", style("╔═══════════════════════════════════════════════════════════════════╗").yellow().bold());
     println!("{}", style("║         DRY RUN - Preview Mode                                    ║").yellow().bold());
     println!("{}", style("╚═══════════════════════════════════════════════════════════════════╝").yellow().bold());
     
-    let action = if operation == "install" { 
-        "installed" 
-    } else { 
-        "removed" 
+    let action = match operation {
+        "install" => "installed",
+        "upgrade" => "upgraded",
+        _ => "removed",
     };
     
     println!("\n{} The following {} packages would be {}:", 
@@ -517,6 +1670,35 @@ fn print_dry_run_preview(packages: &[BrewPackage], operation: &str) {
         }
         println!();
     }
+
+    let mut planned = packages.to_vec();
+    if hook_policy != HookPolicy::Prompt {
+        apply_hook_policy(&mut planned, hook_policy);
+    }
+    let with_hooks: Vec<&BrewPackage> = planned.iter().filter(|p| p.has_hooks()).collect();
+    let dropped = packages.iter().filter(|p| p.has_hooks()).count() - with_hooks.len();
+    if operation == "install" && (!with_hooks.is_empty() || dropped > 0) {
+        println!("  {} Hooks:", style("→").yellow().bold());
+        for package in &with_hooks {
+            print_package_hooks(package);
+        }
+        let note = match hook_policy {
+            HookPolicy::Prompt => with_hooks
+                .iter()
+                .any(|p| p.is_from_remote_source())
+                .then(|| "Hooks from remote recipes run only after confirmation (see --hook-policy).".to_string()),
+            HookPolicy::Trust => Some("Every hook runs without confirmation (--hook-policy trust).".to_string()),
+            HookPolicy::Local | HookPolicy::Skip => (dropped > 0).then(|| {
+                format!("Hooks of {} package(s) would be skipped (--hook-policy {}).", dropped, hook_policy.label())
+            }),
+        };
+        if let Some(note) = note {
+            println!("  {} {}", style("ℹ").cyan(), note);
+        }
+        println!();
+    }
+
+    print_skipped_packages(skipped);
     
     println!("{} No changes were made. Run without {} to execute.", 
         style("✓").green().bold(),
@@ -0,0 +1,221 @@
+use crate::utilities::brim_state::state_dir;
+use crate::webhook::WebhookPayload;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Totals and last values carried from run to run, so the textfile can expose
+/// counters even though each brim invocation only sees its own run.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsState {
+    /// Packages by operation, then outcome (`completed`, `failed`, `rolled_back`, ...).
+    pub packages: BTreeMap<String, BTreeMap<String, u64>>,
+    /// Finished runs by operation, then status (`success`, `partial`, `drift`, ...).
+    pub runs: BTreeMap<String, BTreeMap<String, u64>>,
+    /// Unix time each operation last finished.
+    pub last_run: BTreeMap<String, i64>,
+    /// Each package's duration the last time brim worked on it.
+    pub durations: BTreeMap<String, PackageDuration>,
+    /// Missing and extra packages found by the last `--sync` or `brim watch` check.
+    pub drift: Option<Drift>,
+    /// Failed recipe fetches by source.
+    pub fetch_errors: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageDuration {
+    pub operation: String,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Drift {
+    pub missing: usize,
+    pub extra: usize,
+    /// Unix time of the check.
+    pub checked_at: i64,
+}
+
+impl MetricsState {
+    /// Adds a finished run from its `run.finished` payload. Sync results only
+    /// describe drift, so they update the drift gauges instead of package totals.
+    pub fn record_run(&mut self, payload: &WebhookPayload) {
+        let operation = &payload.run.operation;
+        *self
+            .runs
+            .entry(operation.clone())
+            .or_default()
+            .entry(payload.status.clone())
+            .or_default() += 1;
+        self.last_run.insert(operation.clone(), chrono::Utc::now().timestamp());
+
+        if operation == "sync" {
+            let count = |status: &str| payload.packages.iter().filter(|p| p.status == status).count();
+            self.record_drift(count("missing"), count("extra"));
+            return;
+        }
+
+        for package in &payload.packages {
+            *self
+                .packages
+                .entry(operation.clone())
+                .or_default()
+                .entry(package.status.clone())
+                .or_default() += 1;
+            if let Some(seconds) = package.duration_seconds {
+                self.durations.insert(
+                    package.name.clone(),
+                    PackageDuration {
+                        operation: operation.clone(),
+                        seconds,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Sets the drift gauges; zero for both means the machine matches the recipes.
+    pub fn record_drift(&mut self, missing: usize, extra: usize) {
+        self.drift = Some(Drift {
+            missing,
+            extra,
+            checked_at: chrono::Utc::now().timestamp(),
+        });
+    }
+
+    pub fn record_fetch_error(&mut self, source: &str) {
+        *self.fetch_errors.entry(source.to_string()).or_default() += 1;
+    }
+
+    /// The state in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        family(&mut out, "brim_packages_total", "counter", "Packages brim worked on, by operation and outcome.");
+        for (operation, outcomes) in &self.packages {
+            for (outcome, count) in outcomes {
+                sample(&mut out, "brim_packages_total", &[("operation", operation), ("outcome", outcome)], *count);
+            }
+        }
+
+        family(&mut out, "brim_runs_total", "counter", "Finished brim runs, by operation and status.");
+        for (operation, statuses) in &self.runs {
+            for (status, count) in statuses {
+                sample(&mut out, "brim_runs_total", &[("operation", operation), ("status", status)], *count);
+            }
+        }
+
+        family(
+            &mut out,
+            "brim_last_run_timestamp_seconds",
+            "gauge",
+            "Unix time the last run of each operation finished.",
+        );
+        for (operation, timestamp) in &self.last_run {
+            sample(&mut out, "brim_last_run_timestamp_seconds", &[("operation", operation)], timestamp);
+        }
+
+        family(
+            &mut out,
+            "brim_package_duration_seconds",
+            "gauge",
+            "How long the last install, upgrade or removal of each package took.",
+        );
+        for (package, duration) in &self.durations {
+            sample(
+                &mut out,
+                "brim_package_duration_seconds",
+                &[("package", package), ("operation", &duration.operation)],
+                duration.seconds,
+            );
+        }
+
+        if let Some(ref drift) = self.drift {
+            family(&mut out, "brim_drift_packages", "gauge", "Packages out of line with the recipes at the last check.");
+            sample(&mut out, "brim_drift_packages", &[("kind", "missing")], drift.missing);
+            sample(&mut out, "brim_drift_packages", &[("kind", "extra")], drift.extra);
+            family(&mut out, "brim_drift_check_timestamp_seconds", "gauge", "Unix time of the last drift check.");
+            sample(&mut out, "brim_drift_check_timestamp_seconds", &[], drift.checked_at);
+        }
+
+        family(&mut out, "brim_fetch_errors_total", "counter", "Failed recipe fetches, by source.");
+        for (source, count) in &self.fetch_errors {
+            sample(&mut out, "brim_fetch_errors_total", &[("source", source)], *count);
+        }
+
+        out
+    }
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+        .collect();
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// A textfile for node_exporter's textfile collector and the state behind it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    pub textfile: PathBuf,
+    pub state: PathBuf,
+}
+
+impl Metrics {
+    /// Writes to `textfile`, keeping totals in `<state_dir>/metrics.json`.
+    pub fn new(textfile: &Path) -> Self {
+        Self {
+            textfile: textfile.to_path_buf(),
+            state: state_dir().join("metrics.json"),
+        }
+    }
+
+    pub fn load(&self) -> MetricsState {
+        fs::read_to_string(&self.state)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Applies `change` to the stored state, saves it and rewrites the textfile.
+    pub fn update(&self, change: impl FnOnce(&mut MetricsState)) -> Result<(), String> {
+        let mut state = self.load();
+        change(&mut state);
+
+        let json = serde_json::to_string_pretty(&state).map_err(|e| format!("Failed to serialize metrics: {}", e))?;
+        write_atomically(&self.state, json.as_bytes())?;
+        write_atomically(&self.textfile, state.render().as_bytes())
+    }
+}
+
+/// Writes through a temporary file in the same directory and renames it into
+/// place, so the collector never reads a half-written file.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<(), String> {
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let temporary = dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+    fs::write(&temporary, content).map_err(|e| format!("Failed to write {}: {}", temporary.display(), e))?;
+    fs::rename(&temporary, path).map_err(|e| {
+        let _ = fs::remove_file(&temporary);
+        format!("Failed to write {}: {}", path.display(), e)
+    })
+}
@@ -7,6 +7,60 @@ pub struct BrewPackage {
     #[allow(dead_code)]
     pub url: Option<String>,
     pub cask: Option<bool>,
-    #[allow(dead_code)]
-    pub version: Option<String>
+    pub version: Option<String>,
+    /// Shell command run before `brew install`.
+    pub pre_install: Option<String>,
+    /// Shell command run after a successful `brew install`.
+    pub post_install: Option<String>,
+    /// Only install on this OS: `macos` or `linux`.
+    pub os: Option<String>,
+    /// Only install on this CPU architecture: `arm64` or `x86_64`.
+    pub arch: Option<String>,
+    /// Minimum macOS (or Linux distribution) version, e.g. `13` or `14.2`.
+    pub min_os_version: Option<String>,
+    /// Tag and environment expression, e.g. `tag:backend && !env:CI`.
+    pub when: Option<String>,
+    /// Recipe file or URL this entry was loaded from.
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+impl BrewPackage {
+    /// A package known only by name, as reported by `brew list`.
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            category: None,
+            url: None,
+            cask: None,
+            version: None,
+            pre_install: None,
+            post_install: None,
+            os: None,
+            arch: None,
+            min_os_version: None,
+            when: None,
+            source: None,
+        }
+    }
+
+    pub fn has_hooks(&self) -> bool {
+        self.pre_install.is_some() || self.post_install.is_some()
+    }
+
+    /// Whether the package belongs to one of `categories` (case-insensitive).
+    /// An empty filter matches every package.
+    pub fn in_categories(&self, categories: &[String]) -> bool {
+        categories.is_empty()
+            || self
+                .category
+                .as_deref()
+                .is_some_and(|c| categories.iter().any(|f| f.eq_ignore_ascii_case(c)))
+    }
+
+    pub fn is_from_remote_source(&self) -> bool {
+        self.source
+            .as_deref()
+            .is_some_and(|s| s.starts_with("http://") || s.starts_with("https://"))
+    }
 }
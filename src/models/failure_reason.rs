@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+/// Why a brew command failed for a package, derived from its exit code and output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    FormulaNotFound,
+    AlreadyInstalled,
+    ConflictingFormula,
+    ChecksumMismatch,
+    NetworkError,
+    NeedsSudo,
+    Timeout,
+    Cancelled,
+    HookFailed,
+    Unknown,
+}
+
+// Matched case-insensitively against every captured output line, first match wins.
+// Patterns follow Homebrew's own messages: `*` stands for any text, and a leading `^`
+// anchors the pattern at the start of the line.
+const PATTERNS: &[(&str, FailureReason)] = &[
+    ("no available formula", FailureReason::FormulaNotFound),
+    ("no available cask", FailureReason::FormulaNotFound),
+    ("no formulae or casks found", FailureReason::FormulaNotFound),
+    ("no cask with this name exists", FailureReason::FormulaNotFound),
+    ("no such keg", FailureReason::FormulaNotFound),
+    ("^error: cask '*' is not installed", FailureReason::FormulaNotFound),
+    ("already an app at", FailureReason::AlreadyInstalled),
+    ("is already installed", FailureReason::AlreadyInstalled),
+    ("conflicting formula", FailureReason::ConflictingFormula),
+    ("conflicts with", FailureReason::ConflictingFormula),
+    ("sha256 mismatch", FailureReason::ChecksumMismatch),
+    ("checksum mismatch", FailureReason::ChecksumMismatch),
+    ("could not resolve host", FailureReason::NetworkError),
+    ("failed to connect", FailureReason::NetworkError),
+    ("connection timed out", FailureReason::NetworkError),
+    ("failed to download", FailureReason::NetworkError),
+    ("download failed", FailureReason::NetworkError),
+    ("curl: (", FailureReason::NetworkError),
+    ("sudo: a terminal is required", FailureReason::NeedsSudo),
+    ("sudo: a password is required", FailureReason::NeedsSudo),
+    ("sudo: no tty present", FailureReason::NeedsSudo),
+    ("^password:", FailureReason::NeedsSudo),
+    ("permission denied", FailureReason::NeedsSudo),
+    ("operation not permitted", FailureReason::NeedsSudo),
+];
+
+/// Whether `line` matches a `PATTERNS` entry.
+fn matches_pattern(line: &str, pattern: &str) -> bool {
+    let (anchored, pattern) = match pattern.strip_prefix('^') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let mut rest = line.trim_start();
+    for (index, part) in pattern.split('*').enumerate() {
+        match rest.find(part) {
+            Some(position) if position == 0 || index > 0 || !anchored => rest = &rest[position + part.len()..],
+            _ => return false,
+        }
+    }
+    true
+}
+
+impl FailureReason {
+    /// Classifies a failed command from its exit code and output lines.
+    pub fn classify(exit_code: Option<i32>, lines: &[String]) -> Self {
+        let lowered: Vec<String> = lines.iter().map(|l| l.to_lowercase()).collect();
+
+        // Error lines are the most specific, so look at those before the rest.
+        let error_lines = lowered.iter().filter(|l| l.contains("error"));
+        for line in error_lines.chain(lowered.iter()) {
+            if let Some((_, reason)) = PATTERNS.iter().find(|(pattern, _)| matches_pattern(line, pattern)) {
+                return *reason;
+            }
+        }
+
+        match exit_code {
+            Some(130) | Some(143) => FailureReason::Cancelled,
+            _ => FailureReason::Unknown,
+        }
+    }
+
+    /// Reason for a command that never produced an exit status.
+    pub fn from_io_error(error: &std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::TimedOut => FailureReason::Timeout,
+            std::io::ErrorKind::Interrupted => FailureReason::Cancelled,
+            std::io::ErrorKind::PermissionDenied => FailureReason::NeedsSudo,
+            _ => FailureReason::Unknown,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FailureReason::FormulaNotFound => "formula not found",
+            FailureReason::AlreadyInstalled => "already installed",
+            FailureReason::ConflictingFormula => "conflicting formula",
+            FailureReason::ChecksumMismatch => "checksum mismatch",
+            FailureReason::NetworkError => "network error",
+            FailureReason::NeedsSudo => "needs sudo or password",
+            FailureReason::Timeout => "timeout",
+            FailureReason::Cancelled => "cancelled",
+            FailureReason::HookFailed => "hook failed",
+            FailureReason::Unknown => "unknown",
+        }
+    }
+
+    /// Process exit code used when every failure in a run has this reason.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FailureReason::Unknown => 1,
+            FailureReason::FormulaNotFound => 10,
+            FailureReason::AlreadyInstalled => 11,
+            FailureReason::ConflictingFormula => 12,
+            FailureReason::ChecksumMismatch => 13,
+            FailureReason::NetworkError => 14,
+            FailureReason::NeedsSudo => 15,
+            FailureReason::Timeout => 16,
+            FailureReason::HookFailed => 17,
+            FailureReason::Cancelled => 130,
+        }
+    }
+}
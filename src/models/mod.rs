@@ -1,3 +1,5 @@
 pub mod brew_package;
+pub mod failure_reason;
 
 pub use brew_package::BrewPackage;
+pub use failure_reason::FailureReason;
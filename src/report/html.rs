@@ -0,0 +1,96 @@
+use super::{failure_message, outcome, Outcome};
+use crate::webhook::format::summary;
+use crate::webhook::{PackageResult, WebhookPayload};
+
+const STYLE: &str = "
+body { font: 14px/1.5 -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #1f2328; margin: 2rem auto; max-width: 960px; padding: 0 1rem; }
+h1 { font-size: 1.4rem; margin-bottom: 0.25rem; }
+.meta { color: #59636e; margin-bottom: 1.5rem; }
+.counts span { display: inline-block; margin-right: 0.5rem; padding: 0.2rem 0.6rem; border-radius: 1rem; background: #eef1f4; }
+table { border-collapse: collapse; width: 100%; margin-top: 1.5rem; }
+th, td { text-align: left; padding: 0.4rem 0.6rem; border-bottom: 1px solid #d1d9e0; vertical-align: top; }
+th { background: #f6f8fa; }
+td.num { text-align: right; white-space: nowrap; }
+.status { font-weight: 600; }
+.completed { color: #1a7f37; }
+.failed { color: #cf222e; }
+.skipped { color: #9a6700; }
+pre { background: #f6f8fa; padding: 0.5rem; overflow-x: auto; white-space: pre-wrap; margin: 0.4rem 0 0; }
+";
+
+/// A single HTML page with the run summary and a row per package; no external assets.
+pub fn html(payload: &WebhookPayload) -> String {
+    let run = &payload.run;
+    let skipped = payload
+        .packages
+        .iter()
+        .filter(|p| matches!(outcome(p), Outcome::Skipped(_)))
+        .count();
+
+    let mut meta = vec![
+        format!("Run {}", run.run_id),
+        format!("{} {}", run.os, run.os_version.clone().unwrap_or_default()).trim().to_string(),
+        run.arch.clone(),
+        format!("brim {}", run.brim_version),
+        format!("started {}", run.started_at),
+    ];
+    if !run.finished_at.is_empty() {
+        meta.push(format!("finished {}", run.finished_at));
+    }
+
+    let mut page = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    page.push_str(&format!("<title>{}</title>\n", escape(&summary(payload))));
+    page.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", STYLE));
+    page.push_str(&format!("<h1>{}</h1>\n", escape(&summary(payload))));
+    page.push_str(&format!("<div class=\"meta\">{}</div>\n", escape(&meta.join(" · "))));
+    if !run.sources.is_empty() {
+        page.push_str(&format!("<div class=\"meta\">Sources: {}</div>\n", escape(&run.sources.join(", "))));
+    }
+
+    page.push_str(&format!(
+        "<div class=\"counts\"><span>{} total</span><span class=\"completed\">{} completed</span>\
+         <span class=\"failed\">{} failed</span><span class=\"skipped\">{} skipped</span><span>{}s</span></div>\n",
+        payload.total, payload.completed, payload.failed, skipped, payload.elapsed_seconds
+    ));
+
+    page.push_str("<table>\n<tr><th>Package</th><th>Status</th><th>Version</th><th>Attempts</th><th>Duration</th></tr>\n");
+    for package in &payload.packages {
+        page.push_str(&row(package));
+    }
+    page.push_str("</table>\n</body>\n</html>\n");
+    page
+}
+
+fn row(package: &PackageResult) -> String {
+    let (class, detail) = match outcome(package) {
+        Outcome::Passed => ("completed", String::new()),
+        Outcome::Skipped(reason) => ("skipped", format!("<div>{}</div>", reason)),
+        Outcome::Failed => {
+            let mut detail = format!("<div>{}</div>", escape(&failure_message(package)));
+            if let Some(ref error) = package.error {
+                detail.push_str(&format!("<details><summary>Error output</summary><pre>{}</pre></details>", escape(error)));
+            }
+            ("failed", detail)
+        }
+    };
+
+    format!(
+        "<tr><td>{}</td><td><span class=\"status {}\">{}</span>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+        escape(&package.name),
+        class,
+        escape(&package.status),
+        detail,
+        escape(package.version.as_deref().unwrap_or("")),
+        package.attempts.map(|a| a.to_string()).unwrap_or_default(),
+        package.duration_seconds.map(|d| format!("{:.2}s", d)).unwrap_or_default()
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
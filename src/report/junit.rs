@@ -0,0 +1,101 @@
+use super::{failure_message, outcome, reason_name, Outcome};
+use crate::webhook::{PackageResult, WebhookPayload};
+
+/// JUnit XML with one `<testsuite>` for the run and one `<testcase>` per package.
+/// Failed packages carry their reason as the failure type and brew's error lines as its text.
+pub fn junit(payload: &WebhookPayload) -> String {
+    let outcomes: Vec<Outcome> = payload.packages.iter().map(outcome).collect();
+    let failures = outcomes.iter().filter(|o| **o == Outcome::Failed).count();
+    let skipped = outcomes.iter().filter(|o| matches!(o, Outcome::Skipped(_))).count();
+    let counts = format!(
+        r#"tests="{}" failures="{}" errors="0" skipped="{}" time="{}""#,
+        payload.packages.len(),
+        failures,
+        skipped,
+        payload.elapsed_seconds
+    );
+    let run = &payload.run;
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!("<testsuites name=\"brim\" {}>\n", counts));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" {} timestamp=\"{}\" hostname=\"{}\">\n",
+        escape(&format!("brim {}", run.operation)),
+        counts,
+        escape(&run.started_at),
+        escape(&run.hostname)
+    ));
+
+    xml.push_str("    <properties>\n");
+    let mut properties = vec![
+        ("run_id", run.run_id.clone()),
+        ("operation", run.operation.clone()),
+        ("status", payload.status.clone()),
+        ("os", run.os.clone()),
+        ("os_version", run.os_version.clone().unwrap_or_default()),
+        ("arch", run.arch.clone()),
+        ("brim_version", run.brim_version.clone()),
+    ];
+    properties.extend(run.sources.iter().map(|s| ("source", s.clone())));
+    for (name, value) in properties.iter().filter(|(_, value)| !value.is_empty()) {
+        xml.push_str(&format!("      <property name=\"{}\" value=\"{}\"/>\n", name, escape(value)));
+    }
+    xml.push_str("    </properties>\n");
+
+    for (package, outcome) in payload.packages.iter().zip(&outcomes) {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"brim.{}\" time=\"{}\"",
+            escape(&package.name),
+            escape(&run.operation),
+            package.duration_seconds.unwrap_or(0.0)
+        ));
+        match outcome {
+            Outcome::Passed => xml.push_str("/>\n"),
+            Outcome::Skipped(reason) => {
+                xml.push_str(&format!(">\n      <skipped message=\"{}\"/>\n    </testcase>\n", reason));
+            }
+            Outcome::Failed => {
+                xml.push_str(&format!(
+                    ">\n      <failure message=\"{}\" type=\"{}\">{}</failure>\n    </testcase>\n",
+                    escape(&failure_message(package)),
+                    escape(&reason_name(package).unwrap_or_else(|| "unknown".to_string())),
+                    escape(&failure_details(package))
+                ));
+            }
+        }
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn failure_details(package: &PackageResult) -> String {
+    let mut details = String::new();
+    if let Some(attempts) = package.attempts {
+        details.push_str(&format!("Attempts: {}\n", attempts));
+    }
+    if let Some(ref error) = package.error {
+        details.push_str(error);
+        details.push('\n');
+    }
+    details
+}
+
+/// Escapes markup characters and drops control characters XML 1.0 does not allow,
+/// such as the terminal escapes brew sometimes prints.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
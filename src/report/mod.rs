@@ -0,0 +1,95 @@
+use crate::webhook::{PackageResult, WebhookPayload};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod html;
+mod junit;
+
+pub use html::html;
+pub use junit::junit;
+
+/// File format of a `--report`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    /// JUnit XML, one test case per package, for CI test report views.
+    Junit,
+    /// A single HTML page with inline styles.
+    Html,
+}
+
+impl ReportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "junit" => Some(ReportFormat::Junit),
+            "html" => Some(ReportFormat::Html),
+            _ => None,
+        }
+    }
+
+    /// `html` for `.html` and `.htm` files, JUnit XML for anything else.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref() {
+            Some("html") | Some("htm") => ReportFormat::Html,
+            _ => ReportFormat::Junit,
+        }
+    }
+}
+
+/// Where a run's report goes and in which format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub path: PathBuf,
+    pub format: ReportFormat,
+}
+
+impl Report {
+    /// Renders `payload`, the run's final `run.finished` payload, and writes it to `path`.
+    pub fn write(&self, payload: &WebhookPayload) -> Result<(), String> {
+        let content = match self.format {
+            ReportFormat::Junit => junit(payload),
+            ReportFormat::Html => html(payload),
+        };
+
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create report directory {}: {}", parent.display(), e))?;
+        }
+        fs::write(&self.path, content).map_err(|e| format!("Failed to write report {}: {}", self.path.display(), e))
+    }
+}
+
+/// How a package counts in a report.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Passed,
+    Failed,
+    /// Never ran, or was undone by an atomic rollback; carries the reason.
+    Skipped(&'static str),
+}
+
+fn outcome(package: &PackageResult) -> Outcome {
+    match package.status.as_str() {
+        "completed" => Outcome::Passed,
+        "failed" => Outcome::Failed,
+        "rolled_back" => Outcome::Skipped("Rolled back after another package failed"),
+        "pending" => Outcome::Skipped("Not run"),
+        _ => Outcome::Skipped("Did not finish"),
+    }
+}
+
+/// The reason's name as sent in webhooks, e.g. `formula_not_found`.
+fn reason_name(package: &PackageResult) -> Option<String> {
+    let reason = serde_json::to_value(package.reason?).ok()?;
+    reason.as_str().map(String::from)
+}
+
+/// `reason: first error line` for a failed package, e.g. `formula not found: Error: No available formula`.
+fn failure_message(package: &PackageResult) -> String {
+    let first_line = package.error.as_deref().and_then(|e| e.lines().next()).map(str::trim);
+    match (package.reason, first_line) {
+        (Some(reason), Some(line)) => format!("{}: {}", reason.label(), line),
+        (Some(reason), None) => reason.label().to_string(),
+        (None, Some(line)) => line.to_string(),
+        (None, None) => "Failed".to_string(),
+    }
+}
@@ -1,7 +1,37 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use crate::models::BrewPackage;
-    use crate::webhook::{PackageResult, WebhookPayload};
+    use crate::config::{env_layer, parse_config, resolve_layers, Origin, SettingsLayer};
+    use crate::history::RunRecord;
+    use crate::models::{BrewPackage, FailureReason};
+    use crate::utilities::brew_hooks::{apply_hook_policy, run_hook, HookKind, HookPolicy};
+    use crate::utilities::brew_install_packages::{finish_rollback, rollback_selection};
+    use crate::utilities::brew_outdated_packages::{parse_outdated_json, pin_allows};
+    use crate::utilities::brew_export_packages::{
+        build_recipe, parse_category_map, parse_installed_json, recipe_version, ExportOptions,
+    };
+    use crate::utilities::brim_conditions::Condition;
+    use crate::utilities::brim_platform::{version_at_least, Platform};
+    use crate::utilities::brew_recipe_diff::{diff_recipes, FieldChange};
+    use crate::utilities::brew_recipe_lint::{lint_recipe, parse_info_json, LintSeverity, PackageMetadata};
+    use crate::utilities::brew_recipe_validation::{json_positions, validate_recipe};
+    use crate::utilities::brew_package_metadata::{edit_distance, ExistenceProblem, HomebrewIndex};
+    use crate::utilities::brew_package_log::{error_excerpt, log_file_for, LogStream};
+    use crate::watch::{drift_fingerprint, parse_interval, DriftTracker};
+    use crate::tui::list::ListScroll;
+    use crate::tui::progress::{active_index, summary_indices, PackageProgress};
+    use crate::tui::ProgressState;
+    use crate::webhook::{
+        exit_code_for, parse_header, post_body, render, render_template, retry_delay, signature, PackageResult,
+        RunContext, WebhookFormat, WebhookPayload, WebhookTarget, WEBHOOK_SCHEMA_VERSION,
+    };
+    use crate::webhook::events::{event_matches, parse_event_filter, watch_packages, Endpoint, EventDispatcher};
+    use crate::webhook::outbox::{flush_outbox, load_entries, save_entry, FlushOptions, OutboxEntry};
+    use crate::webhook::DeliveryFailure;
+    use crate::report::{html, junit, Report, ReportFormat};
+    use crate::metrics::{Metrics, MetricsState};
+    use crate::logging::{command_line, format_record, level_for, LogFormat};
+    use std::path::Path;
 
     #[test]
     fn test_brew_package_deserialization() {
@@ -39,10 +69,14 @@ mod tests {
             PackageResult {
                 name: "postgresql".to_string(),
                 status: "completed".to_string(),
+                reason: None,
+                ..Default::default()
             },
             PackageResult {
                 name: "redis".to_string(),
                 status: "completed".to_string(),
+                reason: None,
+                ..Default::default()
             },
         ];
 
@@ -53,6 +87,7 @@ mod tests {
             failed: 0,
             packages,
             elapsed_seconds: 120,
+            ..Default::default()
         };
 
         assert_eq!(payload.status, "success");
@@ -67,10 +102,14 @@ mod tests {
             PackageResult {
                 name: "postgresql".to_string(),
                 status: "completed".to_string(),
+                reason: None,
+                ..Default::default()
             },
             PackageResult {
                 name: "redis".to_string(),
                 status: "failed".to_string(),
+                reason: Some(FailureReason::NetworkError),
+                ..Default::default()
             },
         ];
 
@@ -81,6 +120,7 @@ mod tests {
             failed: 1,
             packages,
             elapsed_seconds: 120,
+            ..Default::default()
         };
 
         let json = serde_json::to_string(&payload).unwrap();
@@ -94,9 +134,1513 @@ mod tests {
         let result = PackageResult {
             name: "test-package".to_string(),
             status: "completed".to_string(),
+            reason: None,
+            ..Default::default()
         };
 
         assert_eq!(result.name, "test-package");
         assert_eq!(result.status, "completed");
     }
+
+    #[test]
+    fn test_error_excerpt_prefers_error_lines() {
+        let lines = vec![
+            (LogStream::Stdout, "==> Fetching wget".to_string()),
+            (LogStream::Stderr, "Warning: wget 1.24 is already installed".to_string()),
+            (LogStream::Stderr, "Error: No available formula with the name \"wgett\".".to_string()),
+            (LogStream::Stdout, "==> Searching for similarly named formulae...".to_string()),
+        ];
+
+        let excerpt = error_excerpt(&lines).unwrap();
+        assert_eq!(excerpt, "Error: No available formula with the name \"wgett\".");
+    }
+
+    #[test]
+    fn test_error_excerpt_falls_back_to_stderr() {
+        let lines = vec![
+            (LogStream::Stdout, "Pouring wget.bottle.tar.gz".to_string()),
+            (LogStream::Stderr, "permission denied".to_string()),
+        ];
+
+        assert_eq!(error_excerpt(&lines), Some("permission denied".to_string()));
+        assert_eq!(error_excerpt(&[(LogStream::Stdout, "ok".to_string())]), None);
+    }
+
+    #[test]
+    fn test_log_file_for_sanitizes_tap_names() {
+        let path = log_file_for(Path::new("/tmp/run"), "homebrew/cask/firefox");
+        assert_eq!(path, Path::new("/tmp/run/homebrew_cask_firefox.log"));
+    }
+
+    #[test]
+    fn test_failure_reason_classification() {
+        let lines = |l: &[&str]| l.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            FailureReason::classify(Some(1), &lines(&["Error: No available formula with the name \"postgressql\"."])),
+            FailureReason::FormulaNotFound
+        );
+        assert_eq!(
+            FailureReason::classify(Some(1), &lines(&["==> Downloading x", "Error: SHA256 mismatch"])),
+            FailureReason::ChecksumMismatch
+        );
+        assert_eq!(
+            FailureReason::classify(Some(1), &lines(&["curl: (6) Could not resolve host: ghcr.io"])),
+            FailureReason::NetworkError
+        );
+        assert_eq!(
+            FailureReason::classify(Some(1), &lines(&["sudo: a terminal is required to read the password"])),
+            FailureReason::NeedsSudo
+        );
+        assert_eq!(
+            FailureReason::classify(Some(1), &lines(&["Error: Cask 'firefox' is not installed."])),
+            FailureReason::FormulaNotFound
+        );
+        assert_eq!(FailureReason::classify(Some(1), &lines(&["Password:"])), FailureReason::NeedsSudo);
+        assert_eq!(FailureReason::classify(Some(1), &lines(&["something odd"])), FailureReason::Unknown);
+        assert_eq!(FailureReason::classify(Some(130), &[]), FailureReason::Cancelled);
+
+        // Near misses of the patterns above are not enough to classify a failure.
+        for near_miss in [
+            "Warning: python@3.12 is not installed, skipping",
+            "==> Verifying checksum for 'wget--1.24.5.arm64_sonoma.bottle.tar.gz'",
+            "==> Downloading https://ghcr.io/v2/homebrew/core/sudo/manifests/1.9.15",
+            "==> Caveats: set the admin password in ~/.config/app.toml",
+            "Warning: Cask 'firefox' is not installed.",
+        ] {
+            assert_eq!(FailureReason::classify(Some(1), &lines(&[near_miss])), FailureReason::Unknown, "{}", near_miss);
+        }
+    }
+
+    #[test]
+    fn test_failure_reason_serializes_snake_case() {
+        let result = PackageResult {
+            name: "wget".to_string(),
+            status: "failed".to_string(),
+            reason: Some(FailureReason::FormulaNotFound),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&result).unwrap();
+        assert!(json.contains("\"reason\":\"formula_not_found\""));
+    }
+
+    #[test]
+    fn test_exit_code_for_results() {
+        let result = |status: &str, reason| PackageResult {
+            name: "pkg".to_string(),
+            status: status.to_string(),
+            reason,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code_for(&[result("completed", None)]), 0);
+        assert_eq!(
+            exit_code_for(&[result("failed", Some(FailureReason::NetworkError)), result("completed", None)]),
+            14
+        );
+        assert_eq!(
+            exit_code_for(&[
+                result("failed", Some(FailureReason::NetworkError)),
+                result("failed", Some(FailureReason::Timeout)),
+            ]),
+            1
+        );
+    }
+
+    #[test]
+    fn test_brew_package_with_hooks() {
+        let json = r#"{
+            "name": "mkcert",
+            "pre_install": "echo preparing",
+            "post_install": "mkcert -install"
+        }"#;
+
+        let package: BrewPackage = serde_json::from_str(json).unwrap();
+        assert!(package.has_hooks());
+        assert_eq!(package.post_install.as_deref(), Some("mkcert -install"));
+        assert_eq!(package.source, None);
+    }
+
+    #[test]
+    fn test_hook_policy_local_strips_remote_hooks() {
+        let package = |source: &str| {
+            let mut package: BrewPackage =
+                serde_json::from_str(r#"{"name": "fzf", "post_install": "fzf-install"}"#).unwrap();
+            package.source = Some(source.to_string());
+            package
+        };
+        let mut packages = vec![package("recipes/local.json"), package("https://example.com/remote.json")];
+
+        apply_hook_policy(&mut packages, HookPolicy::Local);
+        assert!(packages[0].has_hooks());
+        assert!(!packages[1].has_hooks());
+
+        apply_hook_policy(&mut packages, HookPolicy::Skip);
+        assert!(!packages[0].has_hooks());
+    }
+
+    #[test]
+    fn test_run_record_roundtrip() {
+        let mut record = RunRecord::new("20261018-101500-42", "install", "2026-10-18T10:15:00+00:00");
+        record.sources = vec!["base.json".to_string()];
+        record.selected = vec!["wget".to_string(), "jq".to_string()];
+        record.newly_installed = vec!["wget".to_string()];
+        record.packages = vec![PackageResult {
+            name: "jq".to_string(),
+            status: "failed".to_string(),
+            reason: Some(FailureReason::ChecksumMismatch),
+            ..Default::default()
+        }];
+
+        let json = serde_json::to_string(&record).unwrap();
+        let parsed: RunRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.id, record.id);
+        assert_eq!(parsed.newly_installed, vec!["wget".to_string()]);
+        assert_eq!(parsed.packages[0].reason, Some(FailureReason::ChecksumMismatch));
+    }
+
+    #[test]
+    fn test_newly_installed_excludes_preinstalled_and_failed() {
+        let result = |name: &str, status: &str| PackageResult {
+            name: name.to_string(),
+            status: status.to_string(),
+            installed: status == "completed",
+            reason: None,
+            ..Default::default()
+        };
+        let results = vec![result("wget", "completed"), result("jq", "completed"), result("tree", "failed")];
+        let installed_before = vec![BrewPackage::named("jq")];
+
+        assert_eq!(crate::history::newly_installed(&results, &installed_before), vec!["wget".to_string()]);
+    }
+
+    #[test]
+    fn test_newly_installed_includes_failed_hooks() {
+        // `brew install` succeeded, then the post-install hook failed.
+        let mut progress = PackageProgress::new("redis".to_string());
+        progress.installed = true;
+        progress.state = ProgressState::Failed;
+        progress.failure = Some(FailureReason::HookFailed);
+        let results = vec![PackageResult::from_progress(&progress)];
+        assert_eq!(results[0].status, "failed");
+
+        let json = serde_json::to_string(&results).unwrap();
+        let parsed: Vec<PackageResult> = serde_json::from_str(&json).unwrap();
+        assert_eq!(crate::history::newly_installed(&parsed, &[]), vec!["redis".to_string()]);
+
+        // Rolled back packages are no longer installed.
+        progress.installed = false;
+        assert!(crate::history::newly_installed(&[PackageResult::from_progress(&progress)], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_undo_keeps_cask_flag() {
+        let mut record = RunRecord::new("20260101-120000-1", "install", "2026-01-01T12:00:00+00:00");
+        record.packages = vec![
+            PackageResult {
+                name: "firefox".to_string(),
+                status: "completed".to_string(),
+                cask: true,
+                ..Default::default()
+            },
+            PackageResult {
+                name: "wget".to_string(),
+                status: "completed".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(json.matches("\"cask\"").count(), 1);
+        let parsed: RunRecord = serde_json::from_str(&json).unwrap();
+        assert!(parsed.package("firefox").cask.is_some());
+        assert!(parsed.package("wget").cask.is_none());
+    }
+
+    #[test]
+    fn test_parse_outdated_json() {
+        let json = r#"{
+            "formulae": [
+                {"name": "wget", "installed_versions": ["1.21.3", "1.21.4"], "current_version": "1.24.5", "pinned": false},
+                {"name": "node", "installed_versions": ["20.1.0"], "current_version": "22.3.0", "pinned": true}
+            ],
+            "casks": [
+                {"name": "firefox", "installed_versions": "126.0", "current_version": "127.0"}
+            ]
+        }"#;
+
+        let outdated = parse_outdated_json(json).unwrap();
+        assert_eq!(outdated.len(), 3);
+        assert_eq!(outdated[0].installed_version, "1.21.4");
+        assert_eq!(outdated[0].available_version, "1.24.5");
+        assert!(outdated[1].pinned);
+        assert!(outdated[2].cask);
+        assert_eq!(outdated[2].installed_version, "126.0");
+    }
+
+    #[test]
+    fn test_pin_allows() {
+        assert!(pin_allows("1.2", "1.2"));
+        assert!(pin_allows("1.2", "1.2.7"));
+        assert!(pin_allows("1.2.7", "1.2.7_1"));
+        assert!(!pin_allows("1.2", "1.3.0"));
+        assert!(!pin_allows("1.2", "1.20.0"));
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("45").unwrap().as_secs(), 45);
+        assert_eq!(parse_interval("30m").unwrap().as_secs(), 1800);
+        assert_eq!(parse_interval("2h").unwrap().as_secs(), 7200);
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("10w").is_err());
+        assert!(parse_interval("soon").is_err());
+    }
+
+    #[test]
+    fn test_drift_fingerprint_ignores_order() {
+        assert_eq!(
+            drift_fingerprint(&["wget", "jq"], &["tree"]),
+            drift_fingerprint(&["jq", "wget"], &["tree"])
+        );
+        assert_ne!(drift_fingerprint(&["jq"], &[]), drift_fingerprint(&[], &["jq"]));
+    }
+
+    #[test]
+    fn test_drift_tracker_retries_failed_apply() {
+        let fingerprint = drift_fingerprint(&["wget"], &[]);
+        let mut drift = DriftTracker::default();
+        assert!(drift.is_new(&fingerprint));
+
+        // The apply failed, so the same drift is handled again on the next tick.
+        drift.handled(fingerprint.clone(), false);
+        assert!(drift.is_new(&fingerprint));
+
+        drift.handled(fingerprint.clone(), true);
+        assert!(!drift.is_new(&fingerprint));
+        assert!(drift.is_new(&drift_fingerprint(&["wget", "jq"], &[])));
+    }
+
+    #[test]
+    fn test_settings_layer_precedence() {
+        let file = parse_config(
+            r#"
+            [defaults]
+            parallel = true
+            jobs = 2
+
+            [profiles.work]
+            urls = ["base.json", "team.json"]
+            webhook = "https://hooks.example.com/brim"
+            categories = ["Development"]
+            "#,
+        )
+        .unwrap();
+
+        let env = env_layer(|name| match name {
+            "BRIM_JOBS" => Some("6".to_string()),
+            "BRIM_PARALLEL" => Some("false".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let cli = SettingsLayer {
+            jobs: Some(8),
+            ..Default::default()
+        };
+
+        let settings = resolve_layers(Some(&file), Some("work"), &env, &cli).unwrap();
+        assert_eq!(settings.urls, vec!["base.json".to_string(), "team.json".to_string()]);
+        assert_eq!(settings.origin("urls"), Origin::Profile);
+        assert!(!settings.parallel);
+        assert_eq!(settings.origin("parallel"), Origin::Environment);
+        assert_eq!(settings.jobs, 8);
+        assert_eq!(settings.origin("jobs"), Origin::CommandLine);
+        assert_eq!(settings.origin("atomic"), Origin::Default);
+
+        assert!(resolve_layers(Some(&file), Some("home"), &env, &cli).is_err());
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_keys_and_bad_env() {
+        assert!(parse_config("[defaults]\nparalel = true").is_err());
+        assert!(env_layer(|name| (name == "BRIM_ATOMIC").then(|| "maybe".to_string())).is_err());
+        assert!(env_layer(|name| (name == "BRIM_JOBS").then(|| "many".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_in_categories() {
+        let mut package = BrewPackage::named("ffmpeg");
+        package.category = Some("Media".to_string());
+
+        assert!(package.in_categories(&[]));
+        assert!(package.in_categories(&["media".to_string()]));
+        assert!(!package.in_categories(&["Development".to_string()]));
+        assert!(!BrewPackage::named("jq").in_categories(&["Media".to_string()]));
+    }
+
+    #[test]
+    fn test_validate_recipe_reports_every_error_with_position() {
+        let text = r#"[
+  {"name": "wget"},
+  {"name": "bad name!", "naem": "x"},
+  {"name": "wget", "version": "latest"}
+]"#;
+
+        let errors = validate_recipe("recipe.json", text);
+        let located: Vec<(usize, usize)> = errors.iter().map(|e| (e.line, e.column)).collect();
+        assert_eq!(located, vec![(3, 12), (3, 33), (4, 12), (4, 31)]);
+        assert!(errors[1].message.contains("unknown property 'naem'"));
+        assert!(errors[2].message.contains("already defined"));
+        assert_eq!(errors[0].to_string().split(':').take(3).collect::<Vec<_>>(), vec!["recipe.json", "3", "12"]);
+    }
+
+    #[test]
+    fn test_validate_recipe_schema_rules() {
+        assert!(validate_recipe("ok.json", r#"[{"name": "jq", "cask": false, "url": "https://formulae.brew.sh/formula/jq"}]"#).is_empty());
+        assert_eq!(validate_recipe("empty.json", "[]").len(), 1);
+        assert_eq!(validate_recipe("dup.json", r#"[{"name": "jq"}, {"name": "jq"}]"#).len(), 1);
+        assert_eq!(validate_recipe("syntax.json", r#"[{"name": "jq",}]"#)[0].line, 1);
+        assert_eq!(validate_recipe("type.json", r#"{"name": "jq"}"#).len(), 1);
+    }
+
+    #[test]
+    fn test_json_positions() {
+        let positions = json_positions("{\n  \"a/b\": [1, {\"c\": true}]\n}");
+        assert_eq!(positions[""], (1, 1));
+        assert_eq!(positions["/a~1b"], (2, 10));
+        assert_eq!(positions["/a~1b/1/c"], (2, 20));
+    }
+
+    #[test]
+    fn test_homebrew_index_check() {
+        let index = HomebrewIndex {
+            formulae: ["wget", "postgresql", "jq"].iter().map(|s| s.to_string()).collect(),
+            casks: ["firefox"].iter().map(|s| s.to_string()).collect(),
+        };
+        let cask = |name: &str| BrewPackage {
+            cask: Some(true),
+            ..BrewPackage::named(name)
+        };
+        let packages = vec![
+            BrewPackage::named("wget"),
+            BrewPackage::named("postgressql"),
+            BrewPackage::named("firefox"),
+            cask("jq"),
+            BrewPackage::named("youtube-dl"),
+            BrewPackage::named("user/tap/tool"),
+        ];
+
+        let issues = index.check(&packages, |name| (name == "youtube-dl").then(|| "yt-dlp".to_string()));
+        let problems: Vec<(usize, ExistenceProblem)> = issues.iter().map(|i| (i.index, i.problem.clone())).collect();
+        assert_eq!(
+            problems,
+            vec![
+                (1, ExistenceProblem::Unknown { suggestions: vec!["postgresql".to_string()] }),
+                (2, ExistenceProblem::IsCask),
+                (3, ExistenceProblem::IsFormula),
+                (4, ExistenceProblem::Renamed { to: "yt-dlp".to_string() }),
+            ]
+        );
+        assert!(!issues[3].is_error());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("postgressql", "postgresql"), 1);
+        assert_eq!(edit_distance("wget", "wget"), 0);
+        assert_eq!(edit_distance("", "jq"), 2);
+    }
+
+    #[test]
+    fn test_parse_info_json() {
+        let json = r#"{
+            "formulae": [
+                {"name": "youtube-dl", "deprecated": true, "disabled": false,
+                 "deprecation_reason": "unmaintained", "conflicts_with": [], "dependencies": ["python@3.12"]},
+                {"name": "mysql", "deprecated": false, "disabled": false,
+                 "conflicts_with": ["mariadb"], "dependencies": ["openssl@3"]}
+            ],
+            "casks": [
+                {"token": "docker", "deprecated": false, "disabled": true,
+                 "conflicts_with": {"cask": ["docker-edge"]}, "depends_on": {"formula": ["colima"]}}
+            ]
+        }"#;
+
+        let metadata = parse_info_json(json).unwrap();
+        assert_eq!(metadata.len(), 3);
+        assert!(metadata[0].deprecated);
+        assert_eq!(metadata[0].reason.as_deref(), Some("unmaintained"));
+        assert_eq!(metadata[1].conflicts_with, vec!["mariadb"]);
+        assert_eq!(metadata[2].name, "docker");
+        assert!(metadata[2].disabled);
+        assert_eq!(metadata[2].conflicts_with, vec!["docker-edge"]);
+        assert_eq!(metadata[2].dependencies, vec!["colima"]);
+        assert!(parse_info_json("not json").is_err());
+    }
+
+    #[test]
+    fn test_lint_recipe() {
+        let categorized = |name: &str, category: &str| BrewPackage {
+            category: Some(category.to_string()),
+            ..BrewPackage::named(name)
+        };
+        let meta = |name: &str, conflicts: &[&str], dependencies: &[&str]| PackageMetadata {
+            name: name.to_string(),
+            conflicts_with: conflicts.iter().map(|s| s.to_string()).collect(),
+            dependencies: dependencies.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let sources = vec![
+            (
+                "base.json".to_string(),
+                vec![BrewPackage::named("mysql"), BrewPackage::named("openssl@3"), BrewPackage::named("wget")],
+            ),
+            (
+                "team.json".to_string(),
+                vec![
+                    BrewPackage::named("mariadb"),
+                    categorized("wget", "Networking"),
+                    categorized("youtube-dl", "Games"),
+                ],
+            ),
+        ];
+        let metadata = vec![
+            meta("mysql", &["mariadb"], &["openssl@3"]),
+            meta("mariadb", &["mysql"], &[]),
+            meta("openssl@3", &[], &[]),
+            PackageMetadata {
+                deprecated: true,
+                reason: Some("unmaintained".to_string()),
+                ..meta("youtube-dl", &[], &[])
+            },
+            PackageMetadata {
+                disabled: true,
+                ..meta("wget", &[], &[])
+            },
+        ];
+
+        let findings = lint_recipe(&sources, &metadata, &["networking".to_string()]);
+        let summary: Vec<(LintSeverity, &str, &str)> = findings
+            .iter()
+            .map(|f| (f.severity, f.package.as_str(), f.message.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (LintSeverity::Warning, "wget", "defined in base.json, team.json (team.json wins)"),
+                (LintSeverity::Warning, "openssl@3", "is already installed as a dependency of mysql"),
+                (LintSeverity::Error, "wget", "is disabled in Homebrew"),
+                (LintSeverity::Error, "mariadb", "conflicts with 'mysql'"),
+                (LintSeverity::Warning, "youtube-dl", "is deprecated in Homebrew (unmaintained)"),
+                (
+                    LintSeverity::Warning,
+                    "youtube-dl",
+                    "category 'Games' is not in the allowed list (networking)"
+                ),
+            ]
+        );
+
+        assert!(lint_recipe(&sources[..1], &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_platform_skip_reason() {
+        let conditional = |os: Option<&str>, arch: Option<&str>, min: Option<&str>, cask: bool| BrewPackage {
+            os: os.map(String::from),
+            arch: arch.map(String::from),
+            min_os_version: min.map(String::from),
+            cask: Some(true).filter(|_| cask),
+            ..BrewPackage::named("pkg")
+        };
+        let mac = Platform {
+            os: "macos".to_string(),
+            arch: "arm64".to_string(),
+            os_version: Some("14.2".to_string()),
+            tags: vec![],
+        };
+        let linux = Platform {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            os_version: None,
+            tags: vec![],
+        };
+
+        assert_eq!(mac.skip_reason(&conditional(None, None, None, true)), None);
+        assert_eq!(mac.skip_reason(&conditional(Some("macos"), Some("arm64"), Some("14"), false)), None);
+        assert_eq!(
+            mac.skip_reason(&conditional(Some("linux"), None, None, false)).as_deref(),
+            Some("requires linux (this is macos)")
+        );
+        assert_eq!(
+            mac.skip_reason(&conditional(None, Some("x86_64"), None, false)).as_deref(),
+            Some("requires x86_64 (this is arm64)")
+        );
+        assert_eq!(
+            mac.skip_reason(&conditional(None, None, Some("15"), false)).as_deref(),
+            Some("requires macos 15 or later (this is 14.2)")
+        );
+        assert_eq!(
+            linux.skip_reason(&conditional(None, None, None, true)).as_deref(),
+            Some("casks are not supported on Linux")
+        );
+        assert_eq!(
+            linux.skip_reason(&conditional(None, None, Some("22.04"), false)).as_deref(),
+            Some("requires linux 22.04 or later (version unknown)")
+        );
+
+        let (applicable, skipped) = linux.partition(vec![
+            BrewPackage::named("wget"),
+            conditional(None, None, None, true),
+        ]);
+        assert_eq!(applicable.len(), 1);
+        assert_eq!(skipped.len(), 1);
+    }
+
+    #[test]
+    fn test_version_at_least() {
+        assert!(version_at_least("14.2", "14"));
+        assert!(version_at_least("14.2.1", "14.2"));
+        assert!(version_at_least("13.0", "13"));
+        assert!(!version_at_least("13.6", "14"));
+        assert!(!version_at_least("10.9", "10.10"));
+    }
+
+    #[test]
+    fn test_validate_recipe_platform_conditions() {
+        let valid = r#"[{"name": "wget", "os": "linux", "arch": "x86_64", "min_os_version": "22.04"}]"#;
+        assert!(validate_recipe("r.json", valid).is_empty());
+
+        let invalid = r#"[{"name": "wget", "os": "windows", "min_os_version": "latest"}]"#;
+        let messages: Vec<String> = validate_recipe("r.json", invalid).into_iter().map(|e| e.message).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("'/0/os' value 'windows' does not match"));
+        assert!(messages[1].starts_with("'/0/min_os_version' value 'latest' does not match"));
+    }
+
+    #[test]
+    fn test_condition_parse_and_eval() {
+        let tags = vec!["Backend".to_string(), "data".to_string()];
+        let env = |name: &str| match name {
+            "CI" => Some("true".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        let eval = |text: &str| Condition::parse(text).unwrap().eval(&tags, &env);
+
+        assert!(eval("tag:backend"));
+        assert!(!eval("tag:frontend"));
+        assert!(!eval("tag:backend && !env:CI"));
+        assert!(eval("tag:frontend || tag:data && env:CI=true"));
+        assert!(!eval("(tag:frontend || tag:data) && env:CI=false"));
+        assert!(!eval("env:EMPTY"));
+        assert!(eval("!env:HOME_MISSING"));
+
+        let condition = Condition::parse("tag:backend&&!(env:CI||tag:frontend)").unwrap();
+        assert_eq!(condition.to_string(), "tag:backend && !(env:CI || tag:frontend)");
+        assert_eq!(Condition::parse(&condition.to_string()).unwrap(), condition);
+
+        assert!(Condition::parse("tag:backend &&").is_err());
+        assert!(Condition::parse("tag:a & tag:b").is_err());
+        assert!(Condition::parse("(tag:a").is_err());
+        assert!(Condition::parse("os:linux").is_err());
+        assert!(Condition::parse("tag:a tag:b").is_err());
+    }
+
+    #[test]
+    fn test_condition_skip_reason() {
+        let platform = Platform {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            os_version: None,
+            tags: vec!["backend".to_string()],
+        };
+        let env = |name: &str| (name == "CI").then(|| "1".to_string());
+        let when = |expression: &str| BrewPackage {
+            when: Some(expression.to_string()),
+            ..BrewPackage::named("pkg")
+        };
+
+        assert_eq!(platform.skip_reason_with_env(&when("tag:backend"), &env), None);
+        assert_eq!(
+            platform.skip_reason_with_env(&when("tag:backend && !env:CI"), &env).as_deref(),
+            Some("when \"tag:backend && !env:CI\": CI is set")
+        );
+        assert_eq!(
+            platform.skip_reason_with_env(&when("tag:frontend || tag:data"), &env).as_deref(),
+            Some("when \"tag:frontend || tag:data\": not tagged 'frontend' and not tagged 'data'")
+        );
+        assert_eq!(
+            platform.skip_reason_with_env(&when("env:STAGE=prod"), &env).as_deref(),
+            Some("when \"env:STAGE=prod\": STAGE is not set")
+        );
+    }
+
+    #[test]
+    fn test_validate_recipe_when_expression() {
+        let text = r#"[{"name": "wget", "when": "tag:backend &&"}]"#;
+        let errors = validate_recipe("r.json", text);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "invalid when expression: unexpected end of expression");
+        assert_eq!((errors[0].line, errors[0].column), (1, 27));
+    }
+
+    #[test]
+    fn test_export_recipe() {
+        let json = r#"{
+            "formulae": [
+                {"name": "wget", "full_name": "wget", "tap": "homebrew/core",
+                 "installed": [{"version": "1.21.4_1", "installed_on_request": true}]},
+                {"name": "openssl@3", "full_name": "openssl@3", "tap": "homebrew/core",
+                 "installed": [{"version": "3.3.1", "installed_on_request": false}]},
+                {"name": "tool", "full_name": "user/tap/tool", "tap": "user/tap",
+                 "installed": [{"version": "2.0", "installed_on_request": true}]}
+            ],
+            "casks": [{"token": "firefox", "full_token": "firefox", "tap": "homebrew/cask", "installed": "128.0.3"}]
+        }"#;
+        let installed = parse_installed_json(json).unwrap();
+        let names: Vec<&str> = installed.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["wget", "user/tap/tool", "firefox"]);
+
+        let options = ExportOptions {
+            with_versions: true,
+            categories: parse_category_map(r#"{"firefox": "Browsers"}"#).unwrap(),
+        };
+        let text = serde_json::to_string(&build_recipe(&installed, &options)).unwrap();
+        assert_eq!(
+            text,
+            concat!(
+                r#"[{"name":"user/tap/tool","version":"2.0"},"#,
+                r#"{"name":"wget","url":"https://formulae.brew.sh/formula/wget","version":"1.21.4"},"#,
+                r#"{"name":"firefox","category":"Browsers","url":"https://formulae.brew.sh/cask/firefox","cask":true,"version":"128.0.3"}]"#
+            )
+        );
+        assert!(validate_recipe("export.json", &text).is_empty());
+
+        assert!(parse_category_map(r#"["wget"]"#).is_err());
+        assert!(parse_category_map(r#"{"wget": ""}"#).is_err());
+    }
+
+    #[test]
+    fn test_recipe_version() {
+        assert_eq!(recipe_version("3.3.1_1").as_deref(), Some("3.3.1"));
+        assert_eq!(recipe_version("1.2.3.4").as_deref(), Some("1.2.3"));
+        assert_eq!(recipe_version("8.9p1").as_deref(), Some("8.9"));
+        assert_eq!(recipe_version("2024.1").as_deref(), Some("2024.1"));
+        assert_eq!(recipe_version("21"), None);
+        assert_eq!(recipe_version("latest"), None);
+    }
+
+    #[test]
+    fn test_validate_recipe_versioned_and_tap_names() {
+        let text = r#"[{"name": "postgresql@16"}, {"name": "user/tap/tool"}, {"name": "gtk+3"}]"#;
+        assert!(validate_recipe("r.json", text).is_empty());
+        assert_eq!(validate_recipe("r.json", r#"[{"name": "a/b"}]"#).len(), 1);
+    }
+
+    #[test]
+    fn test_diff_recipes() {
+        let package = |name: &str, category: Option<&str>, version: Option<&str>| BrewPackage {
+            category: category.map(String::from),
+            version: version.map(String::from),
+            ..BrewPackage::named(name)
+        };
+        let before = vec![
+            package("wget", Some("Networking"), None),
+            package("jq", None, Some("1.6")),
+            package("tree", None, None),
+        ];
+        let after = vec![
+            package("jq", Some("Tools"), Some("1.7")),
+            package("tree", None, None),
+            BrewPackage {
+                cask: Some(true),
+                ..BrewPackage::named("firefox")
+            },
+        ];
+
+        let diff = diff_recipes(&before, &after);
+        assert_eq!(diff.added.iter().map(|e| (e.name.as_str(), e.cask)).collect::<Vec<_>>(), vec![("firefox", true)]);
+        assert_eq!(diff.removed.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["wget"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].name, "jq");
+        assert_eq!(
+            diff.changed[0].changes,
+            vec![
+                FieldChange {
+                    field: "category",
+                    before: None,
+                    after: Some("Tools".to_string()),
+                },
+                FieldChange {
+                    field: "version",
+                    before: Some("1.6".to_string()),
+                    after: Some("1.7".to_string()),
+                },
+            ]
+        );
+        assert!(diff_recipes(&before, &before).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_and_merge_keeps_recipe_order() {
+        let dir = std::env::temp_dir().join(format!("brim-merge-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("base.json").display().to_string();
+        let team = dir.join("team.json").display().to_string();
+        std::fs::write(&base, r#"[{"name": "zsh"}, {"name": "wget"}, {"name": "jq"}, {"name": "bat"}]"#).unwrap();
+        std::fs::write(&team, r#"[{"name": "node"}, {"name": "wget", "category": "Networking"}]"#).unwrap();
+
+        let merged = crate::utilities::fetch_and_merge(&[&base, &team], false).await.unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let names: Vec<&str> = merged.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["zsh", "wget", "jq", "bat", "node"]);
+        assert_eq!(merged[1].category.as_deref(), Some("Networking"));
+        assert_eq!(merged[1].source.as_deref(), Some(team.as_str()));
+    }
+
+    #[test]
+    fn test_webhook_payload_run_context() {
+        let mut record = RunRecord::new("20260101-120000-42", "install", "2026-01-01T12:00:00+00:00");
+        record.sources = vec!["base.json".to_string()];
+        let run = RunContext::from_record(&record);
+        assert_eq!(run.brim_version, env!("CARGO_PKG_VERSION"));
+        assert!(!run.hostname.is_empty());
+
+        let payload = WebhookPayload::from_results(run, vec![], 3, false);
+        let json: serde_json::Value = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["schema_version"], WEBHOOK_SCHEMA_VERSION);
+        assert_eq!(json["run_id"], "20260101-120000-42");
+        assert_eq!(json["operation"], "install");
+        assert_eq!(json["sources"][0], "base.json");
+        assert_eq!(json["started_at"], "2026-01-01T12:00:00+00:00");
+        for key in ["hostname", "os", "arch", "finished_at", "status", "elapsed_seconds"] {
+            assert!(json.get(key).is_some(), "missing {}", key);
+        }
+
+        let drift = WebhookPayload::drift(RunContext::default(), vec![], 0);
+        assert_eq!(drift.status, "drift");
+        assert_eq!(drift.schema_version, WEBHOOK_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_package_result_details_from_progress() {
+        let mut progress = PackageProgress::new("wget".to_string());
+        progress.state = ProgressState::Completed;
+        progress.attempts = 1;
+        progress.duration = Some(std::time::Duration::from_millis(12_345));
+        progress.version = Some("1.21.4".to_string());
+        progress.message = "Done!".to_string();
+
+        let result = PackageResult::from_progress(&progress);
+        assert_eq!(result.duration_seconds, Some(12.35));
+        assert_eq!(result.attempts, Some(1));
+        assert_eq!(result.version.as_deref(), Some("1.21.4"));
+        assert_eq!(result.error, None);
+
+        progress.state = ProgressState::Failed;
+        progress.failure = Some(FailureReason::FormulaNotFound);
+        progress.message = "Installation failed: Error: No available formula".to_string();
+        progress.error = Some("Error: No available formula".to_string());
+        assert_eq!(PackageResult::from_progress(&progress).error.as_deref(), Some("Error: No available formula"));
+
+        // Packages that never ran report no timing or attempts.
+        let pending = PackageResult::from_progress(&PackageProgress::new("jq".to_string()));
+        assert_eq!((pending.duration_seconds, pending.attempts), (None, None));
+
+        // History written before these fields existed still loads.
+        let old: PackageResult = serde_json::from_str(r#"{"name": "jq", "status": "completed"}"#).unwrap();
+        assert_eq!(old.version, None);
+    }
+
+    #[test]
+    fn test_webhook_signature() {
+        assert_eq!(
+            signature("secret", 1_700_000_000, br#"{"status":"success"}"#),
+            "sha256=b9d885ac4c0b760e29066c5a876592edeab4b3c28f9820d2c9f3d543fc32e3c1"
+        );
+        assert_eq!(retry_delay(1).as_secs(), 1);
+        assert_eq!(retry_delay(3).as_secs(), 4);
+        assert_eq!(retry_delay(10).as_secs(), 30);
+    }
+
+    #[test]
+    fn test_parse_webhook_header() {
+        assert_eq!(
+            parse_header("X-Api-Key:  abc:123 ").unwrap(),
+            ("X-Api-Key".to_string(), "abc:123".to_string())
+        );
+        assert!(parse_header("X-Api-Key").is_err());
+        assert!(parse_header("Bad Name: value").is_err());
+        assert!(parse_header(": value").is_err());
+    }
+
+    #[test]
+    fn test_webhook_settings() {
+        let file = parse_config(
+            r#"
+            [defaults]
+            webhook = "https://hooks.example.com/brim"
+            webhook_secret = "from-file"
+            webhook_headers = ["X-Api-Key: abc"]
+            "#,
+        )
+        .unwrap();
+        let env = env_layer(|name| match name {
+            "BRIM_WEBHOOK_SECRET" => Some("from-env".to_string()),
+            "BRIM_WEBHOOK_HEADERS" => Some("X-Api-Key: a,b\nX-Team: infra".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let cli = SettingsLayer {
+            webhook_retries: Some(0),
+            ..Default::default()
+        };
+
+        let target = resolve_layers(Some(&file), None, &env, &cli).unwrap().webhook_target().unwrap();
+        assert_eq!(target.url, "https://hooks.example.com/brim");
+        assert_eq!(target.secret.as_deref(), Some("from-env"));
+        assert_eq!(target.retries, 0);
+        assert_eq!(
+            target.headers,
+            vec![
+                ("X-Api-Key".to_string(), "a,b".to_string()),
+                ("X-Team".to_string(), "infra".to_string())
+            ]
+        );
+
+        let bad = parse_config("[defaults]\nwebhook_headers = [\"no colon\"]").unwrap();
+        assert!(resolve_layers(Some(&bad), None, &SettingsLayer::default(), &cli).is_err());
+        assert!(env_layer(|name| (name == "BRIM_WEBHOOK_RETRIES").then(|| "often".to_string())).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_post_body_retries_server_errors() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests: Vec<String> = vec![];
+            for status in ["503 Service Unavailable", "200 OK"] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buffer = [0u8; 4096];
+                loop {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some(end) = text.find("\r\n\r\n") else { continue };
+                    let length: usize = text
+                        .lines()
+                        .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(0);
+                    if read == 0 || request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                socket.write_all(response.as_bytes()).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request).to_string());
+            }
+            requests
+        });
+
+        let target = WebhookTarget {
+            url,
+            secret: Some("secret".to_string()),
+            retries: 2,
+            headers: vec![("X-Api-Key".to_string(), "abc".to_string())],
+            ..Default::default()
+        };
+        let body = target.body(&WebhookPayload::default()).unwrap();
+        post_body(&target, &body).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in &requests {
+            let lower = request.to_lowercase();
+            assert!(lower.contains("x-api-key: abc"));
+            assert!(lower.contains("x-brim-timestamp: "));
+            assert!(lower.contains("x-brim-signature: sha256="));
+        }
+
+        let rejected = WebhookTarget {
+            url: "http://127.0.0.1:9/hook".to_string(),
+            retries: 0,
+            ..Default::default()
+        };
+        let failure = post_body(&rejected, &body).await.unwrap_err();
+        assert!(failure.retryable);
+    }
+
+    fn partial_payload() -> WebhookPayload {
+        let run = RunContext {
+            run_id: "20260101-120000-42".to_string(),
+            operation: "install".to_string(),
+            hostname: "dev-laptop".to_string(),
+            os: "macos".to_string(),
+            arch: "arm64".to_string(),
+            finished_at: "2026-01-01T12:04:00+00:00".to_string(),
+            ..Default::default()
+        };
+        let results = vec![
+            PackageResult {
+                name: "wget".to_string(),
+                status: "completed".to_string(),
+                ..Default::default()
+            },
+            PackageResult {
+                name: "redis".to_string(),
+                status: "failed".to_string(),
+                reason: Some(FailureReason::NetworkError),
+                error: Some("Error: Failed to download \"redis\"\ncurl: (6)".to_string()),
+                ..Default::default()
+            },
+        ];
+        WebhookPayload::from_results(run, results, 240, false)
+    }
+
+    #[test]
+    fn test_webhook_chat_formats() {
+        let payload = partial_payload();
+        let parse = |format| serde_json::from_slice::<serde_json::Value>(&render(&payload, format).unwrap()).unwrap();
+        let failure = r#"redis (network error): Error: Failed to download "redis""#;
+
+        let slack = parse(WebhookFormat::Slack);
+        assert_eq!(slack["text"], "brim install on dev-laptop: partial, 1 completed, 1 failed");
+        assert!(slack["blocks"][1]["text"]["text"].as_str().unwrap().contains(failure));
+
+        let discord = parse(WebhookFormat::Discord);
+        assert_eq!(discord["embeds"][0]["color"], 0xe01e5a);
+        assert_eq!(discord["embeds"][0]["fields"][0]["name"], "Failed");
+        assert_eq!(discord["embeds"][0]["fields"][0]["value"], format!("**{}**", failure));
+
+        let teams = parse(WebhookFormat::Teams);
+        assert_eq!(teams["@type"], "MessageCard");
+        assert_eq!(teams["themeColor"], "E01E5A");
+        assert_eq!(teams["sections"][1]["activityTitle"], "Failed");
+
+        let generic = parse(WebhookFormat::Generic);
+        assert_eq!(generic["status"], "partial");
+        assert_eq!(WebhookFormat::parse("teams"), Some(WebhookFormat::Teams));
+        assert_eq!(WebhookFormat::parse("email"), None);
+    }
+
+    #[test]
+    fn test_webhook_template() {
+        let payload = partial_payload();
+        let template = r#"{"msg": "{{ icon }} {{summary}}", "failed": "{{failed_packages}}", "raw": {{payload}}}"#;
+        let body: serde_json::Value = serde_json::from_slice(&render_template(template, &payload).unwrap()).unwrap();
+
+        assert_eq!(body["msg"], "❌ brim install on dev-laptop: partial, 1 completed, 1 failed");
+        assert_eq!(body["failed"], r#"redis (network error): Error: Failed to download "redis""#);
+        assert_eq!(body["raw"]["run_id"], "20260101-120000-42");
+
+        assert!(render_template("{{nope}}", &payload).unwrap_err().contains("unknown placeholder '{{nope}}'"));
+        assert!(render_template("{{status", &payload).is_err());
+        assert!(resolve_layers(
+            None,
+            None,
+            &SettingsLayer::default(),
+            &SettingsLayer {
+                webhook_format: Some("email".to_string()),
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_webhook_event_filters() {
+        assert!(event_matches("package.*", "package.failed"));
+        assert!(event_matches("*", "run.started"));
+        assert!(!event_matches("run.finished", "run.started"));
+        assert!(parse_event_filter(&["run.*".to_string(), "drift.detected".to_string()]).is_ok());
+        assert!(parse_event_filter(&["run.done".to_string()]).is_err());
+
+        let file = parse_config(
+            r#"
+            [defaults]
+            webhook = "https://hooks.example.com/brim"
+
+            [[defaults.webhooks]]
+            url = "https://dashboard.example.com/events"
+            events = ["package.*", "run.*"]
+            format = "slack"
+            "#,
+        )
+        .unwrap();
+        let settings = resolve_layers(Some(&file), None, &SettingsLayer::default(), &SettingsLayer::default()).unwrap();
+        let endpoints = settings.webhook_endpoints();
+        assert_eq!(endpoints.len(), 2);
+        assert!(endpoints[0].accepts("run.finished") && !endpoints[0].accepts("package.started"));
+        assert!(endpoints[1].accepts("package.started") && !endpoints[1].accepts("drift.detected"));
+        assert_eq!(endpoints[1].target.format, WebhookFormat::Slack);
+        assert_eq!(endpoints[1].target.retries, 3);
+
+        let bad = parse_config("[[defaults.webhooks]]\nurl = \"https://x\"\nevents = [\"pkg.*\"]").unwrap();
+        assert!(resolve_layers(Some(&bad), None, &SettingsLayer::default(), &SettingsLayer::default()).is_err());
+    }
+
+    /// Accepts `count` HTTP requests on a local port, answering 200, and returns their bodies.
+    fn capture_bodies(count: usize) -> (String, std::thread::JoinHandle<Vec<serde_json::Value>>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut bodies = vec![];
+            for _ in 0..count {
+                let (mut socket, _) = listener.accept().unwrap();
+                let mut request = vec![];
+                let mut buffer = [0u8; 4096];
+                let body = loop {
+                    let read = socket.read(&mut buffer).unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some(end) = text.find("\r\n\r\n") else { continue };
+                    let length: usize = text
+                        .lines()
+                        .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break request[end + 4..end + 4 + length].to_vec();
+                    }
+                };
+                socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .unwrap();
+                bodies.push(serde_json::from_slice(&body).unwrap());
+            }
+            bodies
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn test_package_lifecycle_events() {
+        let (url, server) = capture_bodies(5);
+        let endpoint = |url: &str, events: &[&str]| Endpoint {
+            target: WebhookTarget {
+                url: url.to_string(),
+                ..Default::default()
+            },
+            events: events.iter().map(|e| e.to_string()).collect(),
+        };
+        let run = RunContext::new("20260101-120000-42", "install", "2026-01-01T12:00:00+00:00", &[]);
+        let events = EventDispatcher::start(
+            vec![endpoint(&url, &["run.started", "package.*"]), endpoint("http://127.0.0.1:9/unused", &["drift.detected"])],
+            run,
+        );
+        events.emit(WebhookPayload::run_started(events.run(), &["wget".to_string(), "redis".to_string()]));
+
+        let packages = std::sync::Arc::new(std::sync::Mutex::new(vec![
+            PackageProgress::new("wget".to_string()),
+            PackageProgress::new("redis".to_string()),
+        ]));
+        let watcher = watch_packages(&packages, events.sink());
+        for (index, state) in [(0, ProgressState::Completed), (1, ProgressState::Failed)] {
+            packages.lock().unwrap()[index].begin();
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            let mut tracked = packages.lock().unwrap();
+            tracked[index].finish();
+            tracked[index].state = state;
+        }
+        drop(watcher);
+
+        let report = events.finish();
+        assert_eq!(report.sent, 5);
+        assert!(report.errors.is_empty());
+
+        let bodies = server.join().unwrap();
+        let names: Vec<String> = bodies
+            .iter()
+            .map(|b| {
+                let package = b["package"]["name"].as_str().unwrap_or("-");
+                format!("{} {}", b["event"].as_str().unwrap(), package)
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "run.started -",
+                "package.started wget",
+                "package.completed wget",
+                "package.started redis",
+                "package.failed redis"
+            ]
+        );
+        assert_eq!(bodies[0]["total"], 2);
+        assert!(bodies[0].get("finished_at").is_none());
+        assert_eq!(bodies[4]["package"]["status"], "failed");
+    }
+
+    /// A local URL nothing listens on.
+    fn closed_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/events", listener.local_addr().unwrap())
+    }
+
+    fn outbox_entry(url: &str, run_id: &str) -> OutboxEntry {
+        let failure = DeliveryFailure {
+            message: "connection refused".to_string(),
+            attempts: 4,
+            retryable: true,
+        };
+        let body = format!(r#"{{"event":"run.finished","run_id":"{}"}}"#, run_id);
+        OutboxEntry::new(url, "run.finished", run_id, body.as_bytes(), &failure)
+    }
+
+    #[test]
+    fn test_failed_webhooks_are_queued() {
+        let dir = std::env::temp_dir().join(format!("brim-outbox-queue-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let endpoint = Endpoint {
+            target: WebhookTarget {
+                url: closed_url(),
+                ..Default::default()
+            },
+            events: vec!["*".to_string()],
+        };
+        let run = RunContext::new("20260101-000000-1", "install", "2026-01-01T00:00:00+00:00", &[]);
+        let events = EventDispatcher::with_outbox(vec![endpoint.clone()], run, Some(dir.clone()));
+        events.emit(WebhookPayload::run_started(events.run(), &["wget".to_string()]));
+        let report = events.finish();
+        assert_eq!((report.sent, report.queued), (0, 1));
+        assert!(report.errors[0].ends_with("(queued for retry)"));
+
+        let entries = load_entries(&dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].url, endpoint.target.url);
+        assert_eq!(entries[0].event, "run.started");
+        assert_eq!(entries[0].run_id, "20260101-000000-1");
+        assert_eq!(entries[0].attempts, 1);
+        let body: serde_json::Value = serde_json::from_str(&entries[0].body).unwrap();
+        assert_eq!(body["packages"][0]["name"], "wget");
+
+        // Without an outbox the failure is only reported.
+        let run = RunContext::new("20260101-000000-2", "install", "2026-01-01T00:00:00+00:00", &[]);
+        let events = EventDispatcher::with_outbox(vec![endpoint], run.clone(), None);
+        events.emit(WebhookPayload::ended(run, "failed", vec![], None, 0));
+        assert_eq!(events.finish().queued, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_flush_outbox() {
+        let dir = std::env::temp_dir().join(format!("brim-outbox-flush-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (url, server) = capture_bodies(1);
+
+        let mut expired = outbox_entry(&url, "old");
+        expired.queued_at = (chrono::Local::now() - chrono::Duration::days(8)).to_rfc3339();
+        save_entry(&dir, &expired).unwrap();
+        save_entry(&dir, &outbox_entry(&url, "recent")).unwrap();
+        assert!(!expired.expired(std::time::Duration::from_secs(9 * 24 * 60 * 60), chrono::Local::now()));
+
+        let options = FlushOptions {
+            ttl: std::time::Duration::from_secs(7 * 24 * 60 * 60),
+            retries: Some(0),
+            stop_on_failure: true,
+        };
+        let endpoint = |url: &str| Endpoint {
+            target: WebhookTarget {
+                url: url.to_string(),
+                ..Default::default()
+            },
+            events: vec!["*".to_string()],
+        };
+
+        // Entries for endpoints that are no longer configured are kept but not sent.
+        let report = flush_outbox(&dir, &[], &options).await;
+        assert_eq!((report.delivered, report.expired, report.unconfigured.len()), (0, 1, 1));
+        assert_eq!(load_entries(&dir).len(), 1);
+
+        let report = flush_outbox(&dir, &[endpoint(&url)], &options).await;
+        assert_eq!(report.delivered, 1);
+        assert!(report.rejected.is_empty() && report.remaining.is_empty() && report.unconfigured.is_empty());
+        assert!(load_entries(&dir).is_empty());
+        assert_eq!(server.join().unwrap()[0]["run_id"], "recent");
+
+        // Offline: the first failure leaves the rest untouched.
+        let offline = closed_url();
+        save_entry(&dir, &outbox_entry(&offline, "first")).unwrap();
+        save_entry(&dir, &outbox_entry(&offline, "second")).unwrap();
+        let report = flush_outbox(&dir, &[endpoint(&offline)], &options).await;
+        assert_eq!(report.delivered, 0);
+        let attempts: Vec<(String, u32)> = load_entries(&dir).into_iter().map(|e| (e.run_id, e.attempts)).collect();
+        assert_eq!(attempts, vec![("first".to_string(), 5), ("second".to_string(), 4)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let mut payload = partial_payload();
+        payload.packages[0].duration_seconds = Some(1.5);
+        payload.packages[1].attempts = Some(2);
+        payload.packages[1].error = Some("Error: \x1b[31mFailed\x1b[0m <redis> & co".to_string());
+        payload.packages.push(PackageResult {
+            name: "jq".to_string(),
+            status: "rolled_back".to_string(),
+            ..Default::default()
+        });
+        let xml = junit(&payload);
+
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        assert!(xml.contains(r#"<testsuite name="brim install" tests="3" failures="1" errors="0" skipped="1" time="240""#));
+        assert!(xml.contains(r#"<property name="run_id" value="20260101-120000-42"/>"#));
+        assert!(xml.contains(r#"<testcase name="wget" classname="brim.install" time="1.5"/>"#));
+        assert!(xml.contains(
+            r#"<failure message="network error: Error: [31mFailed[0m &lt;redis&gt; &amp; co" type="network_error">Attempts: 2"#
+        ));
+        assert!(xml.contains(r#"<skipped message="Rolled back after another package failed"/>"#));
+        assert!(!xml.contains('\x1b'));
+        assert!(xml.ends_with("</testsuite>\n</testsuites>\n"));
+    }
+
+    #[test]
+    fn test_html_report() {
+        let page = html(&partial_payload());
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<h1>brim install on dev-laptop: partial, 1 completed, 1 failed</h1>"));
+        assert!(page.contains("<span class=\"status failed\">failed</span>"));
+        assert!(page.contains("network error: Error: Failed to download &quot;redis&quot;"));
+        assert!(!page.contains("<script") && !page.contains("<link"));
+
+        assert_eq!(ReportFormat::for_path(Path::new("out/report.HTML")), ReportFormat::Html);
+        assert_eq!(ReportFormat::for_path(Path::new("junit.xml")), ReportFormat::Junit);
+        assert_eq!(ReportFormat::for_path(Path::new("report")), ReportFormat::Junit);
+
+        let dir = std::env::temp_dir().join(format!("brim-report-{}", std::process::id()));
+        let report = Report {
+            path: dir.join("nested").join("report.html"),
+            format: ReportFormat::Html,
+        };
+        report.write(&partial_payload()).unwrap();
+        assert_eq!(std::fs::read_to_string(&report.path).unwrap(), page);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_metrics_textfile() {
+        let mut state = MetricsState::default();
+        let mut payload = partial_payload();
+        payload.packages[0].duration_seconds = Some(12.5);
+        state.record_run(&payload);
+        state.record_run(&payload);
+
+        let mut sync = partial_payload();
+        sync.run.operation = "sync".to_string();
+        sync.status = "drift".to_string();
+        sync.packages[0].status = "missing".to_string();
+        sync.packages[1].status = "in_sync".to_string();
+        state.record_run(&sync);
+        state.record_fetch_error("https://example.com/\"team\".json");
+
+        let text = state.render();
+        assert!(text.contains("# TYPE brim_packages_total counter\n"));
+        assert!(text.contains("brim_packages_total{operation=\"install\",outcome=\"completed\"} 2\n"));
+        assert!(text.contains("brim_packages_total{operation=\"install\",outcome=\"failed\"} 2\n"));
+        assert!(!text.contains("operation=\"sync\",outcome"));
+        assert!(text.contains("brim_runs_total{operation=\"install\",status=\"partial\"} 2\n"));
+        assert!(text.contains("brim_runs_total{operation=\"sync\",status=\"drift\"} 1\n"));
+        assert!(text.contains("brim_last_run_timestamp_seconds{operation=\"install\"} "));
+        assert!(text.contains("brim_package_duration_seconds{package=\"wget\",operation=\"install\"} 12.5\n"));
+        assert!(text.contains("brim_drift_packages{kind=\"missing\"} 1\n"));
+        assert!(text.contains("brim_drift_packages{kind=\"extra\"} 0\n"));
+        assert!(text.contains("brim_fetch_errors_total{source=\"https://example.com/\\\"team\\\".json\"} 1\n"));
+
+        let dir = std::env::temp_dir().join(format!("brim-metrics-{}", std::process::id()));
+        let metrics = Metrics {
+            textfile: dir.join("textfile").join("brim.prom"),
+            state: dir.join("state").join("metrics.json"),
+        };
+        metrics.update(|m| m.record_fetch_error("a.json")).unwrap();
+        metrics.update(|m| m.record_fetch_error("a.json")).unwrap();
+        assert_eq!(metrics.load().fetch_errors["a.json"], 2);
+        let text = std::fs::read_to_string(&metrics.textfile).unwrap();
+        assert!(text.contains("brim_fetch_errors_total{source=\"a.json\"} 2\n"));
+        assert_eq!(std::fs::read_dir(dir.join("textfile")).unwrap().count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let sources = vec!["base.json".to_string(), "team.json".to_string()];
+        assert_eq!(crate::utilities::failed_source(&sources, "Failed to fetch from team.json: not found"), Some("team.json"));
+        assert_eq!(crate::utilities::failed_source(&sources, "No URLs provided"), None);
+    }
+
+    #[test]
+    fn test_structured_logging() {
+        use log::kv::Value;
+        use log::{Level, LevelFilter, Record};
+
+        assert_eq!(level_for(0, false), LevelFilter::Warn);
+        assert_eq!(level_for(1, false), LevelFilter::Info);
+        assert_eq!(level_for(2, false), LevelFilter::Debug);
+        assert_eq!(level_for(5, false), LevelFilter::Trace);
+        assert_eq!(level_for(0, true), LevelFilter::Error);
+        assert_eq!(LogFormat::parse("json"), Some(LogFormat::Json));
+        assert_eq!(LogFormat::parse("yaml"), None);
+
+        let fields: [(&str, Value); 4] = [
+            ("command", Value::from("brew install --cask firefox")),
+            ("code", Value::from(1i32)),
+            ("duration_ms", Value::from(1250u64)),
+            ("cask", Value::from(true)),
+        ];
+        let args = format_args!("Finished brew");
+        let record = Record::builder()
+            .args(args)
+            .level(Level::Debug)
+            .target("command")
+            .key_values(&fields)
+            .build();
+
+        assert_eq!(
+            format_record(&record, LogFormat::Text, Some("2026-10-19T09:30:00.000+02:00")),
+            "2026-10-19T09:30:00.000+02:00 DEBUG command: Finished brew \
+             command=\"brew install --cask firefox\" code=1 duration_ms=1250 cask=true"
+        );
+
+        let line = format_record(&record, LogFormat::Json, Some("2026-10-19T09:30:00.000+02:00"));
+        assert!(line.starts_with(r#"{"ts":"2026-10-19T09:30:00.000+02:00","level":"debug","target":"command","#));
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["ts"], "2026-10-19T09:30:00.000+02:00");
+        assert_eq!(json["level"], "debug");
+        assert_eq!(json["target"], "command");
+        assert_eq!(json["message"], "Finished brew");
+        assert_eq!(json["command"], "brew install --cask firefox");
+        assert_eq!(json["code"], 1);
+        assert_eq!(json["duration_ms"], 1250);
+        assert_eq!(json["cask"], true);
+
+        let mut command = std::process::Command::new("brew");
+        command.arg("fetch").arg("--cask").arg("firefox");
+        assert_eq!(command_line(&command), "brew fetch --cask firefox");
+    }
+
+    #[test]
+    fn test_package_list_scrolling() {
+        use crossterm::event::KeyCode;
+
+        let mut scroll = ListScroll::default();
+        assert_eq!(scroll.window(80, 10), 0..10);
+        assert!(scroll.handle_key(KeyCode::Down, 80));
+        assert_eq!(scroll.window(80, 10), 0..10);
+
+        // The list only scrolls once the selection leaves the screen.
+        assert!(scroll.handle_key(KeyCode::PageDown, 80));
+        assert_eq!(scroll.selected, 11);
+        assert_eq!(scroll.window(80, 10), 2..12);
+        assert!(scroll.handle_key(KeyCode::Up, 80));
+        assert_eq!(scroll.window(80, 10), 2..12);
+
+        assert!(scroll.handle_key(KeyCode::End, 80));
+        assert_eq!(scroll.selected, 79);
+        assert_eq!(scroll.window(80, 10), 70..80);
+        assert!(scroll.handle_key(KeyCode::Down, 80));
+        assert_eq!(scroll.selected, 79);
+        assert!(scroll.handle_key(KeyCode::PageUp, 80));
+        assert_eq!(scroll.window(80, 10), 69..79);
+        assert!(scroll.handle_key(KeyCode::Home, 80));
+        assert_eq!(scroll.window(80, 10), 0..10);
+        assert!(!scroll.handle_key(KeyCode::Char('x'), 80));
+
+        // A shorter list, such as the failed-only summary, clamps the selection.
+        scroll.selected = 50;
+        assert_eq!(scroll.window(3, 10), 0..3);
+        assert_eq!(scroll.selected, 2);
+        assert_eq!(scroll.window(0, 10), 0..0);
+
+        // Following keeps the package before the active one in view.
+        assert_eq!(scroll.follow(40, 80, 10), 39..49);
+        assert_eq!(scroll.follow(0, 80, 10), 0..10);
+        assert_eq!(scroll.follow(79, 80, 10), 70..80);
+
+        let mut packages: Vec<PackageProgress> =
+            ["a", "b", "c", "d"].iter().map(|n| PackageProgress::new(n.to_string())).collect();
+        assert_eq!(active_index(&packages), 0);
+        packages[0].state = ProgressState::Failed;
+        packages[1].state = ProgressState::Completed;
+        assert_eq!(active_index(&packages), 2);
+        packages[3].state = ProgressState::Installing;
+        assert_eq!(active_index(&packages), 3);
+        packages[2].state = ProgressState::Failed;
+        packages[3].state = ProgressState::Completed;
+        assert_eq!(active_index(&packages), 3);
+
+        assert_eq!(summary_indices(&packages, false), vec![0, 1, 2, 3]);
+        assert_eq!(summary_indices(&packages, true), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_atomic_rollback() {
+        let package = |name: &str, installed: bool| PackageProgress {
+            installed,
+            ..PackageProgress::new(name.to_string())
+        };
+        let mut tracked = vec![
+            package("wget", true),
+            package("jq", true),
+            package("redis", false),
+            package("tree", true),
+        ];
+        tracked[3].failure = Some(FailureReason::HookFailed);
+        tracked[3].state = ProgressState::Failed;
+        tracked[3].message = "post-install hook failed".to_string();
+
+        // `jq` was installed before the run, `redis` never got installed.
+        let preinstalled = vec!["jq".to_string()];
+        assert_eq!(rollback_selection(&tracked, &preinstalled), vec![0, 3]);
+
+        tracked[0].state = ProgressState::RollingBack;
+        assert_eq!(tracked[0].state_label(), "rolling_back");
+        assert_eq!(active_index(&tracked), 0);
+        finish_rollback(&mut tracked[0], Ok(()));
+        assert_eq!(tracked[0].state, ProgressState::RolledBack);
+        assert!(!tracked[0].installed);
+        assert_eq!(PackageResult::from_progress(&tracked[0]).status, "rolled_back");
+
+        // A failed package keeps its reason once it is rolled back.
+        finish_rollback(&mut tracked[3], Ok(()));
+        assert_eq!(tracked[3].state, ProgressState::Failed);
+        assert_eq!(tracked[3].failure, Some(FailureReason::HookFailed));
+        assert_eq!(tracked[3].message, "post-install hook failed (rolled back)");
+
+        finish_rollback(&mut tracked[1], Err("Rollback failed".to_string()));
+        assert_eq!(tracked[1].state, ProgressState::Failed);
+        assert_eq!(tracked[1].failure, Some(FailureReason::Unknown));
+        assert!(tracked[1].installed);
+        assert_eq!(rollback_selection(&tracked, &[]), vec![1]);
+    }
+
+    #[test]
+    fn test_cancelled_hook_stops_background_children() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        let package = BrewPackage {
+            post_install: Some("sleep 30 & sleep 30".to_string()),
+            ..BrewPackage::named("wget")
+        };
+        let tracker_packages = Arc::new(Mutex::new(vec![PackageProgress::new("wget".to_string())]));
+        let log = Arc::new(crate::utilities::brew_package_log::PackageLog::open("wget", None));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let cancel = Arc::clone(&cancelled);
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            cancel.store(true, Ordering::Relaxed);
+        });
+
+        let started = std::time::Instant::now();
+        let result = run_hook(0, HookKind::PostInstall, &package, &tracker_packages, &log, &cancelled);
+        assert_eq!(result, Err("post-install hook failed: cancelled".to_string()));
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    }
 }
@@ -0,0 +1,53 @@
+use crossterm::event::KeyCode;
+use std::ops::Range;
+
+/// Selection and scroll position of a list that can be longer than the screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ListScroll {
+    pub selected: usize,
+    /// Index of the first visible item.
+    pub offset: usize,
+    /// Items that fit on screen at the last draw, used as the PgUp/PgDn step.
+    pub page: usize,
+}
+
+impl ListScroll {
+    /// Moves the selection for arrow, PgUp/PgDn, Home and End keys.
+    /// Returns `false` for any other key, leaving the selection alone.
+    pub fn handle_key(&mut self, code: KeyCode, len: usize) -> bool {
+        let last = len.saturating_sub(1);
+        let page = self.page.max(1);
+        self.selected = match code {
+            KeyCode::Up => self.selected.saturating_sub(1),
+            KeyCode::Down => (self.selected + 1).min(last),
+            KeyCode::PageUp => self.selected.saturating_sub(page),
+            KeyCode::PageDown => (self.selected + page).min(last),
+            KeyCode::Home => 0,
+            KeyCode::End => last,
+            _ => return false,
+        };
+        true
+    }
+
+    /// Clamps the selection to `len` items and scrolls just enough to keep it
+    /// among the `rows` visible ones. Returns the visible index range.
+    pub fn window(&mut self, len: usize, rows: usize) -> Range<usize> {
+        let rows = rows.max(1);
+        self.page = rows;
+        self.selected = self.selected.min(len.saturating_sub(1));
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + rows {
+            self.offset = self.selected + 1 - rows;
+        }
+        self.offset = self.offset.min(len.saturating_sub(rows));
+        self.offset..(self.offset + rows).min(len)
+    }
+
+    /// Selects `index` and scrolls so the item before it stays in view for context.
+    pub fn follow(&mut self, index: usize, len: usize, rows: usize) -> Range<usize> {
+        self.selected = index;
+        self.offset = index.saturating_sub(1);
+        self.window(len, rows)
+    }
+}
@@ -1,3 +1,4 @@
+pub mod list;
 pub mod progress;
 
 pub use progress::{ProgressTracker, ProgressState};
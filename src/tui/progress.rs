@@ -8,23 +8,32 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Gauge, Paragraph},
+    widgets::{Block, Borders, Gauge, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState},
     Frame, Terminal,
 };
+use super::list::ListScroll;
+use crate::logging;
+use crate::models::FailureReason;
 use std::{
+    fs,
     io::{self, Stdout},
+    path::PathBuf,
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+const LOG_PAGE_LINES: usize = 10;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ProgressState {
     Pending,
     Downloading,
     Installing,
     Removing,
+    RollingBack,
     Completed,
     Failed,
+    RolledBack,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +42,45 @@ pub struct PackageProgress {
     pub state: ProgressState,
     pub progress: u16, // 0-100
     pub message: String,
+    pub log_path: Option<PathBuf>,
+    pub failure: Option<FailureReason>,
+    pub hooks: Vec<HookProgress>,
+    /// Set once `brew install` succeeded for this package in the current run.
+    pub installed: bool,
+    /// When the first brew command for this package started.
+    pub started: Option<Instant>,
+    pub duration: Option<Duration>,
+    /// Runs of the install, upgrade or remove command.
+    pub attempts: u32,
+    /// Installed version, once known.
+    pub version: Option<String>,
+    /// Error excerpt from the package log when brew failed.
+    pub error: Option<String>,
+}
+
+/// A recipe hook shown as a sub-step of its package.
+#[derive(Debug, Clone)]
+pub struct HookProgress {
+    pub label: &'static str,
+    pub state: ProgressState,
+}
+
+impl HookProgress {
+    pub fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            state: ProgressState::Pending,
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self.state {
+            ProgressState::Completed => "✓",
+            ProgressState::Failed => "✗",
+            ProgressState::Pending => "○",
+            _ => "…",
+        }
+    }
 }
 
 impl PackageProgress {
@@ -42,6 +90,27 @@ impl PackageProgress {
             state: ProgressState::Pending,
             progress: 0,
             message: String::new(),
+            log_path: None,
+            failure: None,
+            hooks: Vec::new(),
+            installed: false,
+            started: None,
+            duration: None,
+            attempts: 0,
+            version: None,
+            error: None,
+        }
+    }
+
+    /// Marks the start of a brew command; the first one starts the clock.
+    pub fn begin(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    /// Stops the clock at the package's outcome.
+    pub fn finish(&mut self) {
+        if let Some(started) = self.started {
+            self.duration = Some(started.elapsed());
         }
     }
 
@@ -51,28 +120,82 @@ impl PackageProgress {
             ProgressState::Downloading => Color::Yellow,
             ProgressState::Installing => Color::Blue,
             ProgressState::Removing => Color::Magenta,
+            ProgressState::RollingBack => Color::LightMagenta,
             ProgressState::Completed => Color::Green,
             ProgressState::Failed => Color::Red,
+            ProgressState::RolledBack => Color::DarkGray,
         }
     }
 
+    /// Package name followed by its hook sub-steps, e.g. `fzf · post-install ✓`.
+    pub fn title(&self) -> String {
+        let mut title = self.name.clone();
+        for hook in &self.hooks {
+            title.push_str(&format!(" · {} {}", hook.label, hook.icon()));
+        }
+        title
+    }
+
     pub fn state_label(&self) -> &str {
         match self.state {
             ProgressState::Pending => "pending",
             ProgressState::Downloading => "downloading",
             ProgressState::Installing => "installing",
             ProgressState::Removing => "removing",
+            ProgressState::RollingBack => "rolling_back",
             ProgressState::Completed => "completed",
             ProgressState::Failed => "failed",
+            ProgressState::RolledBack => "rolled_back",
         }
     }
 }
 
+/// The package the progress list follows: the first one brew is working on,
+/// else the next one waiting, else the last one.
+pub fn active_index(packages: &[PackageProgress]) -> usize {
+    packages
+        .iter()
+        .position(|p| {
+            matches!(
+                p.state,
+                ProgressState::Downloading | ProgressState::Installing | ProgressState::Removing | ProgressState::RollingBack
+            )
+        })
+        .or_else(|| packages.iter().position(|p| p.state == ProgressState::Pending))
+        .unwrap_or(packages.len().saturating_sub(1))
+}
+
+/// Indices of the packages the summary lists, optionally only the failed ones.
+pub fn summary_indices(packages: &[PackageProgress], failed_only: bool) -> Vec<usize> {
+    packages
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| !failed_only || p.state == ProgressState::Failed)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// A package log opened from the summary screen.
+struct LogView {
+    title: String,
+    lines: Vec<String>,
+    /// First visible line.
+    scroll: usize,
+}
+
 pub struct ProgressTracker {
     terminal: Terminal<CrosstermBackend<Stdout>>,
     packages: Arc<Mutex<Vec<PackageProgress>>>,
     total_packages: usize,
     show_summary: bool,
+    /// Position in the progress list while packages run.
+    list: ListScroll,
+    /// Keep the progress list on the active package; moving the selection turns it off.
+    follow_active: bool,
+    /// Position in the summary list, counted among the packages it shows.
+    summary: ListScroll,
+    failed_only: bool,
+    log_view: Option<LogView>,
 }
 
 impl ProgressTracker {
@@ -90,11 +213,18 @@ impl ProgressTracker {
 
         let total_packages = packages.len();
 
+        log::debug!(target: "tui", packages = total_packages; "Entered progress view");
+        logging::hold_terminal();
         Ok(Self {
             terminal,
             packages: Arc::new(Mutex::new(packages)),
             total_packages,
             show_summary: false,
+            list: ListScroll::default(),
+            follow_active: true,
+            summary: ListScroll::default(),
+            failed_only: false,
+            log_view: None,
         })
     }
 
@@ -117,22 +247,39 @@ impl ProgressTracker {
         let packages = Arc::clone(&self.packages);
         let total_packages = self.total_packages;
         let show_summary = self.show_summary;
+        let follow_active = self.follow_active;
+        let failed_only = self.failed_only;
+        let mut list = self.list;
+        let mut summary = self.summary;
+        let log_view = &self.log_view;
         
         self.terminal.draw(|f| {
-            if show_summary {
-                Self::render_summary_static(f, &packages, total_packages);
+            if let (true, Some(view)) = (show_summary, log_view) {
+                Self::render_log_static(f, view);
+            } else if show_summary {
+                Self::render_summary_static(f, &packages, total_packages, &mut summary, failed_only);
             } else {
-                Self::render_ui_static(f, &packages, total_packages);
+                Self::render_ui_static(f, &packages, total_packages, &mut list, follow_active);
             }
         })?;
+        // Rendering scrolls the lists to the selection, so keep where they ended up.
+        self.list = list;
+        self.summary = summary;
         Ok(())
     }
 
-    fn render_summary_static(f: &mut Frame, packages_arc: &Arc<Mutex<Vec<PackageProgress>>>, total_packages: usize) {
+    fn render_summary_static(
+        f: &mut Frame,
+        packages_arc: &Arc<Mutex<Vec<PackageProgress>>>,
+        total_packages: usize,
+        scroll: &mut ListScroll,
+        failed_only: bool,
+    ) {
         let packages = packages_arc.lock().unwrap();
         
         let completed = packages.iter().filter(|p| p.state == ProgressState::Completed).count();
         let failed = packages.iter().filter(|p| p.state == ProgressState::Failed).count();
+        let rolled_back = packages.iter().filter(|p| p.state == ProgressState::RolledBack).count();
         
         // Main layout
         let chunks = Layout::default()
@@ -155,7 +302,7 @@ impl ProgressTracker {
         f.render_widget(title, chunks[0]);
 
         // Stats
-        let stats_text = vec![
+        let mut stats_text = vec![
             Line::from(vec![
                 Span::styled("Total: ", Style::default().fg(Color::White)),
                 Span::styled(format!("{}", total_packages), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
@@ -169,15 +316,30 @@ impl ProgressTracker {
                 Span::styled(format!("{}", failed), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
             ]),
         ];
+        if rolled_back > 0 {
+            stats_text[2].spans.extend([
+                Span::styled("   Rolled back: ", Style::default().fg(Color::White)),
+                Span::styled(format!("{}", rolled_back), Style::default().fg(Color::LightMagenta).add_modifier(Modifier::BOLD)),
+            ]);
+        }
+        let stats_title = if failed_only { "Results (showing failed only)" } else { "Results" };
         let stats = Paragraph::new(stats_text)
-            .block(Block::default().title("Results").borders(Borders::ALL));
+            .block(Block::default().title(stats_title).borders(Borders::ALL));
         f.render_widget(stats, chunks[1]);
 
         // Package list
-        let available_height = chunks[2].height.saturating_sub(2);
-        let packages_per_screen = (available_height / 2).max(1) as usize;
-        
-        let visible_packages: Vec<_> = packages.iter().take(packages_per_screen).collect();
+        let indices = summary_indices(&packages, failed_only);
+        let packages_per_screen = (chunks[2].height / 2).max(1) as usize;
+        let visible = scroll.window(indices.len(), packages_per_screen);
+        let start_idx = visible.start;
+        let list_area = Self::render_scrollbar_static(f, chunks[2], indices.len(), packages_per_screen, start_idx);
+
+        if indices.is_empty() {
+            let empty = Paragraph::new(Line::from(Span::styled(" No failed packages", Style::default().fg(Color::Green))));
+            f.render_widget(empty, list_area);
+        }
+
+        let visible_packages: Vec<_> = indices[visible].iter().map(|&i| &packages[i]).collect();
         
         let package_chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -187,20 +349,34 @@ impl ProgressTracker {
                     .map(|_| Constraint::Length(2))
                     .collect::<Vec<_>>(),
             )
-            .split(chunks[2]);
+            .split(list_area);
 
         for (i, package) in visible_packages.iter().enumerate() {
             if i < package_chunks.len() {
                 let status_icon = match package.state {
                     ProgressState::Completed => "✓",
                     ProgressState::Failed => "✗",
+                    ProgressState::RolledBack => "↺",
                     _ => "•",
                 };
                 
-                let line = Line::from(vec![
+                let name_style = if start_idx + i == scroll.selected {
+                    Style::default().fg(Color::Black).bg(Color::White)
+                } else {
+                    Style::default().fg(Color::White)
+                };
+
+                let mut spans = vec![
                     Span::styled(format!(" {} ", status_icon), Style::default().fg(package.state_color()).add_modifier(Modifier::BOLD)),
-                    Span::styled(&package.name, Style::default().fg(Color::White)),
-                ]);
+                    Span::styled(package.title(), name_style),
+                ];
+                if let Some(reason) = package.failure {
+                    spans.push(Span::styled(format!("  [{}]", reason.label()), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+                }
+                if package.state == ProgressState::Failed && !package.message.is_empty() {
+                    spans.push(Span::styled(format!("  {}", package.message), Style::default().fg(Color::Red)));
+                }
+                let line = Line::from(spans);
                 
                 let para = Paragraph::new(line)
                     .block(Block::default().borders(Borders::BOTTOM));
@@ -210,7 +386,12 @@ impl ProgressTracker {
 
         // Footer
         let footer = Paragraph::new(Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::Gray)),
+            Span::styled("↑/↓ PgUp/PgDn", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled(" select, ", Style::default().fg(Color::Gray)),
+            Span::styled("Enter", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled(" log, ", Style::default().fg(Color::Gray)),
+            Span::styled("f", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled(if failed_only { " show all, " } else { " failed only, " }, Style::default().fg(Color::Gray)),
             Span::styled("q", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
             Span::styled(" or ", Style::default().fg(Color::Gray)),
             Span::styled("ESC", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
@@ -220,7 +401,41 @@ impl ProgressTracker {
         f.render_widget(footer, chunks[3]);
     }
 
-    fn render_ui_static(f: &mut Frame, packages_arc: &Arc<Mutex<Vec<PackageProgress>>>, total_packages: usize) {
+    fn render_log_static(f: &mut Frame, view: &LogView) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(2)
+            .constraints([
+                Constraint::Min(10),   // Log
+                Constraint::Length(3), // Footer
+            ])
+            .split(f.area());
+
+        let lines: Vec<Line> = view.lines.iter().map(|l| Line::from(l.as_str())).collect();
+        let log = Paragraph::new(lines)
+            .block(Block::default().title(view.title.clone()).borders(Borders::ALL))
+            .scroll((u16::try_from(view.scroll).unwrap_or(u16::MAX), 0));
+        f.render_widget(log, chunks[0]);
+
+        let footer = Paragraph::new(Line::from(vec![
+            Span::styled("↑/↓ PgUp/PgDn", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled(" scroll, ", Style::default().fg(Color::Gray)),
+            Span::styled("q", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled(" or ", Style::default().fg(Color::Gray)),
+            Span::styled("ESC", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+            Span::styled(" to go back", Style::default().fg(Color::Gray)),
+        ]))
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(footer, chunks[1]);
+    }
+
+    fn render_ui_static(
+        f: &mut Frame,
+        packages_arc: &Arc<Mutex<Vec<PackageProgress>>>,
+        total_packages: usize,
+        scroll: &mut ListScroll,
+        follow_active: bool,
+    ) {
         let packages = packages_arc.lock().unwrap();
         let completed = packages
             .iter()
@@ -263,39 +478,41 @@ impl ProgressTracker {
         f.render_widget(overall_gauge, chunks[1]);
 
         // Package list
-        Self::render_package_list_static(f, chunks[2], &packages);
+        Self::render_package_list_static(f, chunks[2], &packages, scroll, follow_active);
 
         // Footer
         let footer = Paragraph::new(Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::Gray)),
+            Span::styled("↑/↓ PgUp/PgDn", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled(" scroll, ", Style::default().fg(Color::Gray)),
+            Span::styled("f", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            Span::styled(if follow_active { " unfollow, " } else { " follow, " }, Style::default().fg(Color::Gray)),
             Span::styled("q", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-            Span::styled(" to quit (after completion) or ", Style::default().fg(Color::Gray)),
+            Span::styled(" quit when done, ", Style::default().fg(Color::Gray)),
             Span::styled("ESC", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
-            Span::styled(" to force quit", Style::default().fg(Color::Gray)),
+            Span::styled(" force quit", Style::default().fg(Color::Gray)),
         ]))
         .block(Block::default().borders(Borders::ALL));
         f.render_widget(footer, chunks[3]);
     }
 
-    fn render_package_list_static(f: &mut Frame, area: Rect, packages: &[PackageProgress]) {
+    fn render_package_list_static(
+        f: &mut Frame,
+        area: Rect,
+        packages: &[PackageProgress],
+        scroll: &mut ListScroll,
+        follow_active: bool,
+    ) {
         // Calculate how many packages we can show
-        let available_height = area.height.saturating_sub(2); 
-        let packages_per_screen = (available_height / 3).max(1) as usize;
-
-        // Find the first active (non-completed) package
-        let first_active = packages
-            .iter()
-            .position(|p| p.state != ProgressState::Completed)
-            .unwrap_or(0);
+        let packages_per_screen = (area.height / 3).max(1) as usize;
 
-
-        // Show packages starting from the first active one
-        let start_idx = first_active.saturating_sub(1).min(packages.len().saturating_sub(packages_per_screen));
-        let visible_packages: Vec<_> = packages
-            .iter()
-            .skip(start_idx)
-            .take(packages_per_screen)
-            .collect();
+        let visible = if follow_active {
+            scroll.follow(active_index(packages), packages.len(), packages_per_screen)
+        } else {
+            scroll.window(packages.len(), packages_per_screen)
+        };
+        let start_idx = visible.start;
+        let area = Self::render_scrollbar_static(f, area, packages.len(), packages_per_screen, start_idx);
+        let visible_packages = &packages[visible];
 
         let package_chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -309,16 +526,44 @@ impl ProgressTracker {
 
         for (i, package) in visible_packages.iter().enumerate() {
             if i < package_chunks.len() {
-                Self::render_package_static(f, package_chunks[i], package);
+                // The followed package is always the active one, so only a chosen selection is marked.
+                let selected = !follow_active && start_idx + i == scroll.selected;
+                Self::render_package_static(f, package_chunks[i], package, selected);
             }
         }
     }
 
-    fn render_package_static(f: &mut Frame, area: Rect, package: &PackageProgress) {
+    /// Draws a scrollbar along the right edge of `area` when `len` items don't fit
+    /// in `rows`, and returns the area left for the items.
+    fn render_scrollbar_static(f: &mut Frame, area: Rect, len: usize, rows: usize, offset: usize) -> Rect {
+        if len <= rows {
+            return area;
+        }
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(area);
+        // The scrollbar counts scroll positions, so the last one shows the final page.
+        let mut state = ScrollbarState::new(len - rows + 1).position(offset);
+        let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+            .begin_symbol(None)
+            .end_symbol(None)
+            .style(Style::default().fg(Color::DarkGray));
+        f.render_stateful_widget(scrollbar, columns[1], &mut state);
+        columns[0]
+    }
+
+    fn render_package_static(f: &mut Frame, area: Rect, package: &PackageProgress, selected: bool) {
+        let title_style = if selected {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
         let gauge = Gauge::default()
             .block(
                 Block::default()
-                    .title(package.name.clone())
+                    .title(package.title())
+                    .title_style(title_style)
                     .borders(Borders::ALL)
                     .border_style(Style::default().fg(package.state_color())),
             )
@@ -365,7 +610,7 @@ impl ProgressTracker {
                         KeyCode::Char('q') => {
                             if let Ok(packages) = self.packages.lock() {
                                 let all_done = packages.iter().all(|p| {
-                                    p.state == ProgressState::Completed || p.state == ProgressState::Failed
+                                    matches!(p.state, ProgressState::Completed | ProgressState::Failed | ProgressState::RolledBack)
                                 });
                                 if all_done {
                                     user_cancelled = false;
@@ -377,7 +622,7 @@ impl ProgressTracker {
                             user_cancelled = true;
                             break;
                         }
-                        _ => {}
+                        code => self.handle_progress_key(code),
                     }
                 }
             }
@@ -390,11 +635,8 @@ impl ProgressTracker {
                     loop {
                         if event::poll(Duration::from_millis(100))? {
                             if let Event::Key(key) = event::read()? {
-                                match key.code {
-                                    KeyCode::Char('q') | KeyCode::Esc => {
-                                        break;
-                                    }
-                                    _ => {}
+                                if self.handle_summary_key(key.code) {
+                                    break;
                                 }
                             }
                         }
@@ -409,11 +651,84 @@ impl ProgressTracker {
 
         Ok(!user_cancelled)
     }
+
+    /// Scrolls the progress list or toggles following the active package.
+    fn handle_progress_key(&mut self, code: KeyCode) {
+        if code == KeyCode::Char('f') {
+            self.follow_active = !self.follow_active;
+            return;
+        }
+        let len = self.packages.lock().map(|p| p.len()).unwrap_or(0);
+        if self.list.handle_key(code, len) {
+            self.follow_active = false;
+        }
+    }
+
+    /// Handles a key press on the summary screen; returns `true` to exit.
+    fn handle_summary_key(&mut self, code: KeyCode) -> bool {
+        if let Some(view) = self.log_view.as_mut() {
+            let last_line = view.lines.len().saturating_sub(1);
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => self.log_view = None,
+                KeyCode::Up => view.scroll = view.scroll.saturating_sub(1),
+                KeyCode::Down => view.scroll = view.scroll.saturating_add(1).min(last_line),
+                KeyCode::PageUp => view.scroll = view.scroll.saturating_sub(LOG_PAGE_LINES),
+                KeyCode::PageDown => view.scroll = view.scroll.saturating_add(LOG_PAGE_LINES).min(last_line),
+                KeyCode::Home => view.scroll = 0,
+                KeyCode::End => view.scroll = last_line,
+                _ => {}
+            }
+            return false;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Enter | KeyCode::Char('l') => self.open_log(),
+            KeyCode::Char('f') => {
+                self.failed_only = !self.failed_only;
+                self.summary = ListScroll::default();
+            }
+            code => {
+                let len = self.summary_indices().len();
+                self.summary.handle_key(code, len);
+            }
+        }
+        false
+    }
+
+    fn summary_indices(&self) -> Vec<usize> {
+        self.packages
+            .lock()
+            .map(|p| summary_indices(&p, self.failed_only))
+            .unwrap_or_default()
+    }
+
+    fn open_log(&mut self) {
+        let index = self.summary_indices().get(self.summary.selected).copied();
+        let Some(package) = index.and_then(|i| self.packages.lock().ok().and_then(|p| p.get(i).cloned())) else {
+            return;
+        };
+
+        let (title, lines) = match package.log_path {
+            Some(ref path) => match fs::read_to_string(path) {
+                Ok(content) => (
+                    format!("{} ({})", package.name, path.display()),
+                    content.lines().map(str::to_string).collect(),
+                ),
+                Err(e) => (package.name.clone(), vec![format!("Could not read {}: {}", path.display(), e)]),
+            },
+            None => (package.name.clone(), vec!["No log was recorded for this package.".to_string()]),
+        };
+
+        self.log_view = Some(LogView { title, lines, scroll: 0 });
+    }
 }
 
 impl Drop for ProgressTracker {
     fn drop(&mut self) {
         let _ = disable_raw_mode();
         let _ = execute!(self.terminal.backend_mut(), LeaveAlternateScreen);
+        logging::release_terminal();
+        log::debug!(target: "tui", "Left progress view");
     }
 }
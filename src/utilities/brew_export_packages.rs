@@ -0,0 +1,159 @@
+use crate::constants::PROGRAM;
+use crate::logging;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+
+/// A package installed on request, as `brew info --json=v2 --installed` reports it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstalledPackage {
+    /// Tap-qualified for formulae outside homebrew/core.
+    pub name: String,
+    pub cask: bool,
+    pub version: Option<String>,
+    /// Whether the package comes from the official taps, and so has a formulae.brew.sh page.
+    pub core: bool,
+}
+
+/// One entry of an exported recipe, in recipe field order.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecipeEntry {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cask: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+pub struct ExportOptions {
+    pub with_versions: bool,
+    /// Package name to category.
+    pub categories: HashMap<String, String>,
+}
+
+/// Formulae installed on request plus every installed cask; dependencies are left out.
+pub fn list_requested_packages() -> Result<Vec<InstalledPackage>, String> {
+    let output = logging::output(
+        Command::new(PROGRAM)
+            .args(["info", "--json=v2", "--installed"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped()),
+    )
+        .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "brew info failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_installed_json(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn parse_installed_json(json: &str) -> Result<Vec<InstalledPackage>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|e| format!("JSON parse error: {}", e))?;
+    let text = |entry: &Value, key: &str| entry.get(key).and_then(Value::as_str).map(String::from);
+    let core = |entry: &Value| text(entry, "tap").is_none_or(|tap| tap == "homebrew/core" || tap == "homebrew/cask");
+
+    let mut packages: Vec<InstalledPackage> = vec![];
+
+    for entry in value.get("formulae").and_then(Value::as_array).into_iter().flatten() {
+        let Some(install) = entry.get("installed").and_then(Value::as_array).and_then(|i| i.last()) else {
+            continue;
+        };
+        if !install.get("installed_on_request").and_then(Value::as_bool).unwrap_or(false) {
+            continue;
+        }
+        let name = if core(entry) { text(entry, "name") } else { text(entry, "full_name") };
+        let Some(name) = name else {
+            continue;
+        };
+        packages.push(InstalledPackage {
+            name,
+            cask: false,
+            version: text(install, "version"),
+            core: core(entry),
+        });
+    }
+
+    for entry in value.get("casks").and_then(Value::as_array).into_iter().flatten() {
+        let token = if core(entry) { text(entry, "token") } else { text(entry, "full_token") };
+        let Some(name) = token else {
+            continue;
+        };
+        packages.push(InstalledPackage {
+            name,
+            cask: true,
+            version: text(entry, "installed"),
+            core: core(entry),
+        });
+    }
+
+    Ok(packages)
+}
+
+/// Package name to category, from a JSON object such as `{"wget": "Networking"}`.
+pub fn parse_category_map(json: &str) -> Result<HashMap<String, String>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|e| format!("JSON parse error: {}", e))?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| "category map must be a JSON object of package name to category".to_string())?;
+
+    object
+        .iter()
+        .map(|(name, category)| match category.as_str() {
+            Some(category) if !category.is_empty() => Ok((name.clone(), category.to_string())),
+            _ => Err(format!("category for '{}' must be a non-empty string", name)),
+        })
+        .collect()
+}
+
+/// Recipe entries for `packages`: formulae first, then casks, each sorted by name.
+pub fn build_recipe(packages: &[InstalledPackage], options: &ExportOptions) -> Vec<RecipeEntry> {
+    let mut sorted: Vec<&InstalledPackage> = packages.iter().collect();
+    sorted.sort_by(|a, b| (a.cask, &a.name).cmp(&(b.cask, &b.name)));
+
+    sorted
+        .into_iter()
+        .map(|package| RecipeEntry {
+            name: package.name.clone(),
+            category: options.categories.get(&package.name).cloned(),
+            url: package.core.then(|| {
+                let kind = if package.cask { "cask" } else { "formula" };
+                format!("https://formulae.brew.sh/{}/{}", kind, package.name)
+            }),
+            cask: package.cask.then_some(true),
+            version: package
+                .version
+                .as_deref()
+                .filter(|_| options.with_versions)
+                .and_then(recipe_version),
+        })
+        .collect()
+}
+
+/// The part of a Homebrew version a recipe pin can hold (`major.minor[.patch]`),
+/// e.g. `3.3.1` from `3.3.1_1`. `None` when the version has no minor part.
+pub fn recipe_version(version: &str) -> Option<String> {
+    let mut parts: Vec<&str> = vec![];
+
+    for part in version.split('.').take(3) {
+        let end = part.find(|c: char| !c.is_ascii_digit()).unwrap_or(part.len());
+        if end == 0 {
+            break;
+        }
+        parts.push(&part[..end]);
+        // A suffix such as `_1` or `p1` ends the version.
+        if end < part.len() {
+            break;
+        }
+    }
+
+    (parts.len() >= 2).then(|| parts.join("."))
+}
@@ -1,5 +1,7 @@
 use crate::models::BrewPackage;
+use crate::utilities::brew_recipe_validation::{validate_recipe, ValidationError};
 use std::path::Path;
+use std::time::Instant;
 
 #[derive(Debug)]
 pub enum FetchError {
@@ -7,6 +9,7 @@ pub enum FetchError {
     FileError(std::io::Error),
     ParseError(serde_json::Error),
     InvalidUrl(String),
+    InvalidRecipe(Vec<ValidationError>),
 }
 
 impl std::fmt::Display for FetchError {
@@ -16,6 +19,13 @@ impl std::fmt::Display for FetchError {
             FetchError::FileError(e) => write!(f, "File error: {}", e),
             FetchError::ParseError(e) => write!(f, "JSON parse error: {}", e),
             FetchError::InvalidUrl(s) => write!(f, "Invalid URL or file path: {}", s),
+            FetchError::InvalidRecipe(errors) => {
+                write!(f, "Invalid recipe ({} problem(s))", errors.len())?;
+                for error in errors {
+                    write!(f, "\n      {}", error)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -38,9 +48,49 @@ impl From<serde_json::Error> for FetchError {
     }
 }
 
+/// Fetches a recipe and validates it against the recipe schema before parsing.
 pub async fn fetch_packages(url: &String) -> Result<Vec<BrewPackage>, FetchError> {
+    let text = fetch_recipe_text(url).await?;
+
+    let errors = validate_recipe(url, &text);
+    if !errors.is_empty() {
+        return Err(FetchError::InvalidRecipe(errors));
+    }
+
+    let packages: Vec<BrewPackage> = serde_json::from_str(&text)?;
+    Ok(packages)
+}
+
+/// Raw contents of a local or remote recipe.
+pub async fn fetch_recipe_text(url: &String) -> Result<String, FetchError> {
+    let started = Instant::now();
+    log::debug!(target: "fetch", source = url.as_str(); "Fetching recipe");
+
+    let result = read_recipe_text(url).await;
+    let duration_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(ref text) => log::info!(
+            target: "fetch",
+            source = url.as_str(),
+            bytes = text.len(),
+            duration_ms = duration_ms;
+            "Fetched recipe"
+        ),
+        // Callers report the error itself; this only adds the timing.
+        Err(ref e) => log::info!(
+            target: "fetch",
+            source = url.as_str(),
+            error:% = e,
+            duration_ms = duration_ms;
+            "Failed to fetch recipe"
+        ),
+    }
+    result
+}
+
+async fn read_recipe_text(url: &String) -> Result<String, FetchError> {
     if is_local_path(url) {
-        return fetch_local_file(url);
+        return read_local_file(url);
     }
     
     if !url.starts_with("http://") && !url.starts_with("https://") {
@@ -51,6 +101,7 @@ pub async fn fetch_packages(url: &String) -> Result<Vec<BrewPackage>, FetchError
     
     let response = reqwest::get(url)
         .await?;
+    log::debug!(target: "fetch", source = url.as_str(), status = response.status().as_u16(); "Recipe server responded");
     
     if !response.status().is_success() {
         return Err(FetchError::InvalidUrl(
@@ -58,17 +109,14 @@ pub async fn fetch_packages(url: &String) -> Result<Vec<BrewPackage>, FetchError
         ));
     }
     
-    let text = response.text().await?;
-    let packages: Vec<BrewPackage> = serde_json::from_str(&text)?;
-    
-    Ok(packages)
+    Ok(response.text().await?)
 }
 
 fn is_local_path(path: &str) -> bool {
     Path::new(path).exists() || !path.starts_with("http://") && !path.starts_with("https://")
 }
 
-fn fetch_local_file(path: &str) -> Result<Vec<BrewPackage>, FetchError> {
+fn read_local_file(path: &str) -> Result<String, FetchError> {
     let file_path = Path::new(path);
     
     if !file_path.exists() {
@@ -80,58 +128,5 @@ fn fetch_local_file(path: &str) -> Result<Vec<BrewPackage>, FetchError> {
         ));
     }
     
-    let content = std::fs::read_to_string(file_path)?;
-    let packages: Vec<BrewPackage> = serde_json::from_str(&content)?;
-    
-    validate_packages(&packages)?;
-    
-    Ok(packages)
-}
-
-fn validate_packages(packages: &[BrewPackage]) -> Result<(), FetchError> {
-    if packages.is_empty() {
-        return Err(FetchError::InvalidUrl(
-            "Recipe file contains no packages".to_string()
-        ));
-    }
-    
-    for (index, package) in packages.iter().enumerate() {
-        if package.name.trim().is_empty() {
-            return Err(FetchError::InvalidUrl(
-                format!("Package at index {} has empty name", index)
-            ));
-        }
-        
-        if !package.name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.') {
-            return Err(FetchError::InvalidUrl(
-                format!("Package '{}' has invalid name format. Use only alphanumeric characters, dots, hyphens, and underscores.", package.name)
-            ));
-        }
-        
-        if let Some(ref url) = package.url {
-            if !url.trim().is_empty() && !url.starts_with("http://") && !url.starts_with("https://") {
-                return Err(FetchError::InvalidUrl(
-                    format!("Package '{}' has invalid URL: must start with http:// or https://", package.name)
-                ));
-            }
-        }
-        
-        if let Some(ref version) = package.version {
-            if !version.trim().is_empty() && !is_valid_version(version) {
-                return Err(FetchError::InvalidUrl(
-                    format!("Package '{}' has invalid version format: '{}'. Use semantic versioning (e.g., 1.0.0)", package.name, version)
-                ));
-            }
-        }
-    }
-    
-    Ok(())
-}
-
-fn is_valid_version(version: &str) -> bool {
-    let parts: Vec<&str> = version.split('.').collect();
-    if parts.len() < 2 || parts.len() > 3 {
-        return false;
-    }
-    parts.iter().all(|part| part.parse::<u32>().is_ok())
+    Ok(std::fs::read_to_string(file_path)?)
 }
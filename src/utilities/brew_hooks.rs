@@ -0,0 +1,258 @@
+use crate::models::BrewPackage;
+use crate::tui::progress::{HookProgress, PackageProgress};
+use crate::tui::ProgressState;
+use crate::utilities::brew_package_log::{capture_output, PackageLog};
+use console::style;
+use dialoguer::Confirm;
+use crate::logging;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const HOOK_SHELL: &str = "sh";
+const HOOK_TIMEOUT_TICKS: u32 = 6000; // 10 minutes at 100ms per tick
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookKind {
+    PreInstall,
+    PostInstall,
+}
+
+impl HookKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HookKind::PreInstall => "pre-install",
+            HookKind::PostInstall => "post-install",
+        }
+    }
+
+    pub fn command<'a>(&self, package: &'a BrewPackage) -> Option<&'a str> {
+        match self {
+            HookKind::PreInstall => package.pre_install.as_deref(),
+            HookKind::PostInstall => package.post_install.as_deref(),
+        }
+    }
+}
+
+/// Which recipe hooks brim is allowed to run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookPolicy {
+    /// Run hooks from local recipes, ask before running hooks from remote ones.
+    Prompt,
+    /// Run every hook without asking.
+    Trust,
+    /// Run hooks from local recipes only.
+    Local,
+    /// Never run hooks.
+    Skip,
+}
+
+impl HookPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "prompt" => Some(HookPolicy::Prompt),
+            "trust" => Some(HookPolicy::Trust),
+            "local" => Some(HookPolicy::Local),
+            "skip" => Some(HookPolicy::Skip),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HookPolicy::Prompt => "prompt",
+            HookPolicy::Trust => "trust",
+            HookPolicy::Local => "local",
+            HookPolicy::Skip => "skip",
+        }
+    }
+}
+
+/// Strips the hooks `policy` does not allow from `packages`.
+///
+/// With `HookPolicy::Prompt`, hooks from remote recipes are listed and kept
+/// only if the user confirms.
+pub fn apply_hook_policy(packages: &mut [BrewPackage], policy: HookPolicy) {
+    let keep_remote = match policy {
+        HookPolicy::Trust => true,
+        HookPolicy::Local => false,
+        HookPolicy::Skip => {
+            strip_hooks(packages, |_| true);
+            return;
+        }
+        HookPolicy::Prompt => {
+            let remote: Vec<&BrewPackage> = packages
+                .iter()
+                .filter(|p| p.has_hooks() && p.is_from_remote_source())
+                .collect();
+            if remote.is_empty() {
+                return;
+            }
+
+            println!("\n{}", style("⚠ Remote recipes define install hooks:").yellow().bold());
+            for package in &remote {
+                print_package_hooks(package);
+            }
+
+            Confirm::new()
+                .with_prompt("Run these hooks?")
+                .default(false)
+                .interact()
+                .unwrap_or(false)
+        }
+    };
+
+    if !keep_remote {
+        strip_hooks(packages, |p| p.is_from_remote_source());
+    }
+}
+
+fn strip_hooks(packages: &mut [BrewPackage], filter: impl Fn(&BrewPackage) -> bool) {
+    for package in packages.iter_mut().filter(|p| filter(p)) {
+        package.pre_install = None;
+        package.post_install = None;
+    }
+}
+
+pub fn print_package_hooks(package: &BrewPackage) {
+    let origin = package.source.as_deref().unwrap_or("unknown source");
+    println!("  {} {}", style(&package.name).cyan(), style(format!("({})", origin)).dim());
+    for kind in [HookKind::PreInstall, HookKind::PostInstall] {
+        if let Some(command) = kind.command(package) {
+            println!("      {} {}", style(format!("{}:", kind.label())).dim(), command);
+        }
+    }
+}
+
+/// Adds a pending sub-step to each tracked package for every hook it has.
+pub fn attach_hook_steps(tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>, packages: &[BrewPackage]) {
+    if let Ok(mut tracked) = tracker_packages.lock() {
+        for (p, package) in tracked.iter_mut().zip(packages) {
+            for kind in [HookKind::PreInstall, HookKind::PostInstall] {
+                if kind.command(package).is_some() {
+                    p.hooks.push(HookProgress::new(kind.label()));
+                }
+            }
+        }
+    }
+}
+
+/// Runs one hook for the package at `index`, recording its output in `log`.
+///
+/// Returns an error message if the hook exits unsuccessfully, times out or is cancelled.
+pub fn run_hook(
+    index: usize,
+    kind: HookKind,
+    package: &BrewPackage,
+    tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>,
+    log: &Arc<PackageLog>,
+    cancelled: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let Some(script) = kind.command(package) else {
+        return Ok(());
+    };
+
+    set_hook_state(tracker_packages, index, kind, ProgressState::Installing, |p| {
+        p.message = format!("Running {} hook...", kind.label());
+    });
+
+    let mut command = Command::new(HOOK_SHELL);
+    command
+        .arg("-c")
+        .arg(script)
+        .env("BRIM_PACKAGE", &package.name)
+        // Its own process group, so a timeout or cancel also stops what the hook started.
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    log.note(&format!("{} hook", kind.label()));
+    log.command(&command);
+
+    let result = match command.spawn() {
+        Ok(mut child) => {
+            let (stdout_thread, stderr_thread) = capture_output(&mut child, log);
+
+            let mut wait_count = 0;
+            let mut killed = false;
+            let result = loop {
+                if cancelled.load(Ordering::Relaxed) {
+                    kill_process_group(&mut child);
+                    killed = true;
+                    break Err("cancelled".to_string());
+                }
+
+                match child.try_wait() {
+                    Ok(Some(status)) => {
+                        log.exited(Some(&status));
+                        if status.success() {
+                            break Ok(());
+                        }
+                        break Err(format!("exited with {}", status));
+                    }
+                    Ok(None) => {
+                        wait_count += 1;
+                        if wait_count > HOOK_TIMEOUT_TICKS {
+                            kill_process_group(&mut child);
+                            killed = true;
+                            break Err("timed out after 10 minutes".to_string());
+                        }
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => break Err(e.to_string()),
+                }
+            };
+
+            // After a kill, a process that left the group could still hold the
+            // pipes open, so the readers are left to finish on their own.
+            if !killed {
+                let _ = stdout_thread.join();
+                let _ = stderr_thread.join();
+            }
+            // Only logs when the hook was killed; an exit was recorded above.
+            log.exited(None);
+            result
+        }
+        Err(e) => Err(format!("could not start {}: {}", HOOK_SHELL, e)),
+    };
+
+    let state = if result.is_ok() {
+        ProgressState::Completed
+    } else {
+        ProgressState::Failed
+    };
+    set_hook_state(tracker_packages, index, kind, state, |_| {});
+
+    result.map_err(|e| {
+        log.note(&format!("{} hook failed: {}", kind.label(), e));
+        format!("{} hook failed: {}", kind.label(), e)
+    })
+}
+
+/// Kills the hook and every process in its group.
+fn kill_process_group(child: &mut Child) {
+    let _ = logging::output(Command::new("kill").arg("-KILL").arg(format!("-{}", child.id())));
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+fn set_hook_state(
+    tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>,
+    index: usize,
+    kind: HookKind,
+    state: ProgressState,
+    update: impl FnOnce(&mut PackageProgress),
+) {
+    if let Ok(mut tracked) = tracker_packages.lock() {
+        if let Some(p) = tracked.get_mut(index) {
+            if let Some(step) = p.hooks.iter_mut().find(|h| h.label == kind.label()) {
+                step.state = state;
+            }
+            update(p);
+        }
+    }
+}
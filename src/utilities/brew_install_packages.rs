@@ -1,23 +1,79 @@
-use crate::constants::PROGRAM;
-use crate::models::BrewPackage;
+use crate::constants::{DEFAULT_FETCH_JOBS, PROGRAM};
+use crate::models::{BrewPackage, FailureReason};
+use crate::tui::progress::PackageProgress;
 use crate::tui::{ProgressState, ProgressTracker};
-use crate::webhook::PackageResult;
+use crate::utilities::brew_list_installed_packages::installed_version;
+use crate::utilities::brew_hooks::{attach_hook_steps, run_hook, HookKind};
+use crate::utilities::brew_package_log::{attach_log_files, capture_output, failure_message, LogStream, PackageLog};
+use crate::webhook::{watch_packages, EventSink, PackageResult};
+use std::collections::VecDeque;
 use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Duration;
 
-pub fn install_packages(packages: &[BrewPackage], parallel: bool) -> Vec<PackageResult> {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstallOperation {
+    Install,
+    Upgrade,
+}
+
+impl InstallOperation {
+    fn brew_command(&self) -> &'static str {
+        match self {
+            InstallOperation::Install => "install",
+            InstallOperation::Upgrade => "upgrade",
+        }
+    }
+
+    fn failure_summary(&self) -> &'static str {
+        match self {
+            InstallOperation::Install => "Installation failed",
+            InstallOperation::Upgrade => "Upgrade failed",
+        }
+    }
+}
+
+pub struct InstallOptions {
+    pub operation: InstallOperation,
+    pub parallel: bool,
+    /// Concurrent `brew fetch` workers in parallel mode.
+    pub jobs: usize,
+    /// Directory receiving one log file per package, if any.
+    pub log_dir: Option<PathBuf>,
+    /// Uninstall everything this run newly installed if any package fails.
+    pub atomic: bool,
+    /// Packages installed before the run; never rolled back.
+    pub preinstalled: Vec<String>,
+    /// Receives `package.*` events as packages start and finish.
+    pub events: Option<EventSink>,
+}
+
+/// What an interactive install or upgrade run did.
+pub struct InstallOutcome {
+    pub results: Vec<PackageResult>,
+    /// The user stopped the run before it finished.
+    pub cancelled: bool,
+    /// Packages an `--atomic` run uninstalled again.
+    pub rolled_back: usize,
+}
+
+pub fn install_packages(packages: &[BrewPackage], options: &InstallOptions) -> InstallOutcome {
     let package_names: Vec<String> = packages.iter().map(|p| p.name.clone()).collect();
 
 
     let mut tracker = match ProgressTracker::new(package_names) {
         Ok(t) => t,
         Err(e) => {
-            eprintln!("Failed to initialize TUI: {}", e);
-            return vec![];
+            log::error!(target: "tui", "Failed to initialize TUI: {}", e);
+            return InstallOutcome {
+                results: vec![],
+                cancelled: true,
+                rolled_back: 0,
+            };
         }
     };
 
@@ -25,8 +81,14 @@ pub fn install_packages(packages: &[BrewPackage], parallel: bool) -> Vec<Package
     let tracker_packages = tracker.get_packages();
     let tracker_packages_for_result = Arc::clone(&tracker_packages);
 
-    if parallel {
-        return parallel_download_sequential_install(packages_arc, tracker_packages, &mut tracker);
+    if let Some(ref dir) = options.log_dir {
+        attach_log_files(&tracker_packages, dir);
+    }
+    attach_hook_steps(&tracker_packages, packages);
+    let _package_events = options.events.clone().map(|sink| watch_packages(&tracker_packages, sink));
+
+    let (cancelled, rolled_back) = if options.parallel {
+        return parallel_download_sequential_install(packages_arc, tracker_packages, &mut tracker, options);
     } else if false {
         let cancelled = Arc::new(AtomicBool::new(false));
         
@@ -39,7 +101,7 @@ pub fn install_packages(packages: &[BrewPackage], parallel: bool) -> Vec<Package
             let cancelled = Arc::clone(&cancelled);
             
             thread::spawn(move || {
-                install_single_package(index, &package, &tracker_packages, &cancelled);
+                install_single_package(index, &package, &tracker_packages, &cancelled, InstallOperation::Install);
             })
         }).collect()
         };
@@ -57,6 +119,7 @@ pub fn install_packages(packages: &[BrewPackage], parallel: bool) -> Vec<Package
         for thread in install_threads {
             let _ = thread.join();
         }
+        (false, 0)
     } else {
         let cancelled = Arc::new(AtomicBool::new(false));
         
@@ -65,38 +128,88 @@ pub fn install_packages(packages: &[BrewPackage], parallel: bool) -> Vec<Package
             let tracker_packages = Arc::clone(&tracker_packages);
             let cancelled = Arc::clone(&cancelled);
             
+            let atomic = options.atomic;
+            let operation = options.operation;
+            
             thread::spawn(move || {
                 let packages = packages_arc.lock().unwrap();
                 
                 for (index, package) in packages.iter().enumerate() {
-                    if cancelled.load(Ordering::Relaxed) {
+                    if cancelled.load(Ordering::Relaxed) || (atomic && any_failed(&tracker_packages)) {
                         break;
                     }
-                    install_single_package(index, package, &tracker_packages, &cancelled);
+                    install_single_package(index, package, &tracker_packages, &cancelled, operation);
                 }
             })
         };
 
         let cancelled_clone = Arc::clone(&cancelled);
-        let install_completed = tracker.run_with_updates(|| install_thread.is_finished());
+        let install_completed = if options.atomic {
+            tracker.run_without_summary(|| install_thread.is_finished())
+        } else {
+            tracker.run_with_updates(|| install_thread.is_finished())
+        };
         
-        if install_completed.is_err() || !install_completed.unwrap_or(true) {
+        let user_cancelled = install_completed.is_err() || !install_completed.unwrap_or(true);
+        if user_cancelled {
             cancelled_clone.store(true, Ordering::Relaxed);
             thread::sleep(Duration::from_millis(200));
         }
         
         let _ = install_thread.join();
-    }
+
+        let rolled_back = if options.atomic {
+            roll_back_if_needed(&mut tracker, &tracker_packages, packages, &options.preinstalled, user_cancelled)
+        } else {
+            0
+        };
+        (user_cancelled, rolled_back)
+    };
     
-    collect_results(&tracker_packages_for_result)
+    InstallOutcome {
+        results: collect_results(&tracker_packages_for_result, packages),
+        cancelled,
+        rolled_back,
+    }
 }
 
-fn collect_results(tracker_packages: &Arc<Mutex<Vec<crate::tui::progress::PackageProgress>>>) -> Vec<PackageResult> {
-    if let Ok(packages) = tracker_packages.lock() {
-        packages.iter().map(|p| PackageResult {
-            name: p.name.clone(),
-            status: p.state_label().to_string(),
-        }).collect()
+/// Installs `packages` one at a time without the TUI, printing a line per
+/// package. Used by unattended runs such as `brim watch`. Setting `cancelled`
+/// stops the running `brew` and leaves the remaining packages pending.
+pub fn install_packages_headless(
+    packages: &[BrewPackage],
+    options: &InstallOptions,
+    cancelled: &Arc<AtomicBool>,
+) -> Vec<PackageResult> {
+    let tracker_packages: Arc<Mutex<Vec<PackageProgress>>> = Arc::new(Mutex::new(
+        packages.iter().map(|p| PackageProgress::new(p.name.clone())).collect(),
+    ));
+
+    if let Some(ref dir) = options.log_dir {
+        attach_log_files(&tracker_packages, dir);
+    }
+    attach_hook_steps(&tracker_packages, packages);
+    let _package_events = options.events.clone().map(|sink| watch_packages(&tracker_packages, sink));
+
+    for (index, package) in packages.iter().enumerate() {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        install_single_package(index, package, &tracker_packages, cancelled, options.operation);
+
+        if let Ok(tracked) = tracker_packages.lock() {
+            if let Some(p) = tracked.get(index) {
+                println!("  {} {} {}", p.state_label(), p.name, p.message);
+            }
+        }
+    }
+
+    collect_results(&tracker_packages, packages)
+}
+
+fn collect_results(tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>, packages: &[BrewPackage]) -> Vec<PackageResult> {
+    if let Ok(tracked) = tracker_packages.lock() {
+        PackageResult::for_packages(&tracked, packages)
     } else {
         vec![]
     }
@@ -104,111 +217,41 @@ fn collect_results(tracker_packages: &Arc<Mutex<Vec<crate::tui::progress::Packag
 
 fn parallel_download_sequential_install(
     packages_arc: Arc<Mutex<Vec<BrewPackage>>>,
-    tracker_packages: Arc<Mutex<Vec<crate::tui::progress::PackageProgress>>>,
+    tracker_packages: Arc<Mutex<Vec<PackageProgress>>>,
     tracker: &mut ProgressTracker,
-) -> Vec<PackageResult> {
+    options: &InstallOptions,
+) -> InstallOutcome {
     let packages = packages_arc.lock().unwrap().clone();
     let cancelled = Arc::new(AtomicBool::new(false));
     
-    let download_threads: Vec<_> = packages.iter().enumerate().map(|(index, package)| {
-        let package = package.clone();
+    if let Ok(mut tracked) = tracker_packages.lock() {
+        for p in tracked.iter_mut() {
+            p.message = "Queued".to_string();
+        }
+    }
+
+    let queue: Arc<Mutex<VecDeque<(usize, BrewPackage)>>> =
+        Arc::new(Mutex::new(packages.iter().cloned().enumerate().collect()));
+    let workers = options.jobs.clamp(1, packages.len().max(1));
+
+    let download_threads: Vec<_> = (0..workers).map(|_| {
+        let queue = Arc::clone(&queue);
         let tracker_packages = Arc::clone(&tracker_packages);
         let cancelled = Arc::clone(&cancelled);
-        
-        thread::spawn(move || {
-            if let Ok(mut tracked) = tracker_packages.lock() {
-                if let Some(p) = tracked.get_mut(index) {
-                    p.state = ProgressState::Downloading;
-                    p.progress = 0;
-                    p.message = "Fetching...".to_string();
-                }
-            }
-
-            let mut command = Command::new(PROGRAM);
-            command.arg("fetch");
-            
-            if package.cask.is_some() {
-                command.arg("--cask");
-            }
-            
-            command
-                .arg(&package.name)
-                .stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
-
-            let mut child = match command.spawn() {
-                Ok(c) => c,
-                Err(e) => {
-                    if let Ok(mut tracked) = tracker_packages.lock() {
-                        if let Some(p) = tracked.get_mut(index) {
-                            p.state = ProgressState::Failed;
-                            p.message = format!("Spawn error: {}", e);
-                        }
-                    }
-                    return false;
-                }
-            };
 
-            let mut wait_count = 0;
-            let fetch_result = loop {
-                if cancelled.load(Ordering::Relaxed) {
-                    let _ = child.kill();
-                    break None;
-                }
-                
-                match child.try_wait() {
-                    Ok(Some(status)) => {
-                        break Some(status.success());
-                    }
-                    Ok(None) => {
-                        wait_count += 1;
-                        if wait_count > 1200 {
-                            let _ = child.kill();
-                            if let Ok(mut tracked) = tracker_packages.lock() {
-                                if let Some(p) = tracked.get_mut(index) {
-                                    p.state = ProgressState::Failed;
-                                    p.message = "Fetch timeout".to_string();
-                                }
-                            }
-                            break None;
-                        }
-                        
-                        if wait_count % 10 == 0 {
-                            let progress = ((wait_count as f32 / 1200.0) * 90.0) as u16;
-                            if let Ok(mut tracked) = tracker_packages.try_lock() {
-                                if let Some(p) = tracked.get_mut(index) {
-                                    p.progress = progress;
-                                }
-                            }
-                        }
-                        
-                        thread::sleep(Duration::from_millis(100));
-                    }
-                    Err(_) => break None,
-                }
-            };
+        thread::spawn(move || {
+            while !cancelled.load(Ordering::Relaxed) {
+                let next = match queue.lock() {
+                    Ok(mut queue) => queue.pop_front(),
+                    Err(_) => None,
+                };
 
-            match fetch_result {
-                Some(true) => {
-                    if let Ok(mut tracked) = tracker_packages.lock() {
-                        if let Some(p) = tracked.get_mut(index) {
-                            p.progress = 100;
-                            p.message = "Downloaded".to_string();
-                        }
-                    }
-                    true
-                }
-                Some(false) => {
-                    if let Ok(mut tracked) = tracker_packages.lock() {
-                        if let Some(p) = tracked.get_mut(index) {
-                            p.state = ProgressState::Failed;
-                            p.message = "Download failed".to_string();
-                        }
+                match next {
+                    Some((index, package)) => {
+                        fetch_single_package(index, &package, &tracker_packages, &cancelled);
                     }
-                    false
+                    None => break,
                 }
-                None => false,
             }
         })
     }).collect();
@@ -242,17 +285,35 @@ fn parallel_download_sequential_install(
         }
     }
     
+    // Nothing is installed during downloads, so a cancel here has nothing to roll back.
     if user_cancelled {
-        return vec![];
+        return InstallOutcome {
+            results: collect_results(&tracker_packages, &packages),
+            cancelled: true,
+            rolled_back: 0,
+        };
+    }
+
+    // In atomic mode a failed download means nothing gets installed at all.
+    if options.atomic && any_failed(&tracker_packages) {
+        let rolled_back = roll_back_if_needed(tracker, &tracker_packages, &packages, &options.preinstalled, false);
+        return InstallOutcome {
+            results: collect_results(&tracker_packages, &packages),
+            cancelled: false,
+            rolled_back,
+        };
     }
 
     let install_thread = {
         let tracker_packages = Arc::clone(&tracker_packages);
         let cancelled = Arc::clone(&cancelled);
+        let packages = packages.clone();
+        let atomic = options.atomic;
+        let operation = options.operation;
         
         thread::spawn(move || {
             for (index, package) in packages.iter().enumerate() {
-                if cancelled.load(Ordering::Relaxed) {
+                if cancelled.load(Ordering::Relaxed) || (atomic && any_failed(&tracker_packages)) {
                     break;
                 }
                 let should_install = if let Ok(tracked) = tracker_packages.lock() {
@@ -269,49 +330,337 @@ fn parallel_download_sequential_install(
                     continue;
                 }
 
-                install_single_package(index, package, &tracker_packages, &cancelled);
+                install_single_package(index, package, &tracker_packages, &cancelled, operation);
             }
         })
     };
 
     let cancelled_clone = Arc::clone(&cancelled);
-    let install_completed = tracker.run_with_updates(|| install_thread.is_finished());
+    let install_completed = if options.atomic {
+        tracker.run_without_summary(|| install_thread.is_finished())
+    } else {
+        tracker.run_with_updates(|| install_thread.is_finished())
+    };
     
-    if install_completed.is_err() || !install_completed.unwrap_or(true) {
+    let user_cancelled = install_completed.is_err() || !install_completed.unwrap_or(true);
+    if user_cancelled {
         cancelled_clone.store(true, Ordering::Relaxed);
         thread::sleep(Duration::from_millis(200));
     }
     
     let _ = install_thread.join();
-    
-    if let Ok(packages) = tracker_packages.lock() {
-        packages.iter().map(|p| PackageResult {
-            name: p.name.clone(),
-            status: p.state_label().to_string(),
-        }).collect()
+
+    let rolled_back = if options.atomic {
+        roll_back_if_needed(tracker, &tracker_packages, &packages, &options.preinstalled, user_cancelled)
     } else {
-        vec![]
+        0
+    };
+    
+    InstallOutcome {
+        results: collect_results(&tracker_packages, &packages),
+        cancelled: user_cancelled,
+        rolled_back,
+    }
+}
+
+fn any_failed(tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>) -> bool {
+    tracker_packages
+        .lock()
+        .map(|tracked| tracked.iter().any(|p| p.state == ProgressState::Failed))
+        .unwrap_or(false)
+}
+
+/// Indexes of the packages an atomic rollback uninstalls: those this run
+/// installed that were not already installed before it.
+pub fn rollback_selection(tracked: &[PackageProgress], preinstalled: &[String]) -> Vec<usize> {
+    tracked
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.installed && !preinstalled.contains(&p.name))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Atomic mode: if any package failed or the user cancelled, uninstalls every
+/// package this run newly installed, then shows the summary. Returns how many
+/// packages were uninstalled.
+fn roll_back_if_needed(
+    tracker: &mut ProgressTracker,
+    tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>,
+    packages: &[BrewPackage],
+    preinstalled: &[String],
+    cancelled: bool,
+) -> usize {
+    if !cancelled && !any_failed(tracker_packages) {
+        let _ = tracker.run_with_updates(|| true);
+        return 0;
+    }
+
+    let skipped = if cancelled { "Skipped (atomic run cancelled)" } else { "Skipped (atomic run failed)" };
+    let to_roll_back: Vec<(usize, BrewPackage)> = match tracker_packages.lock() {
+        Ok(mut tracked) => {
+            for p in tracked.iter_mut().filter(|p| p.state == ProgressState::Pending) {
+                p.message = skipped.to_string();
+            }
+            rollback_selection(&tracked, preinstalled)
+                .into_iter()
+                .filter_map(|index| packages.get(index).map(|package| (index, package.clone())))
+                .collect()
+        }
+        Err(_) => vec![],
+    };
+    let indexes: Vec<usize> = to_roll_back.iter().map(|(index, _)| *index).collect();
+
+    let rollback_thread = {
+        let tracker_packages = Arc::clone(tracker_packages);
+
+        thread::spawn(move || {
+            for (index, package) in &to_roll_back {
+                roll_back_single_package(*index, package, &tracker_packages);
+            }
+        })
+    };
+
+    let _ = tracker.run_with_updates(|| rollback_thread.is_finished());
+    let _ = rollback_thread.join();
+
+    tracker_packages
+        .lock()
+        .map(|tracked| indexes.iter().filter(|&&index| tracked.get(index).is_some_and(|p| !p.installed)).count())
+        .unwrap_or(0)
+}
+
+fn roll_back_single_package(
+    index: usize,
+    package: &BrewPackage,
+    tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>,
+) {
+    if let Ok(mut tracked) = tracker_packages.lock() {
+        if let Some(p) = tracked.get_mut(index) {
+            p.state = ProgressState::RollingBack;
+            p.progress = 50;
+            p.message = "Rolling back...".to_string();
+        }
+    }
+
+    let mut command = Command::new(PROGRAM);
+    command.arg("uninstall").arg("--force");
+    if package.cask.is_some() {
+        command.arg("--cask");
+    }
+    command
+        .arg(&package.name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let log = PackageLog::for_package(tracker_packages, index);
+    log.note("atomic rollback");
+    log.command(&command);
+
+    let result = match command.spawn() {
+        Ok(mut child) => {
+            let (stdout_thread, stderr_thread) = capture_output(&mut child, &log);
+            let status = child.wait();
+            let _ = stdout_thread.join();
+            let _ = stderr_thread.join();
+            log.exited(status.as_ref().ok());
+            status.map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    if let Ok(mut tracked) = tracker_packages.lock() {
+        if let Some(p) = tracked.get_mut(index) {
+            match result {
+                Ok(status) if status.success() => finish_rollback(p, Ok(())),
+                _ => finish_rollback(p, Err(failure_message("Rollback failed", &log))),
+            }
+        }
+    }
+}
+
+/// Records the outcome of uninstalling `p` during a rollback. A package that
+/// failed keeps its failure, so the reason is not lost.
+pub fn finish_rollback(p: &mut PackageProgress, uninstalled: Result<(), String>) {
+    match uninstalled {
+        Ok(()) => {
+            p.installed = false;
+            p.progress = 100;
+            if p.failure.is_some() {
+                p.state = ProgressState::Failed;
+                p.message = format!("{} (rolled back)", p.message);
+            } else {
+                p.state = ProgressState::RolledBack;
+                p.message = "Rolled back".to_string();
+            }
+        }
+        Err(message) => {
+            p.state = ProgressState::Failed;
+            p.failure.get_or_insert(FailureReason::Unknown);
+            p.progress = 0;
+            p.message = message;
+        }
+    }
+}
+
+fn fetch_single_package(
+    index: usize,
+    package: &BrewPackage,
+    tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>,
+    cancelled: &Arc<AtomicBool>,
+) -> bool {
+    if let Ok(mut tracked) = tracker_packages.lock() {
+        if let Some(p) = tracked.get_mut(index) {
+            p.state = ProgressState::Downloading;
+            p.progress = 0;
+            p.message = "Fetching...".to_string();
+            p.begin();
+        }
+    }
+
+    let mut command = Command::new(PROGRAM);
+    command.arg("fetch");
+    
+    if package.cask.is_some() {
+        command.arg("--cask");
+    }
+    
+    command
+        .arg(&package.name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let log = PackageLog::for_package(tracker_packages, index);
+    log.command(&command);
+
+    let mut child = match command.spawn() {
+        Ok(c) => c,
+        Err(e) => {
+            log.note(&format!("spawn error: {}", e));
+            if let Ok(mut tracked) = tracker_packages.lock() {
+                if let Some(p) = tracked.get_mut(index) {
+                    p.state = ProgressState::Failed;
+                    p.failure = Some(FailureReason::from_io_error(&e));
+                    p.message = format!("Spawn error: {}", e);
+                    p.finish();
+                }
+            }
+            return false;
+        }
+    };
+
+    let (stdout_thread, stderr_thread) = capture_output(&mut child, &log);
+
+    let mut wait_count = 0;
+    let fetch_result = loop {
+        if cancelled.load(Ordering::Relaxed) {
+            let _ = child.kill();
+            break None;
+        }
+        
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                break Some(status);
+            }
+            Ok(None) => {
+                wait_count += 1;
+                if wait_count > 1200 {
+                    let _ = child.kill();
+                    if let Ok(mut tracked) = tracker_packages.lock() {
+                        if let Some(p) = tracked.get_mut(index) {
+                            p.state = ProgressState::Failed;
+                            p.failure = Some(FailureReason::Timeout);
+                            p.message = "Fetch timeout".to_string();
+                            p.finish();
+                        }
+                    }
+                    break None;
+                }
+                
+                if wait_count % 10 == 0 {
+                    let progress = ((wait_count as f32 / 1200.0) * 90.0) as u16;
+                    if let Ok(mut tracked) = tracker_packages.try_lock() {
+                        if let Some(p) = tracked.get_mut(index) {
+                            p.progress = progress;
+                        }
+                    }
+                }
+                
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(_) => break None,
+        }
+    };
+
+    let _ = stdout_thread.join();
+    let _ = stderr_thread.join();
+    log.exited(fetch_result.as_ref());
+
+    match fetch_result {
+        Some(status) if status.success() => {
+            if let Ok(mut tracked) = tracker_packages.lock() {
+                if let Some(p) = tracked.get_mut(index) {
+                    p.progress = 100;
+                    p.message = "Downloaded".to_string();
+                }
+            }
+            true
+        }
+        Some(status) => {
+            let reason = FailureReason::classify(status.code(), &log.tail_lines());
+            if let Ok(mut tracked) = tracker_packages.lock() {
+                if let Some(p) = tracked.get_mut(index) {
+                    p.state = ProgressState::Failed;
+                    p.failure = Some(reason);
+                    p.message = failure_message("Download failed", &log);
+                    p.error = log.error_excerpt();
+                    p.finish();
+                }
+            }
+            false
+        }
+        None => false,
     }
 }
 
 fn install_single_package(
     index: usize,
     package: &BrewPackage,
-    tracker_packages: &Arc<Mutex<Vec<crate::tui::progress::PackageProgress>>>,
+    tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>,
     cancelled: &Arc<AtomicBool>,
+    operation: InstallOperation,
 ) {
     if let Ok(mut tracked) = tracker_packages.lock() {
         if let Some(p) = tracked.get_mut(index) {
             p.state = ProgressState::Downloading;
             p.progress = 0;
             p.message = "Starting...".to_string();
+            p.begin();
+            p.attempts += 1;
         }
     }
 
     thread::sleep(Duration::from_millis(200));
 
+    let log = PackageLog::for_package(tracker_packages, index);
+
+    if let Err(message) = run_hook(index, HookKind::PreInstall, package, tracker_packages, &log, cancelled) {
+        if let Ok(mut tracked) = tracker_packages.lock() {
+            if let Some(p) = tracked.get_mut(index) {
+                p.state = ProgressState::Failed;
+                p.failure = Some(FailureReason::HookFailed);
+                p.progress = 0;
+                p.message = message;
+                p.finish();
+            }
+        }
+        return;
+    }
+
     let mut command = Command::new(PROGRAM);
-    command.arg("install").arg(&package.name);
+    command.arg(operation.brew_command()).arg(&package.name);
 
     if package.cask.is_some() {
         command.arg("--cask");
@@ -322,14 +671,19 @@ fn install_single_package(
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
+    log.command(&command);
+
     let mut child = match command.spawn() {
         Ok(c) => c,
         Err(e) => {
+            log.note(&format!("spawn error: {}", e));
             if let Ok(mut tracked) = tracker_packages.lock() {
                 if let Some(p) = tracked.get_mut(index) {
                     p.state = ProgressState::Failed;
+                    p.failure = Some(FailureReason::from_io_error(&e));
                     p.progress = 0;
                     p.message = format!("Error: {}", e);
+                    p.finish();
                 }
             }
             return;
@@ -339,10 +693,12 @@ fn install_single_package(
     let stdout = child.stdout.take().unwrap();
     let stderr = child.stderr.take().unwrap();
     let tracker_packages_clone = Arc::clone(tracker_packages);
+    let stdout_log = Arc::clone(&log);
     
     let stdout_thread = thread::spawn(move || {
         let reader = BufReader::new(stdout);
         for line in reader.lines().map_while(Result::ok) {
+                stdout_log.line(LogStream::Stdout, &line);
                 let progress = estimate_progress(&line);
                 let state = if line.contains("Downloading") || line.contains("download") {
                     ProgressState::Downloading
@@ -363,9 +719,11 @@ fn install_single_package(
     });
 
     let tracker_packages_clone = Arc::clone(tracker_packages);
+    let stderr_log = Arc::clone(&log);
     let stderr_thread = thread::spawn(move || {
         let reader = BufReader::new(stderr);
         for line in reader.lines().map_while(Result::ok) {
+            stderr_log.line(LogStream::Stderr, &line);
             if !line.trim().is_empty() && line.len() < 50 {
                     if let Ok(mut tracked) = tracker_packages_clone.try_lock() {
                         if let Some(p) = tracked.get_mut(index) {
@@ -419,22 +777,54 @@ fn install_single_package(
     let _ = stderr_thread.join();
     
     let status = status.unwrap();
+    log.exited(status.as_ref().ok());
+
+    let post_install = match status {
+        Ok(ref exit_status) if exit_status.success() => {
+            if let Ok(mut tracked) = tracker_packages.lock() {
+                if let Some(p) = tracked.get_mut(index) {
+                    p.installed = true;
+                }
+            }
+            run_hook(index, HookKind::PostInstall, package, tracker_packages, &log, cancelled)
+        }
+        _ => Ok(()),
+    };
+
+    let version = match status {
+        Ok(ref exit_status) if exit_status.success() => installed_version(&package.name, package.cask.is_some()),
+        _ => None,
+    };
 
     if let Ok(mut tracked) = tracker_packages.lock() {
         if let Some(p) = tracked.get_mut(index) {
+            p.finish();
+            p.version = version;
             match status {
-                Ok(exit_status) if exit_status.success() => {
-                    p.state = ProgressState::Completed;
-                    p.progress = 100;
-                    p.message = "Done!".to_string();
-                }
-                Ok(_) => {
+                Ok(exit_status) if exit_status.success() => match post_install {
+                    Ok(()) => {
+                        p.state = ProgressState::Completed;
+                        p.progress = 100;
+                        p.message = "Done!".to_string();
+                    }
+                    Err(message) => {
+                        p.state = ProgressState::Failed;
+                        p.failure = Some(FailureReason::HookFailed);
+                        p.progress = 0;
+                        p.message = message;
+                    }
+                },
+                Ok(exit_status) => {
                     p.state = ProgressState::Failed;
+                    p.failure = Some(FailureReason::classify(exit_status.code(), &log.tail_lines()));
                     p.progress = 0;
-                    p.message = "Installation failed".to_string();
+                    p.message = failure_message(operation.failure_summary(), &log);
+                    p.error = log.error_excerpt();
                 }
                 Err(e) => {
+                    log.note(&e.to_string());
                     p.state = ProgressState::Failed;
+                    p.failure = Some(FailureReason::from_io_error(&e));
                     p.progress = 0;
                     p.message = format!("Error: {}", e);
                 }
@@ -445,6 +835,13 @@ fn install_single_package(
     thread::sleep(Duration::from_millis(100));
 }
 
+/// Number of concurrent `brew fetch` workers used when `--jobs` is not given.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(DEFAULT_FETCH_JOBS)
+}
+
 fn estimate_progress(line: &str) -> u16 {
     if let Some(pos) = line.find('%') {
         let before = &line[..pos];
@@ -1,25 +1,41 @@
 use crate::constants::PROGRAM;
+use crate::logging;
 use crate::models::BrewPackage;
 use std::process::{exit, Command, Stdio};
 
+/// The newest installed version of a formula or cask, from `brew list --versions`.
+pub fn installed_version(name: &str, cask: bool) -> Option<String> {
+    let mut command = Command::new(PROGRAM);
+    command.arg("list").arg("--versions");
+    if cask {
+        command.arg("--cask");
+    }
+    let output = logging::output(
+        command
+            .arg(name)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null()),
+    )
+        .ok()
+        .filter(|o| o.status.success())?;
+
+    // `wget 1.21.3 1.21.4`: the name, then each installed version.
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .skip(1)
+        .last()
+        .map(String::from)
+}
+
 pub fn list_installed_packages() -> Vec<BrewPackage> {
-    let output = Command::new(PROGRAM)
-        .arg("list")
-        .stdout(Stdio::piped())
-        .output();
+    let output = logging::output(Command::new(PROGRAM).arg("list").stdout(Stdio::piped()));
 
     match output {
         Ok(output) => {
             let stdout = &output.stdout.clone();
             let result: Vec<BrewPackage> = String::from_utf8_lossy(stdout)
                 .lines()
-                .map(|s| BrewPackage {
-                    name: s.to_string(),
-                    category: None,
-                    url: None,
-                    cask: None,
-                    version: None,
-                }) // Convert &str to String
+                .map(BrewPackage::named)
                 .collect();
 
             result
@@ -0,0 +1,86 @@
+use crate::constants::PROGRAM;
+use crate::logging;
+use serde_json::Value;
+use std::process::{Command, Stdio};
+
+/// A formula or cask with a newer version available, as reported by `brew outdated`.
+#[derive(Debug, Clone)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub cask: bool,
+    pub installed_version: String,
+    pub available_version: String,
+    /// Pinned with `brew pin`.
+    pub pinned: bool,
+}
+
+pub fn list_outdated_packages() -> Result<Vec<OutdatedPackage>, String> {
+    let output = logging::output(
+        Command::new(PROGRAM)
+            .arg("outdated")
+            .arg("--json=v2")
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped()),
+    )
+        .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "brew outdated failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_outdated_json(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub fn parse_outdated_json(json: &str) -> Result<Vec<OutdatedPackage>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|e| format!("JSON parse error: {}", e))?;
+
+    let entries = |key: &str| -> Vec<Value> {
+        value.get(key).and_then(Value::as_array).cloned().unwrap_or_default()
+    };
+
+    let mut outdated: Vec<OutdatedPackage> = vec![];
+    for (key, cask) in [("formulae", false), ("casks", true)] {
+        for entry in entries(key) {
+            let Some(name) = entry.get("name").and_then(Value::as_str) else {
+                continue;
+            };
+
+            outdated.push(OutdatedPackage {
+                name: name.to_string(),
+                cask,
+                installed_version: installed_version(&entry),
+                available_version: entry
+                    .get("current_version")
+                    .and_then(Value::as_str)
+                    .unwrap_or("?")
+                    .to_string(),
+                pinned: entry.get("pinned").and_then(Value::as_bool).unwrap_or(false),
+            });
+        }
+    }
+
+    Ok(outdated)
+}
+
+// Formulae report a list of installed versions, older casks a single string.
+fn installed_version(entry: &Value) -> String {
+    match entry.get("installed_versions") {
+        Some(Value::Array(versions)) => versions
+            .last()
+            .and_then(Value::as_str)
+            .unwrap_or("?")
+            .to_string(),
+        Some(Value::String(version)) => version.clone(),
+        _ => "?".to_string(),
+    }
+}
+
+/// Whether a recipe version pin allows upgrading to `available`.
+///
+/// A pin matches itself and any version that extends it, so `1.2` allows `1.2.7` but not `1.3.0`.
+pub fn pin_allows(pin: &str, available: &str) -> bool {
+    available == pin || available.starts_with(&format!("{}.", pin)) || available.starts_with(&format!("{}_", pin))
+}
@@ -0,0 +1,215 @@
+use crate::logging::command_line;
+use crate::tui::progress::PackageProgress;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+const TAIL_CAPACITY: usize = 200;
+const ERROR_EXCERPT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// Full output of the brew commands run for one package.
+///
+/// Every line is appended to the package's log file (when the run has a log
+/// directory) and the most recent lines are kept in memory so failures can
+/// be summarised without re-reading the file.
+pub struct PackageLog {
+    package: String,
+    file: Mutex<Option<File>>,
+    tail: Mutex<VecDeque<(LogStream, String)>>,
+    /// The command currently running and when it started, for the debug log.
+    running: Mutex<Option<(String, Instant)>>,
+}
+
+impl PackageLog {
+    pub fn open(package: &str, path: Option<&Path>) -> Self {
+        let file = path.and_then(|p| OpenOptions::new().create(true).append(true).open(p).ok());
+
+        Self {
+            package: package.to_string(),
+            file: Mutex::new(file),
+            tail: Mutex::new(VecDeque::with_capacity(TAIL_CAPACITY)),
+            running: Mutex::new(None),
+        }
+    }
+
+    /// Opens the log attached to the tracked package at `index`.
+    pub fn for_package(tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>, index: usize) -> Arc<Self> {
+        let (name, path) = tracker_packages
+            .lock()
+            .ok()
+            .and_then(|tracked| tracked.get(index).map(|p| (p.name.clone(), p.log_path.clone())))
+            .unwrap_or_default();
+
+        Arc::new(Self::open(&name, path.as_deref()))
+    }
+
+    pub fn command(&self, command: &Command) {
+        let line = command_line(command);
+        log::debug!(target: "command", package = self.package.as_str(), command = line.as_str(); "Running {}", line);
+        self.write_raw(&format!("$ {}", line));
+        if let Ok(mut running) = self.running.lock() {
+            *running = Some((line, Instant::now()));
+        }
+    }
+
+    /// Records how the last [`command`](Self::command) ended; `None` when it was
+    /// killed, timed out or could not be waited on.
+    pub fn exited(&self, status: Option<&ExitStatus>) {
+        let Some((line, started)) = self.running.lock().ok().and_then(|mut running| running.take()) else {
+            return;
+        };
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match status {
+            Some(status) => log::debug!(
+                target: "command",
+                package = self.package.as_str(),
+                command = line.as_str(),
+                code = status.code().unwrap_or(-1),
+                duration_ms = duration_ms;
+                "Finished {}",
+                line
+            ),
+            None => log::debug!(
+                target: "command",
+                package = self.package.as_str(),
+                command = line.as_str(),
+                duration_ms = duration_ms;
+                "Stopped {}",
+                line
+            ),
+        }
+    }
+
+    pub fn line(&self, stream: LogStream, line: &str) {
+        let prefix = match stream {
+            LogStream::Stdout => "out",
+            LogStream::Stderr => "err",
+        };
+        self.write_raw(&format!("[{}] {}", prefix, line));
+
+        if let Ok(mut tail) = self.tail.lock() {
+            if tail.len() == TAIL_CAPACITY {
+                tail.pop_front();
+            }
+            tail.push_back((stream, line.to_string()));
+        }
+    }
+
+    pub fn note(&self, message: &str) {
+        self.write_raw(&format!("# {}", message));
+    }
+
+    /// Most recent output lines, oldest first.
+    pub fn tail_lines(&self) -> Vec<String> {
+        self.tail
+            .lock()
+            .map(|tail| tail.iter().map(|(_, line)| line.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// The last few lines that explain a failure, joined for display.
+    pub fn error_excerpt(&self) -> Option<String> {
+        let tail = self.tail.lock().ok()?;
+        let lines: Vec<(LogStream, String)> = tail.iter().cloned().collect();
+        error_excerpt(&lines)
+    }
+
+    fn write_raw(&self, text: &str) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = writeln!(file, "{}", text);
+            }
+        }
+    }
+}
+
+/// Picks the lines that look like errors, falling back to the last stderr
+/// output, and keeps at most `ERROR_EXCERPT_LINES` of them.
+pub fn error_excerpt(lines: &[(LogStream, String)]) -> Option<String> {
+    let meaningful = |line: &str| !line.trim().is_empty() && !line.trim_start().starts_with("==>");
+
+    let mut picked: Vec<&str> = lines
+        .iter()
+        .map(|(_, line)| line.as_str())
+        .filter(|line| meaningful(line) && line.to_lowercase().contains("error"))
+        .collect();
+
+    if picked.is_empty() {
+        picked = lines
+            .iter()
+            .filter(|(stream, line)| *stream == LogStream::Stderr && meaningful(line))
+            .map(|(_, line)| line.as_str())
+            .collect();
+    }
+
+    if picked.is_empty() {
+        return None;
+    }
+
+    let start = picked.len().saturating_sub(ERROR_EXCERPT_LINES);
+    Some(
+        picked[start..]
+            .iter()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join(" | "),
+    )
+}
+
+/// File name used for a package's log inside a run log directory.
+pub fn log_file_for(dir: &Path, package_name: &str) -> PathBuf {
+    let safe: String = package_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    dir.join(format!("{}.log", safe))
+}
+
+/// Points every tracked package at its log file inside `dir`.
+pub fn attach_log_files(tracker_packages: &Arc<Mutex<Vec<PackageProgress>>>, dir: &Path) {
+    if let Ok(mut tracked) = tracker_packages.lock() {
+        for p in tracked.iter_mut() {
+            p.log_path = Some(log_file_for(dir, &p.name));
+        }
+    }
+}
+
+/// Drains the child's stdout and stderr into `log` on background threads.
+pub fn capture_output(child: &mut Child, log: &Arc<PackageLog>) -> (JoinHandle<()>, JoinHandle<()>) {
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    (
+        spawn_reader(stdout, LogStream::Stdout, Arc::clone(log)),
+        spawn_reader(stderr, LogStream::Stderr, Arc::clone(log)),
+    )
+}
+
+fn spawn_reader<R: Read + Send + 'static>(source: Option<R>, stream: LogStream, log: Arc<PackageLog>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        if let Some(source) = source {
+            for line in BufReader::new(source).lines().map_while(Result::ok) {
+                log.line(stream, &line);
+            }
+        }
+    })
+}
+
+/// `summary` followed by the error excerpt from `log`, when there is one.
+pub fn failure_message(summary: &str, log: &PackageLog) -> String {
+    match log.error_excerpt() {
+        Some(excerpt) => format!("{}: {}", summary, excerpt),
+        None => summary.to_string(),
+    }
+}
@@ -0,0 +1,172 @@
+use crate::constants::PROGRAM;
+use crate::logging;
+use crate::models::BrewPackage;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::process::{Command, Stdio};
+
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Every formula and cask name Homebrew knows about, from its local metadata.
+pub struct HomebrewIndex {
+    pub formulae: HashSet<String>,
+    pub casks: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExistenceProblem {
+    /// Neither a formula nor a cask; carries close names.
+    Unknown { suggestions: Vec<String> },
+    /// Listed as a formula but only exists as a cask.
+    IsCask,
+    /// Listed as a cask but only exists as a formula.
+    IsFormula,
+    /// An old formula name Homebrew still resolves.
+    Renamed { to: String },
+}
+
+/// A recipe entry that does not resolve cleanly; `index` is its position in the checked list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExistenceIssue {
+    pub index: usize,
+    pub name: String,
+    pub problem: ExistenceProblem,
+}
+
+impl ExistenceIssue {
+    /// Renames still install, so they are reported without failing the check.
+    pub fn is_error(&self) -> bool {
+        !matches!(self.problem, ExistenceProblem::Renamed { .. })
+    }
+
+    pub fn message(&self) -> String {
+        match &self.problem {
+            ExistenceProblem::Unknown { suggestions } if suggestions.is_empty() => {
+                format!("'{}' is not a known formula or cask", self.name)
+            }
+            ExistenceProblem::Unknown { suggestions } => format!(
+                "'{}' is not a known formula or cask; did you mean {}?",
+                self.name,
+                suggestions.iter().map(|s| format!("'{}'", s)).collect::<Vec<_>>().join(", ")
+            ),
+            ExistenceProblem::IsCask => format!("'{}' is a cask; set \"cask\": true", self.name),
+            ExistenceProblem::IsFormula => format!("'{}' is a formula, not a cask; remove \"cask\"", self.name),
+            ExistenceProblem::Renamed { to } => format!("'{}' was renamed to '{}'", self.name, to),
+        }
+    }
+}
+
+impl HomebrewIndex {
+    /// Lists all formulae and casks in one call each.
+    pub fn load() -> Result<Self, String> {
+        Ok(Self {
+            formulae: brew_names("formulae")?,
+            casks: brew_names("casks")?,
+        })
+    }
+
+    /// Checks each package's name and cask flag. `renamed` resolves names
+    /// that are not in the index, and is only called for those.
+    pub fn check(&self, packages: &[BrewPackage], renamed: impl Fn(&str) -> Option<String>) -> Vec<ExistenceIssue> {
+        let mut issues: Vec<ExistenceIssue> = vec![];
+
+        for (index, package) in packages.iter().enumerate() {
+            let name = package.name.as_str();
+            // Tap-qualified names are tapped on demand by `brew install`.
+            if name.contains('/') {
+                continue;
+            }
+            let is_formula = self.formulae.contains(name);
+            let is_cask = self.casks.contains(name);
+
+            let problem = match (package.cask.is_some(), is_formula, is_cask) {
+                (false, true, _) | (true, _, true) => continue,
+                (false, false, true) => ExistenceProblem::IsCask,
+                (true, true, false) => ExistenceProblem::IsFormula,
+                (_, false, false) => match renamed(name) {
+                    Some(to) => ExistenceProblem::Renamed { to },
+                    None => ExistenceProblem::Unknown {
+                        suggestions: self.suggestions(name),
+                    },
+                },
+            };
+
+            issues.push(ExistenceIssue {
+                index,
+                name: name.to_string(),
+                problem,
+            });
+        }
+
+        issues
+    }
+
+    /// The closest known names, best first.
+    pub fn suggestions(&self, name: &str) -> Vec<String> {
+        let limit = (name.chars().count() / 3).clamp(1, 3);
+        let mut close: Vec<(usize, &String)> = self
+            .formulae
+            .iter()
+            .chain(self.casks.iter())
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= limit)
+            .collect();
+
+        close.sort();
+        close.dedup_by(|a, b| a.1 == b.1);
+        close.into_iter().take(MAX_SUGGESTIONS).map(|(_, c)| c.clone()).collect()
+    }
+}
+
+fn brew_names(kind: &str) -> Result<HashSet<String>, String> {
+    let output = logging::output(
+        Command::new(PROGRAM)
+            .arg(kind)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null()),
+    )
+        .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!("brew {} failed", kind));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// The current name of a renamed formula, if Homebrew resolves `name` to a different one.
+pub fn renamed_formula(name: &str) -> Option<String> {
+    let output = logging::output(
+        Command::new(PROGRAM)
+            .args(["info", "--json=v2", "--formula", name])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null()),
+    )
+        .ok()
+        .filter(|o| o.status.success())?;
+
+    let json: Value = serde_json::from_slice(&output.stdout).ok()?;
+    let resolved = json.get("formulae")?.get(0)?.get("name")?.as_str()?;
+    Some(resolved.to_string()).filter(|r| r != name)
+}
+
+/// Levenshtein distance between two names.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
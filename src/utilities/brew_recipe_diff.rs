@@ -0,0 +1,149 @@
+use crate::models::BrewPackage;
+use console::style;
+use serde::Serialize;
+
+/// A package present in only one of the two recipes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffEntry {
+    pub name: String,
+    pub cask: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// One recipe field that differs; `None` means the field is not set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedPackage {
+    pub name: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Differences between two merged recipes, in recipe order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RecipeDiff {
+    pub added: Vec<DiffEntry>,
+    pub removed: Vec<DiffEntry>,
+    pub changed: Vec<ChangedPackage>,
+}
+
+impl RecipeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Every recipe field besides `name`, as compared by `diff_recipes`.
+fn fields(package: &BrewPackage) -> [(&'static str, Option<String>); 10] {
+    [
+        ("cask", package.cask.map(|c| c.to_string())),
+        ("category", package.category.clone()),
+        ("version", package.version.clone()),
+        ("url", package.url.clone()),
+        ("os", package.os.clone()),
+        ("arch", package.arch.clone()),
+        ("min_os_version", package.min_os_version.clone()),
+        ("when", package.when.clone()),
+        ("pre_install", package.pre_install.clone()),
+        ("post_install", package.post_install.clone()),
+    ]
+}
+
+fn entry(package: &BrewPackage) -> DiffEntry {
+    DiffEntry {
+        name: package.name.clone(),
+        cask: package.cask.is_some(),
+        category: package.category.clone(),
+        version: package.version.clone(),
+    }
+}
+
+/// Compares recipe `before` with recipe `after`, matching packages by name.
+pub fn diff_recipes(before: &[BrewPackage], after: &[BrewPackage]) -> RecipeDiff {
+    let mut diff = RecipeDiff::default();
+
+    for old in before {
+        match after.iter().find(|p| p.name == old.name) {
+            None => diff.removed.push(entry(old)),
+            Some(new) => {
+                let changes: Vec<FieldChange> = fields(old)
+                    .into_iter()
+                    .zip(fields(new))
+                    .filter(|((_, a), (_, b))| a != b)
+                    .map(|((field, before), (_, after))| FieldChange { field, before, after })
+                    .collect();
+                if !changes.is_empty() {
+                    diff.changed.push(ChangedPackage {
+                        name: old.name.clone(),
+                        changes,
+                    });
+                }
+            }
+        }
+    }
+
+    diff.added = after
+        .iter()
+        .filter(|new| !before.iter().any(|p| p.name == new.name))
+        .map(entry)
+        .collect();
+
+    diff
+}
+
+pub fn print_recipe_diff(diff: &RecipeDiff, before: &str, after: &str) {
+    println!("{}", style(format!("--- {}", before)).red().bold());
+    println!("{}", style(format!("+++ {}", after)).green().bold());
+
+    let details = |entry: &DiffEntry| {
+        let mut details = String::new();
+        if entry.cask {
+            details.push_str(" [cask]");
+        }
+        if let Some(ref category) = entry.category {
+            details.push_str(&format!(" [{}]", category));
+        }
+        if let Some(ref version) = entry.version {
+            details.push_str(&format!(" @{}", version));
+        }
+        details
+    };
+
+    for entry in &diff.added {
+        println!("{} {}{}", style("+").green().bold(), style(&entry.name).green(), style(details(entry)).dim());
+    }
+    for entry in &diff.removed {
+        println!("{} {}{}", style("-").red().bold(), style(&entry.name).red(), style(details(entry)).dim());
+    }
+    for package in &diff.changed {
+        println!("{} {}", style("~").yellow().bold(), style(&package.name).yellow());
+        for change in &package.changes {
+            let value = |v: &Option<String>| v.clone().unwrap_or_else(|| "(none)".to_string());
+            println!(
+                "    {}: {} → {}",
+                change.field,
+                style(value(&change.before)).red(),
+                style(value(&change.after)).green()
+            );
+        }
+    }
+
+    if diff.is_empty() {
+        println!("\n{} No differences", style("✓").green().bold());
+    } else {
+        println!(
+            "\n{} added, {} removed, {} changed",
+            style(diff.added.len()).green().bold(),
+            style(diff.removed.len()).red().bold(),
+            style(diff.changed.len()).yellow().bold()
+        );
+    }
+}
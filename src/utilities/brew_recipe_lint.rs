@@ -0,0 +1,223 @@
+use crate::constants::PROGRAM;
+use crate::logging;
+use crate::models::BrewPackage;
+use crate::utilities::merge_sources;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::process::{Command, Stdio};
+
+/// The parts of `brew info --json=v2` that lint rules look at.
+#[derive(Debug, Clone, Default)]
+pub struct PackageMetadata {
+    pub name: String,
+    pub deprecated: bool,
+    pub disabled: bool,
+    /// Deprecation or disable reason, when Homebrew gives one.
+    pub reason: Option<String>,
+    pub conflicts_with: Vec<String>,
+    pub dependencies: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintSeverity {
+    Error,
+    Warning,
+}
+
+impl LintSeverity {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LintSeverity::Error => "error",
+            LintSeverity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintFinding {
+    pub severity: LintSeverity,
+    pub package: String,
+    pub message: String,
+}
+
+impl LintFinding {
+    fn new(severity: LintSeverity, package: &str, message: String) -> Self {
+        Self {
+            severity,
+            package: package.to_string(),
+            message,
+        }
+    }
+}
+
+/// Metadata for the given formulae and casks, in at most two `brew info` calls.
+/// Names must exist in Homebrew, otherwise the whole batch fails.
+pub fn fetch_metadata(formulae: &[&str], casks: &[&str]) -> Result<Vec<PackageMetadata>, String> {
+    let mut metadata: Vec<PackageMetadata> = vec![];
+
+    for (flag, names) in [("--formula", formulae), ("--cask", casks)] {
+        if names.is_empty() {
+            continue;
+        }
+
+        let output = logging::output(
+            Command::new(PROGRAM)
+                .args(["info", "--json=v2", flag])
+                .args(names)
+                .stdin(Stdio::null())
+                .stderr(Stdio::piped()),
+        )
+            .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "brew info failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        metadata.extend(parse_info_json(&String::from_utf8_lossy(&output.stdout))?);
+    }
+
+    Ok(metadata)
+}
+
+pub fn parse_info_json(json: &str) -> Result<Vec<PackageMetadata>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|e| format!("JSON parse error: {}", e))?;
+
+    let strings = |v: Option<&Value>| -> Vec<String> {
+        v.and_then(Value::as_array)
+            .map(|a| a.iter().filter_map(Value::as_str).map(String::from).collect())
+            .unwrap_or_default()
+    };
+    let flag = |entry: &Value, key: &str| entry.get(key).and_then(Value::as_bool).unwrap_or(false);
+    let reason = |entry: &Value| {
+        ["disable_reason", "deprecation_reason"]
+            .iter()
+            .find_map(|key| entry.get(*key).and_then(Value::as_str))
+            .map(String::from)
+    };
+
+    let mut metadata: Vec<PackageMetadata> = vec![];
+
+    for entry in value.get("formulae").and_then(Value::as_array).into_iter().flatten() {
+        let Some(name) = entry.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        metadata.push(PackageMetadata {
+            name: name.to_string(),
+            deprecated: flag(entry, "deprecated"),
+            disabled: flag(entry, "disabled"),
+            reason: reason(entry),
+            conflicts_with: strings(entry.get("conflicts_with")),
+            dependencies: strings(entry.get("dependencies")),
+        });
+    }
+
+    // Casks key conflicts and dependencies by kind: {"cask": [...], "formula": [...]}.
+    for entry in value.get("casks").and_then(Value::as_array).into_iter().flatten() {
+        let Some(token) = entry.get("token").and_then(Value::as_str) else {
+            continue;
+        };
+        let by_kind = |key: &str| -> Vec<String> {
+            let group = entry.get(key);
+            let mut names = strings(group.and_then(|g| g.get("cask")));
+            names.extend(strings(group.and_then(|g| g.get("formula"))));
+            names
+        };
+        metadata.push(PackageMetadata {
+            name: token.to_string(),
+            deprecated: flag(entry, "deprecated"),
+            disabled: flag(entry, "disabled"),
+            reason: reason(entry),
+            conflicts_with: by_kind("conflicts_with"),
+            dependencies: by_kind("depends_on"),
+        });
+    }
+
+    Ok(metadata)
+}
+
+/// Lints a chained recipe. `sources` are the recipe files in chain order,
+/// `metadata` covers the merged packages Homebrew knows about, and
+/// `allowed_categories` (case-insensitive) is ignored when empty.
+pub fn lint_recipe(
+    sources: &[(String, Vec<BrewPackage>)],
+    metadata: &[PackageMetadata],
+    allowed_categories: &[String],
+) -> Vec<LintFinding> {
+    let mut findings: Vec<LintFinding> = vec![];
+
+    let merged = merge_sources(sources);
+    let mut defined_in: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (source, packages) in sources {
+        for package in packages {
+            let files = defined_in.entry(package.name.as_str()).or_default();
+            if !files.contains(&source.as_str()) {
+                files.push(source.as_str());
+            }
+        }
+    }
+
+    for package in &merged {
+        let files = &defined_in[package.name.as_str()];
+        if files.len() > 1 {
+            findings.push(LintFinding::new(
+                LintSeverity::Warning,
+                &package.name,
+                format!("defined in {} ({} wins)", files.join(", "), files[files.len() - 1]),
+            ));
+        }
+    }
+
+    let by_name: HashMap<&str, &PackageMetadata> = metadata.iter().map(|m| (m.name.as_str(), m)).collect();
+    let names: HashSet<&str> = merged.iter().map(|p| p.name.as_str()).collect();
+
+    for package in &merged {
+        let name = package.name.as_str();
+
+        if let Some(meta) = by_name.get(name) {
+            let because = meta.reason.as_deref().map(|r| format!(" ({})", r)).unwrap_or_default();
+            if meta.disabled {
+                findings.push(LintFinding::new(LintSeverity::Error, name, format!("is disabled in Homebrew{}", because)));
+            } else if meta.deprecated {
+                findings.push(LintFinding::new(LintSeverity::Warning, name, format!("is deprecated in Homebrew{}", because)));
+            }
+
+            for other in meta.conflicts_with.iter().filter(|o| names.contains(o.as_str())) {
+                // Report each pair once, from the side that sorts first.
+                let declared_back = by_name
+                    .get(other.as_str())
+                    .is_some_and(|m| m.conflicts_with.iter().any(|c| c == name));
+                if name < other.as_str() || !declared_back {
+                    findings.push(LintFinding::new(LintSeverity::Error, name, format!("conflicts with '{}'", other)));
+                }
+            }
+        }
+
+        let parents: Vec<&str> = metadata
+            .iter()
+            .filter(|m| m.name != name && names.contains(m.name.as_str()) && m.dependencies.iter().any(|d| d == name))
+            .map(|m| m.name.as_str())
+            .collect();
+        if !parents.is_empty() {
+            findings.push(LintFinding::new(
+                LintSeverity::Warning,
+                name,
+                format!("is already installed as a dependency of {}", parents.join(", ")),
+            ));
+        }
+
+        if let (false, Some(category)) = (allowed_categories.is_empty(), package.category.as_deref()) {
+            if !allowed_categories.iter().any(|c| c.eq_ignore_ascii_case(category)) {
+                findings.push(LintFinding::new(
+                    LintSeverity::Warning,
+                    name,
+                    format!("category '{}' is not in the allowed list ({})", category, allowed_categories.join(", ")),
+                ));
+            }
+        }
+    }
+
+    findings
+}
@@ -0,0 +1,96 @@
+use crate::models::BrewPackage;
+use crate::utilities::fetch_packages;
+use console::style;
+
+/// Fetches and merges recipe files in order. A package keeps the position
+/// where it first appears; later files override its fields.
+pub async fn fetch_and_merge(urls: &[&String], verbose: bool) -> Result<Vec<BrewPackage>, String> {
+    if urls.is_empty() {
+        return Err("No URLs provided".to_string());
+    }
+    
+    if verbose {
+        println!("\n{} Fetching recipe files...", style("→").cyan().bold());
+    }
+    
+    let mut sources: Vec<(String, Vec<BrewPackage>)> = vec![];
+    
+    for (index, url) in urls.iter().enumerate() {
+        if verbose {
+            println!("  {} {}", 
+                style(format!("{}/{}:", index + 1, urls.len())).dim(),
+                style(url).cyan()
+            );
+        }
+        
+        match fetch_packages(url).await {
+            Ok(mut packages) => {
+                for package in &mut packages {
+                    package.source = Some(url.to_string());
+                }
+                if verbose {
+                    println!("    {} Loaded {} packages", 
+                        style("✓").green(),
+                        packages.len()
+                    );
+                }
+                sources.push((url.to_string(), packages));
+            }
+            Err(err) => {
+                if verbose {
+                    eprintln!("    {} Error: {}", style("✗").red(), err);
+                }
+                return Err(format!("Failed to fetch from {}: {}", url, err));
+            }
+        }
+    }
+    
+    let fetch_count = sources.len();
+    if fetch_count == 0 {
+        return Err("Failed to fetch any recipe files".to_string());
+    }
+    let merged: Vec<BrewPackage> = merge_sources(&sources).into_iter().cloned().collect();
+    log::info!(target: "merge", packages = merged.len(), sources = fetch_count; "Merged recipes");
+    
+    if verbose {
+        println!("\n{} Merged {} unique packages from {} recipe file(s)", 
+            style("✓").green().bold(),
+            style(merged.len()).cyan().bold(),
+            style(fetch_count).cyan().bold()
+        );
+    }
+    
+    Ok(merged)
+}
+
+/// Merges chained recipe sources by name. A package keeps the position
+/// where it first appears; later sources override its fields.
+pub fn merge_sources(sources: &[(String, Vec<BrewPackage>)]) -> Vec<&BrewPackage> {
+    let mut merged: Vec<(&str, &BrewPackage)> = vec![];
+    for (source, packages) in sources {
+        for package in packages {
+            match merged.iter().position(|(_, p)| p.name == package.name) {
+                Some(index) => {
+                    log::debug!(
+                        target: "merge",
+                        package = package.name.as_str(),
+                        source = source.as_str(),
+                        previous = merged[index].0;
+                        "Later recipe overrides package"
+                    );
+                    merged[index] = (source, package);
+                }
+                None => merged.push((source, package)),
+            }
+        }
+    }
+    merged.into_iter().map(|(_, package)| package).collect()
+}
+
+/// The source a `fetch_and_merge` error names, if any.
+pub fn failed_source<'a>(sources: &'a [String], error: &str) -> Option<&'a str> {
+    sources
+        .iter()
+        .find(|source| error.starts_with(&format!("Failed to fetch from {}: ", source)))
+        .map(String::as_str)
+}
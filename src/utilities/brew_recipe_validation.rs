@@ -0,0 +1,336 @@
+use crate::utilities::brim_conditions::Condition;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The recipe schema shipped with brim, also published as `recipe-schema.json`.
+pub const RECIPE_SCHEMA: &str = include_str!("../../recipe-schema.json");
+
+/// One problem found in a recipe, located by line and column (both 1-based).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub source: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.source, self.line, self.column, self.message)
+    }
+}
+
+/// Checks a recipe against the embedded schema and brim's semantic rules,
+/// returning every problem found rather than stopping at the first.
+pub fn validate_recipe(source: &str, text: &str) -> Vec<ValidationError> {
+    let instance: Value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(e) => {
+            return vec![ValidationError {
+                source: source.to_string(),
+                line: e.line(),
+                column: e.column(),
+                message: format!("invalid JSON: {}", e),
+            }]
+        }
+    };
+
+    let schema: Value = serde_json::from_str(RECIPE_SCHEMA).expect("embedded recipe schema is valid JSON");
+    let mut problems: Vec<(String, String)> = vec![];
+    check_schema(&schema, &instance, "", &mut problems);
+    check_semantics(&instance, &mut problems);
+
+    let positions = json_positions(text);
+    let mut errors: Vec<ValidationError> = problems
+        .into_iter()
+        .map(|(pointer, message)| {
+            let (line, column) = locate(&positions, &pointer);
+            ValidationError {
+                source: source.to_string(),
+                line,
+                column,
+                message,
+            }
+        })
+        .collect();
+
+    errors.sort_by_key(|e| (e.line, e.column));
+    errors
+}
+
+/// An error at the value `pointer` refers to in `text`.
+pub fn error_at(source: &str, text: &str, pointer: &str, message: String) -> ValidationError {
+    let (line, column) = locate(&json_positions(text), pointer);
+    ValidationError {
+        source: source.to_string(),
+        line,
+        column,
+        message,
+    }
+}
+
+// Supports the draft-07 keywords `recipe-schema.json` uses.
+fn check_schema(schema: &Value, instance: &Value, pointer: &str, problems: &mut Vec<(String, String)>) {
+    let at = |problems: &mut Vec<(String, String)>, message: String| {
+        problems.push((pointer.to_string(), message));
+    };
+    let subject = if pointer.is_empty() { "recipe".to_string() } else { format!("'{}'", pointer) };
+
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        if type_name(instance) != expected {
+            at(problems, format!("{} must be {} {}, found {}", subject, article(expected), expected, type_name(instance)));
+            return;
+        }
+    }
+
+    match instance {
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    at(problems, format!("{} must contain at least {} item(s)", subject, min));
+                }
+            }
+            if schema.get("uniqueItems").and_then(Value::as_bool) == Some(true) {
+                for (index, item) in items.iter().enumerate() {
+                    if let Some(first) = items[..index].iter().position(|other| other == item) {
+                        problems.push((
+                            format!("{}/{}", pointer, index),
+                            format!("item {} is identical to item {}", index, first),
+                        ));
+                    }
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    check_schema(item_schema, item, &format!("{}/{}", pointer, index), problems);
+                }
+            }
+        }
+        Value::Object(object) => check_object(schema, object, pointer, problems),
+        Value::String(text) => {
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if (text.chars().count() as u64) < min {
+                    at(problems, format!("{} must not be empty", subject));
+                }
+            }
+            if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                if Regex::new(pattern).is_ok_and(|re| !re.is_match(text)) {
+                    at(problems, format!("{} value '{}' does not match {}", subject, text, pattern));
+                }
+            }
+            if schema.get("format").and_then(Value::as_str) == Some("uri")
+                && !text.starts_with("http://")
+                && !text.starts_with("https://")
+            {
+                at(problems, format!("{} must be a URL starting with http:// or https://", subject));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_object(schema: &Value, object: &Map<String, Value>, pointer: &str, problems: &mut Vec<(String, String)>) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                problems.push((pointer.to_string(), format!("missing required property '{}'", key)));
+            }
+        }
+    }
+
+    for (key, value) in object {
+        let child = format!("{}/{}", pointer, escape_pointer(key));
+        match properties.and_then(|p| p.get(key)) {
+            Some(property_schema) => check_schema(property_schema, value, &child, problems),
+            None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                problems.push((child, format!("unknown property '{}'", key)));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Rules the schema cannot express.
+fn check_semantics(instance: &Value, problems: &mut Vec<(String, String)>) {
+    let Some(items) = instance.as_array() else {
+        return;
+    };
+
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (index, item) in items.iter().enumerate() {
+        if let Some(when) = item.get("when").and_then(Value::as_str) {
+            if let Err(err) = Condition::parse(when) {
+                problems.push((format!("/{}/when", index), format!("invalid when expression: {}", err)));
+            }
+        }
+
+        let Some(name) = item.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        match seen.get(name) {
+            // Identical items are already reported through `uniqueItems`.
+            Some(first) if items[*first] != *item => problems.push((
+                format!("/{}/name", index),
+                format!("package '{}' is already defined by item {}", name, first),
+            )),
+            Some(_) => {}
+            None => {
+                seen.insert(name, index);
+            }
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn article(type_name: &str) -> &'static str {
+    if type_name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// Falls back to the closest ancestor when a pointer has no recorded position.
+fn locate(positions: &HashMap<String, (usize, usize)>, pointer: &str) -> (usize, usize) {
+    let mut current = pointer;
+    loop {
+        if let Some(position) = positions.get(current) {
+            return *position;
+        }
+        match current.rfind('/') {
+            Some(index) => current = &current[..index],
+            None => return (1, 1),
+        }
+    }
+}
+
+/// Line and column of every value in `text`, keyed by JSON pointer.
+/// Expects syntactically valid JSON.
+pub fn json_positions(text: &str) -> HashMap<String, (usize, usize)> {
+    let mut scanner = Scanner {
+        chars: text.chars().collect(),
+        index: 0,
+        line: 1,
+        column: 1,
+        positions: HashMap::new(),
+    };
+    scanner.value(String::new());
+    scanner.positions
+}
+
+struct Scanner {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+    positions: HashMap<String, (usize, usize)>,
+}
+
+impl Scanner {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn value(&mut self, pointer: String) {
+        self.skip_whitespace();
+        self.positions.insert(pointer.clone(), (self.line, self.column));
+
+        match self.peek() {
+            Some('{') => {
+                self.bump();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        self.bump();
+                        break;
+                    }
+                    let key = self.string();
+                    self.skip_whitespace();
+                    self.bump(); // ':'
+                    self.value(format!("{}/{}", pointer, escape_pointer(&key)));
+                    self.skip_whitespace();
+                    if self.bump() != Some(',') {
+                        break;
+                    }
+                }
+            }
+            Some('[') => {
+                self.bump();
+                let mut index = 0;
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(']') {
+                        self.bump();
+                        break;
+                    }
+                    self.value(format!("{}/{}", pointer, index));
+                    index += 1;
+                    self.skip_whitespace();
+                    if self.bump() != Some(',') {
+                        break;
+                    }
+                }
+            }
+            Some('"') => {
+                self.string();
+            }
+            _ => {
+                while self.peek().is_some_and(|c| !matches!(c, ',' | ']' | '}') && !c.is_whitespace()) {
+                    self.bump();
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let start = self.index;
+        self.bump(); // opening quote
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '"' => break,
+                _ => {}
+            }
+        }
+        let raw: String = self.chars[start..self.index].iter().collect();
+        serde_json::from_str(&raw).unwrap_or_default()
+    }
+}
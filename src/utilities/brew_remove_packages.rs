@@ -1,21 +1,28 @@
 use crate::constants::PROGRAM;
-use crate::models::BrewPackage;
+use crate::models::{BrewPackage, FailureReason};
 use crate::tui::{ProgressState, ProgressTracker};
-use crate::webhook::PackageResult;
+use crate::utilities::brew_package_log::{attach_log_files, capture_output, failure_message, LogStream, PackageLog};
+use crate::webhook::{watch_packages, EventSink, PackageResult};
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Duration;
 
-pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<PackageResult> {
+pub fn remove_packages(
+    packages: &[BrewPackage],
+    _parallel: bool,
+    log_dir: Option<&Path>,
+    events: Option<&EventSink>,
+) -> Vec<PackageResult> {
     let package_names: Vec<String> = packages.iter().map(|p| p.name.clone()).collect();
 
     let mut tracker = match ProgressTracker::new(package_names) {
         Ok(t) => t,
         Err(e) => {
-            eprintln!("Failed to initialize TUI: {}", e);
+            log::error!(target: "tui", "Failed to initialize TUI: {}", e);
             return vec![];
         }
     };
@@ -25,6 +32,11 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
     let tracker_packages_for_result = Arc::clone(&tracker_packages);
     let cancelled = Arc::new(AtomicBool::new(false));
 
+    if let Some(dir) = log_dir {
+        attach_log_files(&tracker_packages, dir);
+    }
+    let _package_events = events.map(|sink| watch_packages(&tracker_packages, sink.clone()));
+
     let remove_threads: Vec<_> = {
         let packages = packages_arc.lock().unwrap();
         
@@ -43,6 +55,8 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
                         p.state = ProgressState::Removing;
                         p.progress = 10;
                         p.message = "Removing...".to_string();
+                        p.begin();
+                        p.attempts += 1;
                     }
                 }
 
@@ -57,14 +71,20 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
                     .stdout(Stdio::piped())
                     .stderr(Stdio::piped());
 
+                let log = PackageLog::for_package(&tracker_packages, index);
+                log.command(&command);
+
                 let mut child = match command.spawn() {
                     Ok(c) => c,
                     Err(e) => {
+                        log.note(&format!("spawn error: {}", e));
                         if let Ok(mut tracked) = tracker_packages.lock() {
                             if let Some(p) = tracked.get_mut(index) {
                                 p.state = ProgressState::Failed;
+                                p.failure = Some(FailureReason::from_io_error(&e));
                                 p.progress = 0;
                                 p.message = format!("Error: {}", e);
+                                p.finish();
                             }
                         }
                         return;
@@ -74,10 +94,12 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
                 let stdout = child.stdout.take().unwrap();
                 let stderr = child.stderr.take().unwrap();
                 let tracker_packages_clone = Arc::clone(&tracker_packages);
+                let stdout_log = Arc::clone(&log);
                 
                 let stdout_thread = thread::spawn(move || {
                     let reader = BufReader::new(stdout);
                     for line in reader.lines().map_while(Result::ok) {
+                        stdout_log.line(LogStream::Stdout, &line);
                         if let Ok(mut tracked) = tracker_packages_clone.try_lock() {
                                 if let Some(p) = tracked.get_mut(index) {
                                     p.progress = 50;
@@ -90,9 +112,11 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
                 });
 
                 let tracker_packages_clone = Arc::clone(&tracker_packages);
+                let stderr_log = Arc::clone(&log);
                 let stderr_thread = thread::spawn(move || {
                     let reader = BufReader::new(stderr);
                     for line in reader.lines().map_while(Result::ok) {
+                        stderr_log.line(LogStream::Stderr, &line);
                         if !line.trim().is_empty() && line.len() < 50 {
                                 if let Ok(mut tracked) = tracker_packages_clone.try_lock() {
                                     if let Some(p) = tracked.get_mut(index) {
@@ -144,6 +168,7 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
                 let _ = stderr_thread.join();
                 
                 let status = status.unwrap();
+                log.exited(status.as_ref().ok());
 
                 match status {
                     Ok(exit_status) if exit_status.success() => {
@@ -160,23 +185,28 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
                             .stdin(Stdio::null())
                             .stdout(Stdio::piped())
                             .stderr(Stdio::piped());
+                        log.command(&auto_cmd);
 
                         if let Ok(mut auto_child) = auto_cmd.spawn() {
+                            let (auto_stdout, auto_stderr) = capture_output(&mut auto_child, &log);
                             let mut auto_wait_count = 0;
-                            loop {
+                            let auto_status = loop {
                                 match auto_child.try_wait() {
-                                    Ok(Some(_)) => break,
+                                    Ok(Some(status)) => break Some(status),
                                     Ok(None) => {
                                         auto_wait_count += 1;
                                         if auto_wait_count > 600 {
                                             let _ = auto_child.kill();
-                                            break;
+                                            break None;
                                         }
                                         thread::sleep(Duration::from_millis(100));
                                     }
-                                    Err(_) => break,
+                                    Err(_) => break None,
                                 }
-                            }
+                            };
+                            let _ = auto_stdout.join();
+                            let _ = auto_stderr.join();
+                            log.exited(auto_status.as_ref());
                         }
 
                         if let Ok(mut tracked) = tracker_packages.lock() {
@@ -184,24 +214,32 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
                                 p.state = ProgressState::Completed;
                                 p.progress = 100;
                                 p.message = "Removed!".to_string();
+                                p.finish();
                             }
                         }
                     }
-                    Ok(_) => {
+                    Ok(exit_status) => {
+                        let reason = FailureReason::classify(exit_status.code(), &log.tail_lines());
                         if let Ok(mut tracked) = tracker_packages.lock() {
                             if let Some(p) = tracked.get_mut(index) {
                                 p.state = ProgressState::Failed;
+                                p.failure = Some(reason);
                                 p.progress = 0;
-                                p.message = "Removal failed".to_string();
+                                p.message = failure_message("Removal failed", &log);
+                                p.error = log.error_excerpt();
+                                p.finish();
                             }
                         }
                     }
                     Err(e) => {
+                        log.note(&e.to_string());
                         if let Ok(mut tracked) = tracker_packages.lock() {
                             if let Some(p) = tracked.get_mut(index) {
                                 p.state = ProgressState::Failed;
+                                p.failure = Some(FailureReason::from_io_error(&e));
                                 p.progress = 0;
                                 p.message = format!("Error: {}", e);
+                                p.finish();
                             }
                         }
                     }
@@ -227,11 +265,8 @@ pub fn remove_packages(packages: &[BrewPackage], _parallel: bool) -> Vec<Package
     }
     
     let guard = tracker_packages_for_result.lock();
-    if let Ok(packages) = guard {
-        packages.iter().map(|p| PackageResult {
-            name: p.name.clone(),
-            status: p.state_label().to_string(),
-        }).collect()
+    if let Ok(tracked) = guard {
+        PackageResult::for_packages(&tracked, packages)
     } else {
         vec![]
     }
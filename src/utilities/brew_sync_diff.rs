@@ -0,0 +1,45 @@
+use crate::models::BrewPackage;
+use crate::webhook::PackageResult;
+
+/// Difference between installed packages and a merged recipe.
+pub struct SyncDiff<'a> {
+    pub to_install: Vec<&'a BrewPackage>,
+    pub to_remove: Vec<&'a BrewPackage>,
+    pub in_sync: Vec<&'a BrewPackage>,
+}
+
+impl SyncDiff<'_> {
+    pub fn as_results(&self) -> Vec<PackageResult> {
+        let result = |package: &&BrewPackage, status: &str| PackageResult {
+            name: package.name.clone(),
+            status: status.to_string(),
+            reason: None,
+            ..Default::default()
+        };
+
+        self.in_sync.iter().map(|p| result(p, "in_sync"))
+            .chain(self.to_install.iter().map(|p| result(p, "missing")))
+            .chain(self.to_remove.iter().map(|p| result(p, "extra")))
+            .collect()
+    }
+}
+
+/// Splits `recipe` and `installed` into packages to install, to remove and already in sync.
+pub fn compute_sync_diff<'a>(installed: &'a [BrewPackage], recipe: &'a [BrewPackage]) -> SyncDiff<'a> {
+    let to_install: Vec<&BrewPackage> = recipe
+        .iter()
+        .filter(|pkg| !installed.iter().any(|inst| inst.name == pkg.name))
+        .collect();
+    
+    let to_remove: Vec<&BrewPackage> = installed
+        .iter()
+        .filter(|inst| !recipe.iter().any(|pkg| pkg.name == inst.name))
+        .collect();
+    
+    let in_sync: Vec<&BrewPackage> = recipe
+        .iter()
+        .filter(|pkg| installed.iter().any(|inst| inst.name == pkg.name))
+        .collect();
+
+    SyncDiff { to_install, to_remove, in_sync }
+}
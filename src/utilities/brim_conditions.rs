@@ -0,0 +1,235 @@
+use std::fmt;
+
+/// A parsed `when` expression, e.g. `tag:backend && !env:CI`.
+///
+/// Terms are `tag:NAME` (the machine has the tag), `env:NAME` (the variable
+/// is set and non-empty) and `env:NAME=VALUE`. They combine with `!`, `&&`,
+/// `||` and parentheses; `&&` binds tighter than `||`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Tag(String),
+    Env { name: String, value: Option<String> },
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Tag(tag) => write!(f, "tag:{}", tag),
+            Condition::Env { name, value: None } => write!(f, "env:{}", name),
+            Condition::Env { name, value: Some(value) } => write!(f, "env:{}={}", name, value),
+            Condition::Not(inner) => match **inner {
+                Condition::And(..) | Condition::Or(..) => write!(f, "!({})", inner),
+                _ => write!(f, "!{}", inner),
+            },
+            Condition::And(a, b) => {
+                let side = |c: &Condition| match c {
+                    Condition::Or(..) => format!("({})", c),
+                    _ => c.to_string(),
+                };
+                write!(f, "{} && {}", side(a), side(b))
+            }
+            Condition::Or(a, b) => write!(f, "{} || {}", a, b),
+        }
+    }
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, index: 0 };
+        let condition = parser.or()?;
+        match parser.tokens.get(parser.index) {
+            None => Ok(condition),
+            Some(token) => Err(format!("unexpected '{}'", token)),
+        }
+    }
+
+    /// Evaluates against the machine's `tags` (case-insensitive) and the environment via `env`.
+    pub fn eval(&self, tags: &[String], env: &impl Fn(&str) -> Option<String>) -> bool {
+        match self {
+            Condition::Tag(tag) => tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            Condition::Env { name, value: None } => env(name).is_some_and(|v| !v.is_empty()),
+            Condition::Env { name, value: Some(value) } => env(name).as_deref() == Some(value.as_str()),
+            Condition::Not(inner) => !inner.eval(tags, env),
+            Condition::And(a, b) => a.eval(tags, env) && b.eval(tags, env),
+            Condition::Or(a, b) => a.eval(tags, env) || b.eval(tags, env),
+        }
+    }
+
+    /// Which part made the expression false, e.g. `not tagged 'backend'` or `CI is set`.
+    /// Only meaningful when `eval` returned false.
+    pub fn explain(&self, tags: &[String], env: &impl Fn(&str) -> Option<String>) -> String {
+        match self {
+            Condition::Tag(tag) => format!("not tagged '{}'", tag),
+            Condition::Env { name, value: None } => format!("{} is not set", name),
+            Condition::Env { name, value: Some(value) } => match env(name) {
+                Some(actual) => format!("{} is '{}', not '{}'", name, actual, value),
+                None => format!("{} is not set", name),
+            },
+            Condition::Not(inner) => match **inner {
+                Condition::Tag(ref tag) => format!("tagged '{}'", tag),
+                Condition::Env { ref name, value: None } => format!("{} is set", name),
+                Condition::Env { ref name, value: Some(ref value) } => format!("{} is '{}'", name, value),
+                _ => format!("{} holds", inner),
+            },
+            Condition::And(a, b) => {
+                if a.eval(tags, env) {
+                    b.explain(tags, env)
+                } else {
+                    a.explain(tags, env)
+                }
+            }
+            Condition::Or(a, b) => format!("{} and {}", a.explain(tags, env), b.explain(tags, env)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Term(String),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Term(term) => write!(f, "{}", term),
+            Token::Not => write!(f, "!"),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens: Vec<Token> = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            '&' | '|' => {
+                let c = chars[i];
+                if chars.get(i + 1) != Some(&c) {
+                    return Err(format!("expected '{}{}'", c, c));
+                }
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+                i += 2;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !"!()&|".contains(chars[i]) {
+                    i += 1;
+                }
+                tokens.push(Token::Term(chars[start..i].iter().collect()));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.index) == Some(token) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut left = self.and()?;
+        while self.eat(&Token::Or) {
+            left = Condition::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut left = self.unary()?;
+        while self.eat(&Token::And) {
+            left = Condition::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Condition, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Condition::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let inner = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(inner)
+            }
+            Some(Token::Term(term)) => term_condition(&term),
+            Some(token) => Err(format!("unexpected '{}'", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn term_condition(term: &str) -> Result<Condition, String> {
+    let valid = |name: &str, extra: &str| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || extra.contains(c))
+    };
+
+    if let Some(tag) = term.strip_prefix("tag:") {
+        if !valid(tag, ".-") {
+            return Err(format!("invalid tag '{}'", tag));
+        }
+        return Ok(Condition::Tag(tag.to_string()));
+    }
+
+    if let Some(env) = term.strip_prefix("env:") {
+        let (name, value) = match env.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (env, None),
+        };
+        if !valid(name, "") {
+            return Err(format!("invalid environment variable '{}'", name));
+        }
+        return Ok(Condition::Env {
+            name: name.to_string(),
+            value,
+        });
+    }
+
+    Err(format!("unknown term '{}': use tag:NAME or env:NAME", term))
+}
@@ -0,0 +1,174 @@
+use crate::logging;
+use crate::models::BrewPackage;
+use crate::utilities::brim_conditions::Condition;
+use console::style;
+use std::env;
+use std::fs;
+use std::process::{Command, Stdio};
+
+/// The machine brim runs on, in the terms recipe conditions use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Platform {
+    /// `macos` or `linux`.
+    pub os: String,
+    /// `arm64` or `x86_64`.
+    pub arch: String,
+    /// macOS product version or the Linux distribution's `VERSION_ID`, if known.
+    pub os_version: Option<String>,
+    /// Machine tags that `when` expressions test with `tag:NAME`.
+    pub tags: Vec<String>,
+}
+
+/// A recipe entry whose conditions the current platform does not meet.
+#[derive(Debug, Clone)]
+pub struct SkippedPackage {
+    pub package: BrewPackage,
+    pub reason: String,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        let os = std::env::consts::OS.to_string();
+        let arch = match std::env::consts::ARCH {
+            "aarch64" => "arm64".to_string(),
+            other => other.to_string(),
+        };
+        let os_version = match os.as_str() {
+            "macos" => macos_version(),
+            "linux" => linux_version(),
+            _ => None,
+        };
+
+        Self {
+            os,
+            arch,
+            os_version,
+            tags: vec![],
+        }
+    }
+
+    pub fn with_tags(mut self, tags: &[String]) -> Self {
+        self.tags = tags.to_vec();
+        self
+    }
+
+    pub fn label(&self) -> String {
+        let mut label = match self.os_version {
+            Some(ref version) => format!("{} {} {}", self.os, version, self.arch),
+            None => format!("{} {}", self.os, self.arch),
+        };
+        if !self.tags.is_empty() {
+            label.push_str(&format!(" (tags: {})", self.tags.join(", ")));
+        }
+        label
+    }
+
+    /// Why `package` does not apply here, or `None` when it does.
+    /// Casks are skipped on Linux even without an `os` condition.
+    pub fn skip_reason(&self, package: &BrewPackage) -> Option<String> {
+        self.skip_reason_with_env(package, &|name| env::var(name).ok())
+    }
+
+    /// `skip_reason` with environment lookups for `env:` terms going through `env`.
+    pub fn skip_reason_with_env(&self, package: &BrewPackage, env: &impl Fn(&str) -> Option<String>) -> Option<String> {
+        if let Some(ref os) = package.os {
+            if !os.eq_ignore_ascii_case(&self.os) {
+                return Some(format!("requires {} (this is {})", os, self.os));
+            }
+        }
+        if package.cask.is_some() && self.os == "linux" {
+            return Some("casks are not supported on Linux".to_string());
+        }
+        if let Some(ref arch) = package.arch {
+            if !arch.eq_ignore_ascii_case(&self.arch) {
+                return Some(format!("requires {} (this is {})", arch, self.arch));
+            }
+        }
+        if let Some(ref minimum) = package.min_os_version {
+            match self.os_version {
+                Some(ref version) if version_at_least(version, minimum) => {}
+                Some(ref version) => {
+                    return Some(format!("requires {} {} or later (this is {})", self.os, minimum, version))
+                }
+                None => return Some(format!("requires {} {} or later (version unknown)", self.os, minimum)),
+            }
+        }
+        if let Some(ref when) = package.when {
+            return match Condition::parse(when) {
+                Ok(condition) if condition.eval(&self.tags, env) => None,
+                Ok(condition) => Some(format!("when \"{}\": {}", when, condition.explain(&self.tags, env))),
+                Err(err) => Some(format!("invalid when \"{}\": {}", when, err)),
+            };
+        }
+        None
+    }
+
+    /// Splits merged recipe entries into those that apply here and those that don't.
+    pub fn partition(&self, packages: Vec<BrewPackage>) -> (Vec<BrewPackage>, Vec<SkippedPackage>) {
+        let mut applicable: Vec<BrewPackage> = vec![];
+        let mut skipped: Vec<SkippedPackage> = vec![];
+
+        for package in packages {
+            match self.skip_reason(&package) {
+                Some(reason) => skipped.push(SkippedPackage { package, reason }),
+                None => applicable.push(package),
+            }
+        }
+
+        (applicable, skipped)
+    }
+}
+
+/// Compares dotted versions numerically; missing parts count as zero.
+pub fn version_at_least(version: &str, minimum: &str) -> bool {
+    let parts = |v: &str| -> Vec<u64> { v.split('.').map(|p| p.trim().parse().unwrap_or(0)).collect() };
+    let (version, minimum) = (parts(version), parts(minimum));
+    let len = version.len().max(minimum.len());
+
+    for i in 0..len {
+        let (have, want) = (version.get(i).copied().unwrap_or(0), minimum.get(i).copied().unwrap_or(0));
+        if have != want {
+            return have > want;
+        }
+    }
+    true
+}
+
+pub fn print_skipped_packages(skipped: &[SkippedPackage]) {
+    if skipped.is_empty() {
+        return;
+    }
+
+    println!("  {} Skipped on this machine:", style("→").dim().bold());
+    for (i, entry) in skipped.iter().enumerate() {
+        println!(
+            "    {} {} {}",
+            style(format!("{:2}.", i + 1)).dim(),
+            style(&entry.package.name).dim(),
+            style(format!("- {}", entry.reason)).dim()
+        );
+    }
+    println!();
+}
+
+fn macos_version() -> Option<String> {
+    let output = logging::output(
+        Command::new("sw_vers")
+            .arg("-productVersion")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null()),
+    )
+        .ok()
+        .filter(|o| o.status.success())?;
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn linux_version() -> Option<String> {
+    fs::read_to_string("/etc/os-release")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("VERSION_ID="))
+        .map(|v| v.trim_matches('"').to_string())
+        .filter(|v| !v.is_empty())
+}
@@ -0,0 +1,32 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Directory where brim keeps per-machine state (logs, run history, ...).
+///
+/// Follows `$XDG_STATE_HOME` when set and falls back to `~/.local/state/brim`.
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("brim");
+    }
+
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_else(env::temp_dir);
+    home.join(".local").join("state").join("brim")
+}
+
+/// Identifier for a single brim invocation, sortable by start time.
+pub fn new_run_id() -> String {
+    format!(
+        "{}-{}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        std::process::id()
+    )
+}
+
+/// Creates `<state_dir>/logs/<run_id>` and returns its path.
+pub fn create_run_log_dir(run_id: &str) -> io::Result<PathBuf> {
+    let dir = state_dir().join("logs").join(run_id);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
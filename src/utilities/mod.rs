@@ -1,10 +1,34 @@
+pub mod brew_export_packages;
 pub mod brew_fetch_packages;
 pub mod brew_formatting;
+pub mod brew_hooks;
 pub mod brew_install_packages;
 pub mod brew_list_installed_packages;
+pub mod brew_outdated_packages;
+pub mod brew_package_log;
+pub mod brew_package_metadata;
+pub mod brew_recipe_diff;
+pub mod brew_recipe_lint;
+pub mod brew_recipe_merge;
+pub mod brew_recipe_validation;
 pub mod brew_remove_packages;
+pub mod brew_sync_diff;
+pub mod brim_conditions;
+pub mod brim_platform;
+pub mod brim_state;
 
-pub use brew_fetch_packages::fetch_packages;
-pub use brew_install_packages::install_packages;
+pub use brew_export_packages::{build_recipe, list_requested_packages, parse_category_map, ExportOptions};
+pub use brew_fetch_packages::{fetch_packages, fetch_recipe_text};
+pub use brew_hooks::{apply_hook_policy, print_package_hooks, HookPolicy};
+pub use brew_install_packages::{default_jobs, install_packages, install_packages_headless, InstallOperation, InstallOptions};
 pub use brew_list_installed_packages::list_installed_packages;
+pub use brew_outdated_packages::{list_outdated_packages, pin_allows, OutdatedPackage};
+pub use brew_package_metadata::{renamed_formula, HomebrewIndex};
+pub use brew_recipe_diff::{diff_recipes, print_recipe_diff};
+pub use brew_recipe_lint::{fetch_metadata, lint_recipe, LintFinding, LintSeverity};
+pub use brew_recipe_merge::{failed_source, fetch_and_merge, merge_sources};
+pub use brew_recipe_validation::{error_at, validate_recipe};
 pub use brew_remove_packages::remove_packages;
+pub use brew_sync_diff::{compute_sync_diff, SyncDiff};
+pub use brim_platform::{print_skipped_packages, Platform, SkippedPackage};
+pub use brim_state::{create_run_log_dir, new_run_id};
@@ -0,0 +1,335 @@
+use crate::history::{newly_installed, save_history, RunRecord};
+use crate::metrics::{Metrics, MetricsState};
+use crate::models::BrewPackage;
+use crate::utilities::{
+    apply_hook_policy, compute_sync_diff, create_run_log_dir, failed_source, fetch_and_merge, install_packages_headless,
+    list_installed_packages, new_run_id, HookPolicy, InstallOperation, InstallOptions, Platform, SyncDiff,
+};
+use crate::webhook::{exit_code_for, flush_outbox, outbox_dir, Endpoint, EventDispatcher, FlushOptions, RunContext, WebhookPayload};
+use console::style;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// What `brim watch` does when the recipes and the machine drift apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchPolicy {
+    /// Log the drift and send it to the webhook, if any.
+    Report,
+    /// Install packages missing from the machine without asking.
+    Apply,
+}
+
+impl WatchPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "report" => Some(WatchPolicy::Report),
+            "apply" => Some(WatchPolicy::Apply),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WatchPolicy::Report => "report",
+            WatchPolicy::Apply => "apply",
+        }
+    }
+}
+
+pub struct WatchOptions {
+    pub urls: Vec<String>,
+    /// Only recipe packages in these categories are considered; empty means all.
+    pub categories: Vec<String>,
+    /// Machine tags for recipe `when` conditions.
+    pub tags: Vec<String>,
+    pub interval: Duration,
+    pub policy: WatchPolicy,
+    /// Webhook endpoints for drift, fetch failures and apply runs.
+    pub webhooks: Vec<Endpoint>,
+    /// How long failed webhook deliveries are retried from the outbox.
+    pub outbox_ttl: Duration,
+    /// Prometheus textfile updated after every check.
+    pub metrics: Option<Metrics>,
+}
+
+/// Parses an interval such as `45s`, `30m`, `2h` or `1d`; a bare number is seconds.
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid interval '{}': expected e.g. 30s, 30m, 2h", value))?;
+    let seconds = match unit {
+        "" | "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 60 * 60,
+        "d" => amount * 60 * 60 * 24,
+        _ => return Err(format!("Invalid interval unit '{}': use s, m, h or d", unit)),
+    };
+
+    if seconds == 0 {
+        return Err("Interval must be greater than zero".to_string());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// The reverse of `parse_interval`, in the largest unit that divides evenly.
+pub fn format_interval(interval: Duration) -> String {
+    let seconds = interval.as_secs();
+    [(60 * 60 * 24, "d"), (60 * 60, "h"), (60, "m")]
+        .iter()
+        .find(|(unit, _)| seconds > 0 && seconds.is_multiple_of(*unit))
+        .map(|(unit, suffix)| format!("{}{}", seconds / unit, suffix))
+        .unwrap_or_else(|| format!("{}s", seconds))
+}
+
+/// Stable description of a drift, used to skip reporting the same diff twice.
+pub fn drift_fingerprint(missing: &[&str], extra: &[&str]) -> String {
+    let mut entries: Vec<String> = missing
+        .iter()
+        .map(|name| format!("+{}", name))
+        .chain(extra.iter().map(|name| format!("-{}", name)))
+        .collect();
+    entries.sort();
+    entries.join(",")
+}
+
+/// Remembers the last drift that was fully handled so an unchanged drift is
+/// not reported again. A drift whose apply run failed is not remembered, so
+/// the next tick tries again.
+#[derive(Debug, Default)]
+pub struct DriftTracker {
+    settled: Option<String>,
+}
+
+impl DriftTracker {
+    /// Whether the drift described by `fingerprint` still needs handling.
+    pub fn is_new(&self, fingerprint: &str) -> bool {
+        self.settled.as_deref() != Some(fingerprint)
+    }
+
+    /// Records a handled drift; only `settled` ones are skipped on later ticks.
+    pub fn handled(&mut self, fingerprint: String, settled: bool) {
+        self.settled = settled.then_some(fingerprint);
+    }
+}
+
+/// Re-fetches the recipes every `interval` until interrupted, reporting or
+/// applying drift. Fetch errors are logged and retried on the next tick.
+pub async fn watch_recipes(options: WatchOptions) {
+    println!(
+        "\n{} Watching {} recipe source(s) every {} (policy: {}). Press Ctrl+C to stop.",
+        style("→").cyan().bold(),
+        options.urls.len(),
+        format_interval(options.interval),
+        options.policy.label()
+    );
+
+    let url_refs: Vec<&String> = options.urls.iter().collect();
+    let mut drift = DriftTracker::default();
+
+    loop {
+        retry_outbox(&options).await;
+        match fetch_and_merge(&url_refs, false).await {
+            Ok(recipe) => {
+                let (recipe, skipped) = Platform::current().with_tags(&options.tags).partition(recipe);
+                let recipe: Vec<BrewPackage> =
+                    recipe.into_iter().filter(|p| p.in_categories(&options.categories)).collect();
+                let installed: Vec<BrewPackage> = list_installed_packages()
+                    .into_iter()
+                    .filter(|p| !skipped.iter().any(|s| s.package.name == p.name))
+                    .collect();
+                let diff = compute_sync_diff(&installed, &recipe);
+                let missing: Vec<&str> = diff.to_install.iter().map(|p| p.name.as_str()).collect();
+                let extra: Vec<&str> = diff.to_remove.iter().map(|p| p.name.as_str()).collect();
+                let fingerprint = drift_fingerprint(&missing, &extra);
+                update_metrics(&options, |m| m.record_drift(missing.len(), extra.len()));
+
+                if drift.is_new(&fingerprint) {
+                    match handle_drift(&options, &diff, &installed).await {
+                        DriftOutcome::Stopped => {
+                            log_line("Stopped watching");
+                            return;
+                        }
+                        outcome => drift.handled(fingerprint, outcome == DriftOutcome::Settled),
+                    }
+                } else {
+                    log_line(&style("No change since last check").dim().to_string());
+                }
+            }
+            Err(err) => {
+                log_line(&format!("{} {} (retrying in {})", style("✗").red(), err, format_interval(options.interval)));
+                if let Some(source) = failed_source(&options.urls, &err) {
+                    update_metrics(&options, |m| m.record_fetch_error(source));
+                }
+                let events = start_events(&options, &new_run_id(), &chrono::Local::now().to_rfc3339());
+                events.emit(WebhookPayload::fetch_failed(events.run(), &err));
+                finish_events(events);
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(options.interval) => {}
+            _ = tokio::signal::ctrl_c() => {
+                log_line("Stopped watching");
+                return;
+            }
+        }
+    }
+}
+
+/// How handling one tick's drift ended.
+#[derive(Debug, PartialEq)]
+enum DriftOutcome {
+    /// Reported, or applied without failures.
+    Settled,
+    /// The apply run had failures to retry on the next tick.
+    Failed,
+    /// Ctrl+C interrupted the apply run.
+    Stopped,
+}
+
+/// Reports the drift and, under `--policy apply`, installs the missing
+/// packages. The install runs on a blocking thread so Ctrl+C can stop it.
+async fn handle_drift(options: &WatchOptions, diff: &SyncDiff<'_>, installed: &[BrewPackage]) -> DriftOutcome {
+    let started = Instant::now();
+    let run_id = new_run_id();
+    let started_at = chrono::Local::now().to_rfc3339();
+
+    if diff.to_install.is_empty() && diff.to_remove.is_empty() {
+        log_line(&format!("{} In sync with recipes", style("✓").green()));
+        return DriftOutcome::Settled;
+    }
+
+    log_line(&format!(
+        "{} Drift: {} missing, {} extra",
+        style("⚠").yellow().bold(),
+        diff.to_install.len(),
+        diff.to_remove.len()
+    ));
+    for package in &diff.to_install {
+        println!("    {} {}", style("+").green(), package.name);
+    }
+    for package in &diff.to_remove {
+        println!("    {} {}", style("-").yellow(), style(&package.name).dim());
+    }
+
+    let events = start_events(options, &run_id, &started_at);
+    events.emit(WebhookPayload::drift(events.run().finished(), diff.as_results(), started.elapsed().as_secs()));
+
+    if options.policy == WatchPolicy::Report || diff.to_install.is_empty() {
+        finish_events(events);
+        return DriftOutcome::Settled;
+    }
+
+    let mut packages: Vec<BrewPackage> = diff.to_install.iter().map(|p| (*p).clone()).collect();
+    // Nobody is around to confirm remote hooks, so only local ones run.
+    apply_hook_policy(&mut packages, HookPolicy::Local);
+
+    let log_dir = create_run_log_dir(&run_id).ok();
+    let install_options = InstallOptions {
+        operation: InstallOperation::Install,
+        parallel: false,
+        jobs: 1,
+        log_dir,
+        atomic: false,
+        preinstalled: installed.iter().map(|p| p.name.clone()).collect(),
+        events: Some(events.sink()),
+    };
+
+    let names: Vec<String> = packages.iter().map(|p| p.name.clone()).collect();
+    events.emit(WebhookPayload::run_started(events.run(), &names));
+    log_line(&format!("Installing {} missing package(s)", packages.len()));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let mut install = {
+        let cancelled = Arc::clone(&cancelled);
+        tokio::task::spawn_blocking(move || install_packages_headless(&packages, &install_options, &cancelled))
+    };
+    let mut stopped = false;
+    let joined = tokio::select! {
+        joined = &mut install => joined,
+        _ = tokio::signal::ctrl_c() => {
+            log_line("Stopping the install in progress");
+            stopped = true;
+            cancelled.store(true, Ordering::Relaxed);
+            install.await
+        }
+    };
+    let results = match joined {
+        Ok(results) => results,
+        Err(e) => {
+            log::error!(target: "watch", "Install task failed: {}", e);
+            finish_events(events);
+            return if stopped { DriftOutcome::Stopped } else { DriftOutcome::Failed };
+        }
+    };
+
+    let mut record = RunRecord::new(&run_id, "watch", &started_at);
+    record.sources = options.urls.clone();
+    record.selected = names;
+    record.newly_installed = newly_installed(&results, installed);
+    record.packages = results.clone();
+    save_history(&record);
+
+    let succeeded = exit_code_for(&results) == 0;
+    if !succeeded && !stopped {
+        log_line(&format!("{} Apply failed; retrying on the next check", style("✗").red()));
+    }
+    let payload = WebhookPayload::from_results(RunContext::from_record(&record), results, started.elapsed().as_secs(), false);
+    update_metrics(options, |m| m.record_run(&payload));
+    events.emit(payload);
+    finish_events(events);
+    match (stopped, succeeded) {
+        (true, _) => DriftOutcome::Stopped,
+        (false, true) => DriftOutcome::Settled,
+        (false, false) => DriftOutcome::Failed,
+    }
+}
+
+fn update_metrics(options: &WatchOptions, change: impl FnOnce(&mut MetricsState)) {
+    if let Some(ref metrics) = options.metrics {
+        if let Err(e) = metrics.update(change) {
+            log::warn!(target: "metrics", "Failed to update metrics: {}", e);
+        }
+    }
+}
+
+fn start_events(options: &WatchOptions, run_id: &str, started_at: &str) -> EventDispatcher {
+    EventDispatcher::start(options.webhooks.clone(), RunContext::new(run_id, "watch", started_at, &options.urls))
+}
+
+fn finish_events(events: EventDispatcher) {
+    for error in events.finish().errors {
+        log::warn!(target: "webhook", "Failed to send webhook: {}", error);
+    }
+}
+
+/// Gives queued webhooks another try each tick, logging only what changed.
+async fn retry_outbox(options: &WatchOptions) {
+    let flush = FlushOptions {
+        ttl: options.outbox_ttl,
+        retries: Some(0),
+        stop_on_failure: true,
+    };
+    let report = flush_outbox(&outbox_dir(), &options.webhooks, &flush).await;
+    if report.delivered > 0 {
+        log_line(&format!("{} Delivered {} queued webhook(s)", style("✓").green(), report.delivered));
+    }
+    if report.expired > 0 {
+        log::warn!(target: "webhook", "Dropped {} queued webhook(s) older than webhook_outbox_ttl", report.expired);
+    }
+    for entry in report.rejected {
+        log::warn!(target: "webhook", "Dropped {} for run {}: {}", entry.event, entry.run_id, entry.last_error);
+    }
+}
+
+fn log_line(message: &str) {
+    println!(
+        "{} {}",
+        style(chrono::Local::now().format("[%Y-%m-%d %H:%M:%S]")).dim(),
+        message
+    );
+}
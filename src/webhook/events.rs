@@ -0,0 +1,340 @@
+use super::outbox::{save_entry, OutboxEntry};
+use super::{endpoint_host, post_body, PackageResult, RunContext, WebhookPayload, WebhookTarget};
+use crate::tui::progress::PackageProgress;
+use crate::tui::ProgressState;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A point in a run's lifecycle that can be posted to webhook endpoints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebhookEvent {
+    RunStarted,
+    FetchFailed,
+    PackageStarted,
+    PackageCompleted,
+    PackageFailed,
+    RunFinished,
+    DriftDetected,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 7] = [
+        WebhookEvent::RunStarted,
+        WebhookEvent::FetchFailed,
+        WebhookEvent::PackageStarted,
+        WebhookEvent::PackageCompleted,
+        WebhookEvent::PackageFailed,
+        WebhookEvent::RunFinished,
+        WebhookEvent::DriftDetected,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::RunStarted => "run.started",
+            WebhookEvent::FetchFailed => "fetch.failed",
+            WebhookEvent::PackageStarted => "package.started",
+            WebhookEvent::PackageCompleted => "package.completed",
+            WebhookEvent::PackageFailed => "package.failed",
+            WebhookEvent::RunFinished => "run.finished",
+            WebhookEvent::DriftDetected => "drift.detected",
+        }
+    }
+}
+
+/// Events an endpoint receives when it doesn't list any: the final summary and drift.
+pub const DEFAULT_EVENTS: [&str; 2] = ["run.finished", "drift.detected"];
+
+/// Whether `pattern` selects `event`: an exact name, `*`, or a prefix such as `package.*`.
+pub fn event_matches(pattern: &str, event: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => event.starts_with(prefix),
+        None => pattern == event,
+    }
+}
+
+/// Checks that every pattern selects at least one event.
+pub fn parse_event_filter(patterns: &[String]) -> Result<Vec<String>, String> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            let pattern = pattern.trim();
+            if WebhookEvent::ALL.iter().any(|e| event_matches(pattern, e.name())) {
+                Ok(pattern.to_string())
+            } else {
+                let names: Vec<&str> = WebhookEvent::ALL.iter().map(|e| e.name()).collect();
+                Err(format!("Unknown webhook event '{}': use {} or a prefix such as package.*", pattern, names.join(", ")))
+            }
+        })
+        .collect()
+}
+
+/// A webhook target and the events it subscribes to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub target: WebhookTarget,
+    pub events: Vec<String>,
+}
+
+impl Endpoint {
+    pub fn accepts(&self, event: &str) -> bool {
+        self.events.iter().any(|pattern| event_matches(pattern, event))
+    }
+}
+
+enum Message {
+    Deliver(usize, Box<WebhookPayload>),
+    Finish,
+}
+
+/// Outcome of every delivery a dispatcher made.
+#[derive(Debug, Default)]
+pub struct DeliveryReport {
+    pub sent: usize,
+    /// Failed deliveries written to the outbox for a later run to retry.
+    pub queued: usize,
+    pub errors: Vec<String>,
+}
+
+/// Queues events for the dispatcher's endpoints. Cheap to clone into worker threads.
+#[derive(Clone)]
+pub struct EventSink {
+    run: RunContext,
+    endpoints: Arc<Vec<Endpoint>>,
+    sender: Option<Sender<Message>>,
+}
+
+impl EventSink {
+    /// Run context for an event raised now; `finished_at` stays empty until the run ends.
+    pub fn run(&self) -> RunContext {
+        self.run.clone()
+    }
+
+    /// Queues `payload` for every endpoint subscribed to its event.
+    pub fn emit(&self, payload: WebhookPayload) {
+        let Some(ref sender) = self.sender else {
+            return;
+        };
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            if endpoint.accepts(&payload.event) {
+                let _ = sender.send(Message::Deliver(index, Box::new(payload.clone())));
+            }
+        }
+    }
+}
+
+/// Posts a run's events in the order they were raised, from a background
+/// thread so package events go out while brew is still working. Errors are
+/// collected rather than printed, since the progress view may own the terminal.
+/// Deliveries that fail for a reason that may pass are queued in the outbox.
+pub struct EventDispatcher {
+    sink: EventSink,
+    worker: Option<JoinHandle<DeliveryReport>>,
+}
+
+impl EventDispatcher {
+    /// Starts a dispatcher for `run`; with no endpoints every event is dropped.
+    pub fn start(endpoints: Vec<Endpoint>, run: RunContext) -> Self {
+        Self::with_outbox(endpoints, run, Some(super::outbox_dir()))
+    }
+
+    /// Like `start`, queuing failed deliveries in `outbox`, or dropping them when `None`.
+    pub fn with_outbox(endpoints: Vec<Endpoint>, run: RunContext, outbox: Option<PathBuf>) -> Self {
+        if endpoints.is_empty() {
+            return Self {
+                sink: EventSink {
+                    run,
+                    endpoints: Arc::new(endpoints),
+                    sender: None,
+                },
+                worker: None,
+            };
+        }
+
+        let endpoints = Arc::new(endpoints);
+        let (sender, receiver) = mpsc::channel();
+        let worker = {
+            let endpoints = Arc::clone(&endpoints);
+            thread::spawn(move || deliver(&endpoints, outbox, receiver))
+        };
+
+        Self {
+            sink: EventSink {
+                run,
+                endpoints,
+                sender: Some(sender),
+            },
+            worker: Some(worker),
+        }
+    }
+
+    pub fn sink(&self) -> EventSink {
+        self.sink.clone()
+    }
+
+    pub fn run(&self) -> RunContext {
+        self.sink.run()
+    }
+
+    pub fn emit(&self, payload: WebhookPayload) {
+        self.sink.emit(payload);
+    }
+
+    /// Waits for every queued event to be delivered or given up on.
+    pub fn finish(self) -> DeliveryReport {
+        let Some(worker) = self.worker else {
+            return DeliveryReport::default();
+        };
+        if let Some(ref sender) = self.sink.sender {
+            let _ = sender.send(Message::Finish);
+        }
+        worker.join().unwrap_or_default()
+    }
+}
+
+fn deliver(endpoints: &[Endpoint], outbox: Option<PathBuf>, receiver: Receiver<Message>) -> DeliveryReport {
+    let mut report = DeliveryReport::default();
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            report.errors.push(format!("Failed to start webhook delivery: {}", e));
+            return report;
+        }
+    };
+
+    while let Ok(Message::Deliver(index, payload)) = receiver.recv() {
+        let target = &endpoints[index].target;
+        let body = match target.body(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                report.errors.push(format!("{}: {}", target.url, e));
+                continue;
+            }
+        };
+
+        let failure = match runtime.block_on(post_body(target, &body)) {
+            Ok(()) => {
+                log::info!(
+                    target: "webhook",
+                    event = payload.event.as_str(),
+                    endpoint = endpoint_host(&target.url).as_str();
+                    "Delivered event"
+                );
+                report.sent += 1;
+                continue;
+            }
+            Err(failure) => failure,
+        };
+        let queued = match outbox {
+            Some(ref dir) if failure.retryable => {
+                let entry = OutboxEntry::new(&target.url, &payload.event, &payload.run.run_id, &body, &failure);
+                match save_entry(dir, &entry) {
+                    Ok(()) => {
+                        log::info!(
+                            target: "webhook",
+                            event = payload.event.as_str(),
+                            id = entry.id.as_str();
+                            "Queued event in the outbox"
+                        );
+                        true
+                    }
+                    Err(e) => {
+                        report.errors.push(format!("Failed to queue webhook in {}: {}", dir.display(), e));
+                        false
+                    }
+                }
+            }
+            _ => false,
+        };
+        if queued {
+            report.queued += 1;
+            report.errors.push(format!("{}: {} (queued for retry)", target.url, failure.message));
+        } else {
+            report.errors.push(format!("{}: {}", target.url, failure.message));
+        }
+    }
+    report
+}
+
+/// Raises `package.*` events as packages in a progress list start and finish.
+/// Stops, after a last look at the list, when dropped.
+pub struct PackageEvents {
+    stop: Arc<AtomicBool>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+/// Starts watching `packages`, polling a few times a second.
+pub fn watch_packages(packages: &Arc<Mutex<Vec<PackageProgress>>>, sink: EventSink) -> PackageEvents {
+    let stop = Arc::new(AtomicBool::new(false));
+    let watcher = {
+        let packages = Arc::clone(packages);
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let mut seen = PackagesSeen::default();
+            while !stop.load(Ordering::Relaxed) {
+                seen.check(&packages, &sink);
+                thread::sleep(Duration::from_millis(250));
+            }
+            seen.check(&packages, &sink);
+        })
+    };
+
+    PackageEvents {
+        stop,
+        watcher: Some(watcher),
+    }
+}
+
+impl Drop for PackageEvents {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(watcher) = self.watcher.take() {
+            let _ = watcher.join();
+        }
+    }
+}
+
+#[derive(Default)]
+struct PackagesSeen {
+    started: Vec<bool>,
+    finished: Vec<bool>,
+}
+
+impl PackagesSeen {
+    fn check(&mut self, packages: &Arc<Mutex<Vec<PackageProgress>>>, sink: &EventSink) {
+        let Ok(packages) = packages.lock() else {
+            return;
+        };
+        self.started.resize(packages.len(), false);
+        self.finished.resize(packages.len(), false);
+
+        for (index, package) in packages.iter().enumerate() {
+            if package.started.is_some() && !self.started[index] {
+                self.started[index] = true;
+                sink.emit(WebhookPayload::package_event(
+                    WebhookEvent::PackageStarted,
+                    sink.run(),
+                    PackageResult {
+                        name: package.name.clone(),
+                        status: "started".to_string(),
+                        ..Default::default()
+                    },
+                ));
+            }
+
+            // `finish` and the final state are set under the same lock.
+            let event = match package.state {
+                ProgressState::Completed => WebhookEvent::PackageCompleted,
+                ProgressState::Failed => WebhookEvent::PackageFailed,
+                _ => continue,
+            };
+            if package.duration.is_some() && !self.finished[index] {
+                self.finished[index] = true;
+                sink.emit(WebhookPayload::package_event(event, sink.run(), PackageResult::from_progress(package)));
+            }
+        }
+    }
+}
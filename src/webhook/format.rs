@@ -0,0 +1,321 @@
+use super::{PackageResult, WebhookPayload};
+use serde_json::{json, Value};
+
+/// How a payload is shaped before it is posted.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum WebhookFormat {
+    /// The `WebhookPayload` JSON itself.
+    #[default]
+    Generic,
+    /// A Slack incoming-webhook message with blocks.
+    Slack,
+    /// A Discord webhook message with one embed.
+    Discord,
+    /// A Microsoft Teams connector message card.
+    Teams,
+}
+
+impl WebhookFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "generic" => Some(WebhookFormat::Generic),
+            "slack" => Some(WebhookFormat::Slack),
+            "discord" => Some(WebhookFormat::Discord),
+            "teams" => Some(WebhookFormat::Teams),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WebhookFormat::Generic => "generic",
+            WebhookFormat::Slack => "slack",
+            WebhookFormat::Discord => "discord",
+            WebhookFormat::Teams => "teams",
+        }
+    }
+}
+
+/// Packages listed by name in a chat message before the rest are counted.
+const MAX_LISTED: usize = 20;
+
+/// Request body for `payload` in `format`.
+pub fn render(payload: &WebhookPayload, format: WebhookFormat) -> Result<Vec<u8>, String> {
+    let body = match format {
+        WebhookFormat::Generic => return serialize(payload),
+        WebhookFormat::Slack => slack(payload),
+        WebhookFormat::Discord => discord(payload),
+        WebhookFormat::Teams => teams(payload),
+    };
+    serde_json::to_vec(&body).map_err(|e| format!("Failed to serialize webhook payload: {}", e))
+}
+
+fn serialize(payload: &WebhookPayload) -> Result<Vec<u8>, String> {
+    serde_json::to_vec(payload).map_err(|e| format!("Failed to serialize webhook payload: {}", e))
+}
+
+/// One-line summary, e.g. `brim install on dev-laptop: partial, 9 completed, 1 failed`.
+pub fn summary(payload: &WebhookPayload) -> String {
+    let package = payload.package.as_ref().map(|p| p.name.as_str()).unwrap_or_default();
+    let outcome = match (payload.event.as_str(), payload.status.as_str()) {
+        ("run.started", _) => format!("started, {} package(s)", payload.total),
+        ("package.started", _) => format!("{} started", package),
+        ("package.completed", _) => format!("{} completed", package),
+        ("package.failed", _) => format!("{} failed", package),
+        ("fetch.failed", _) => "recipe fetch failed".to_string(),
+        (_, "drift") => format!("drift, {} missing, {} extra", count(payload, "missing"), count(payload, "extra")),
+        (_, "dry_run") => format!("dry run, {} package(s) planned", payload.total),
+        (_, "cancelled") | (_, "failed") => payload.status.clone(),
+        (_, status) if payload.failed > 0 => {
+            format!("{}, {} completed, {} failed", status, payload.completed, payload.failed)
+        }
+        (_, status) => format!("{}, {} of {} completed", status, payload.completed, payload.total),
+    };
+    format!("brim {} on {}: {}", payload.run.operation, payload.run.hostname, outcome)
+}
+
+fn count(payload: &WebhookPayload, status: &str) -> usize {
+    payload.packages.iter().filter(|p| p.status == status).count()
+}
+
+/// How an event went: good, bad, worth a look, or still in progress.
+#[derive(PartialEq)]
+enum Tone {
+    Good,
+    Bad,
+    Warning,
+    Neutral,
+}
+
+fn tone(payload: &WebhookPayload) -> Tone {
+    match (payload.event.as_str(), payload.status.as_str()) {
+        ("package.completed", _) | (_, "success") => Tone::Good,
+        ("package.failed", _) | ("fetch.failed", _) => Tone::Bad,
+        (_, "drift") | (_, "cancelled") => Tone::Warning,
+        (_, "running") | (_, "dry_run") => Tone::Neutral,
+        _ => Tone::Bad,
+    }
+}
+
+fn icon(payload: &WebhookPayload) -> &'static str {
+    match tone(payload) {
+        Tone::Good => "✅",
+        Tone::Bad => "❌",
+        Tone::Warning => "⚠️",
+        Tone::Neutral => "⏳",
+    }
+}
+
+/// Green, red, amber or grey as `0xRRGGBB`.
+fn color(payload: &WebhookPayload) -> u32 {
+    match tone(payload) {
+        Tone::Good => 0x2eb67d,
+        Tone::Bad => 0xe01e5a,
+        Tone::Warning => 0xecb22e,
+        Tone::Neutral => 0x8d8d8d,
+    }
+}
+
+/// `name (reason): first error line` for a failed package.
+pub fn failure_line(package: &PackageResult) -> String {
+    let mut line = package.name.clone();
+    if let Some(reason) = package.reason {
+        line.push_str(&format!(" ({})", reason.label()));
+    }
+    if let Some(error) = package.error.as_deref().and_then(|e| e.lines().next()) {
+        line.push_str(&format!(": {}", error.trim()));
+    }
+    line
+}
+
+/// Names of the packages with `status`, cut off after `MAX_LISTED`.
+fn names_with(payload: &WebhookPayload, status: &str) -> Vec<String> {
+    let names: Vec<String> = payload
+        .packages
+        .iter()
+        .filter(|p| p.status == status)
+        .map(|p| p.name.clone())
+        .collect();
+    truncate(names)
+}
+
+fn failures(payload: &WebhookPayload) -> Vec<String> {
+    truncate(
+        payload
+            .packages
+            .iter()
+            .chain(payload.package.as_ref())
+            .filter(|p| p.status == "failed")
+            .map(failure_line)
+            .collect(),
+    )
+}
+
+fn truncate(mut lines: Vec<String>) -> Vec<String> {
+    if lines.len() > MAX_LISTED {
+        let rest = lines.len() - MAX_LISTED;
+        lines.truncate(MAX_LISTED);
+        lines.push(format!("and {} more", rest));
+    }
+    lines
+}
+
+/// Labelled lists for the message body: failures, missing and extra on drift, or the run's error.
+fn sections(payload: &WebhookPayload) -> Vec<(&'static str, Vec<String>)> {
+    let mut sections = if payload.status == "drift" {
+        vec![("Missing", names_with(payload, "missing")), ("Extra", names_with(payload, "extra"))]
+    } else {
+        vec![("Failed", failures(payload))]
+    };
+    if let Some(ref error) = payload.error {
+        sections.push(("Error", error.lines().map(String::from).collect()));
+    }
+    sections.into_iter().filter(|(_, lines)| !lines.is_empty()).collect()
+}
+
+fn context(payload: &WebhookPayload) -> String {
+    let run = &payload.run;
+    format!(
+        "run {} · {} {} · brim {} · {}s",
+        run.run_id, run.os, run.arch, run.brim_version, payload.elapsed_seconds
+    )
+}
+
+fn slack(payload: &WebhookPayload) -> Value {
+    let mut blocks = vec![json!({
+        "type": "section",
+        "text": {"type": "mrkdwn", "text": format!("{} *{}*", icon(payload), summary(payload))},
+    })];
+    for (label, lines) in sections(payload) {
+        let list: Vec<String> = lines.iter().map(|l| format!("• `{}`", l)).collect();
+        blocks.push(json!({
+            "type": "section",
+            // Slack rejects section text over 3000 characters.
+            "text": {"type": "mrkdwn", "text": clip(&format!("*{}*\n{}", label, list.join("\n")), 3000)},
+        }));
+    }
+    blocks.push(json!({
+        "type": "context",
+        "elements": [{"type": "mrkdwn", "text": context(payload)}],
+    }));
+
+    json!({"text": summary(payload), "blocks": blocks})
+}
+
+fn discord(payload: &WebhookPayload) -> Value {
+    let fields: Vec<Value> = sections(payload)
+        .into_iter()
+        .map(|(label, lines)| {
+            let list: Vec<String> = lines.iter().map(|l| format!("**{}**", l)).collect();
+            // Discord rejects field values over 1024 characters.
+            json!({"name": label, "value": clip(&list.join("\n"), 1024)})
+        })
+        .collect();
+
+    json!({
+        "embeds": [{
+            "title": format!("{} {}", icon(payload), summary(payload)),
+            "color": color(payload),
+            "fields": fields,
+            "footer": {"text": context(payload)},
+            "timestamp": payload.run.finished_at,
+        }],
+    })
+}
+
+fn teams(payload: &WebhookPayload) -> Value {
+    let run = &payload.run;
+    let mut blocks = vec![json!({
+        "facts": [
+            {"name": "Run", "value": run.run_id},
+            {"name": "Host", "value": format!("{} ({} {})", run.hostname, run.os, run.arch)},
+            {"name": "Elapsed", "value": format!("{}s", payload.elapsed_seconds)},
+        ],
+    })];
+    for (label, lines) in sections(payload) {
+        let list: Vec<String> = lines.iter().map(|l| format!("- **{}**", l)).collect();
+        blocks.push(json!({"activityTitle": label, "text": list.join("\n")}));
+    }
+
+    json!({
+        "@type": "MessageCard",
+        "@context": "https://schema.org/extensions",
+        "summary": summary(payload),
+        "themeColor": format!("{:06X}", color(payload)),
+        "title": format!("{} {}", icon(payload), summary(payload)),
+        "sections": blocks,
+    })
+}
+
+fn clip(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut clipped: String = text.chars().take(max - 1).collect();
+    clipped.push('…');
+    clipped
+}
+
+/// Fills a user template's `{{placeholder}}`s from `payload`.
+///
+/// Values are JSON-escaped without quotes, so placeholders belong inside JSON
+/// strings; `{{payload}}` is the generic payload as raw JSON.
+pub fn render_template(template: &str, payload: &WebhookPayload) -> Result<Vec<u8>, String> {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "Invalid webhook template: unclosed '{{'".to_string())?;
+        let name = after[..end].trim();
+
+        if name == "payload" {
+            let json = serialize(payload)?;
+            output.push_str(&String::from_utf8_lossy(&json));
+        } else {
+            let value = placeholder(name, payload)
+                .ok_or_else(|| format!("Invalid webhook template: unknown placeholder '{{{{{}}}}}'", name))?;
+            let escaped = Value::String(value).to_string();
+            output.push_str(&escaped[1..escaped.len() - 1]);
+        }
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(output.into_bytes())
+}
+
+/// Text value of a template placeholder other than `payload`.
+pub fn placeholder(name: &str, payload: &WebhookPayload) -> Option<String> {
+    let run = &payload.run;
+    let value = match name {
+        "summary" => summary(payload),
+        "event" => payload.event.clone(),
+        "package" => payload.package.as_ref().map(|p| p.name.clone()).unwrap_or_default(),
+        "error" => payload.error.clone().unwrap_or_default(),
+        "icon" => icon(payload).to_string(),
+        "status" => payload.status.clone(),
+        "operation" => run.operation.clone(),
+        "run_id" => run.run_id.clone(),
+        "hostname" => run.hostname.clone(),
+        "os" => run.os.clone(),
+        "os_version" => run.os_version.clone().unwrap_or_default(),
+        "arch" => run.arch.clone(),
+        "brim_version" => run.brim_version.clone(),
+        "sources" => run.sources.join(", "),
+        "started_at" => run.started_at.clone(),
+        "finished_at" => run.finished_at.clone(),
+        "total" => payload.total.to_string(),
+        "completed" => payload.completed.to_string(),
+        "failed" => payload.failed.to_string(),
+        "elapsed_seconds" => payload.elapsed_seconds.to_string(),
+        "failed_packages" => failures(payload).join("\n"),
+        "missing_packages" => names_with(payload, "missing").join("\n"),
+        "extra_packages" => names_with(payload, "extra").join("\n"),
+        _ => return None,
+    };
+    Some(value)
+}
@@ -1,34 +1,453 @@
-use serde::Serialize;
-use std::time::Duration;
+use crate::history::RunRecord;
+use crate::logging;
+use crate::models::{BrewPackage, FailureReason};
+use crate::tui::progress::PackageProgress;
+use crate::utilities::brim_platform::Platform;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 
-#[derive(Serialize)]
+pub mod events;
+pub mod format;
+pub mod outbox;
+
+pub use events::{watch_packages, Endpoint, EventDispatcher, EventSink, WebhookEvent, DEFAULT_EVENTS};
+pub use format::{render, render_template, WebhookFormat};
+pub use outbox::{flush_outbox, outbox_dir, FlushOptions, FlushReport, DEFAULT_OUTBOX_TTL};
+
+/// Bumped whenever a payload field is removed or changes meaning.
+/// Version 1 was the unversioned summary without run context.
+pub const WEBHOOK_SCHEMA_VERSION: u32 = 2;
+
+/// Which run a payload describes and the machine it ran on.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunContext {
+    pub run_id: String,
+    /// `install`, `upgrade`, `remove`, `sync`, `watch` or `undo`.
+    pub operation: String,
+    pub hostname: String,
+    pub os: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    pub arch: String,
+    pub brim_version: String,
+    pub sources: Vec<String>,
+    /// RFC 3339 timestamps; `finished_at` is left out until the run ends.
+    pub started_at: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub finished_at: String,
+}
+
+impl RunContext {
+    /// Context for a run on this machine that has not finished yet.
+    pub fn new(run_id: &str, operation: &str, started_at: &str, sources: &[String]) -> Self {
+        let platform = Platform::current();
+        Self {
+            run_id: run_id.to_string(),
+            operation: operation.to_string(),
+            hostname: hostname(),
+            os: platform.os,
+            os_version: platform.os_version,
+            arch: platform.arch,
+            brim_version: env!("CARGO_PKG_VERSION").to_string(),
+            sources: sources.to_vec(),
+            started_at: started_at.to_string(),
+            finished_at: String::new(),
+        }
+    }
+
+    /// Context for `record` on this machine, finishing now.
+    pub fn from_record(record: &RunRecord) -> Self {
+        Self::new(&record.id, &record.operation, &record.timestamp, &record.sources).finished()
+    }
+
+    /// This context with the run finishing now.
+    pub fn finished(mut self) -> Self {
+        self.finished_at = chrono::Local::now().to_rfc3339();
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct WebhookPayload {
+    pub schema_version: u32,
+    /// One of the `WebhookEvent` names, e.g. `run.finished`.
+    pub event: String,
+    /// When the event was raised (RFC 3339).
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub run: RunContext,
     pub status: String,
     pub total: usize,
     pub completed: usize,
     pub failed: usize,
     pub packages: Vec<PackageResult>,
     pub elapsed_seconds: u64,
+    /// The package a `package.*` event is about.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<PackageResult>,
+    /// Why a run or recipe fetch failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
-#[derive(Serialize)]
+impl WebhookPayload {
+    /// Summarises per-package results; `rolled_back` marks an atomic run that was reverted.
+    pub fn from_results(run: RunContext, results: Vec<PackageResult>, elapsed_seconds: u64, rolled_back: bool) -> Self {
+        let completed = results.iter().filter(|r| r.status == "completed").count();
+        let failed = results.iter().filter(|r| r.status == "failed").count();
+
+        let status = if rolled_back {
+            "rolled_back"
+        } else if failed > 0 {
+            "partial"
+        } else {
+            "success"
+        };
+
+        Self {
+            status: status.to_string(),
+            total: results.len(),
+            completed,
+            failed,
+            packages: results,
+            elapsed_seconds,
+            ..Self::event(WebhookEvent::RunFinished, run)
+        }
+    }
+
+    /// Recipe drift found by `--sync` or `brim watch`; `results` carry `missing` and `extra` statuses.
+    pub fn drift(run: RunContext, results: Vec<PackageResult>, elapsed_seconds: u64) -> Self {
+        Self {
+            status: "drift".to_string(),
+            total: results.len(),
+            packages: results,
+            elapsed_seconds,
+            ..Self::event(WebhookEvent::DriftDetected, run)
+        }
+    }
+
+    /// An empty payload for `event`, raised now.
+    fn event(event: WebhookEvent, run: RunContext) -> Self {
+        Self {
+            schema_version: WEBHOOK_SCHEMA_VERSION,
+            event: event.name().to_string(),
+            timestamp: chrono::Local::now().to_rfc3339(),
+            run,
+            ..Default::default()
+        }
+    }
+
+    /// `run.started` for a run about to work on `packages`, all still `pending`.
+    pub fn run_started(run: RunContext, packages: &[String]) -> Self {
+        let pending = packages
+            .iter()
+            .map(|name| PackageResult {
+                name: name.clone(),
+                status: "pending".to_string(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        Self {
+            status: "running".to_string(),
+            total: pending.len(),
+            packages: pending,
+            ..Self::event(WebhookEvent::RunStarted, run)
+        }
+    }
+
+    /// A `package.*` event about one package while the run is in progress.
+    pub fn package_event(event: WebhookEvent, run: RunContext, package: PackageResult) -> Self {
+        Self {
+            status: "running".to_string(),
+            package: Some(package),
+            ..Self::event(event, run)
+        }
+    }
+
+    /// `fetch.failed` when the recipes could not be fetched or parsed.
+    pub fn fetch_failed(run: RunContext, error: &str) -> Self {
+        Self {
+            status: "failed".to_string(),
+            error: Some(error.to_string()),
+            ..Self::event(WebhookEvent::FetchFailed, run)
+        }
+    }
+
+    /// `run.finished` for a run that ended without per-package results:
+    /// `failed` (with `error`), `cancelled` or `dry_run` (listing the planned packages).
+    pub fn ended(run: RunContext, status: &str, packages: Vec<PackageResult>, error: Option<String>, elapsed_seconds: u64) -> Self {
+        Self {
+            status: status.to_string(),
+            total: packages.len(),
+            packages,
+            error,
+            elapsed_seconds,
+            ..Self::event(WebhookEvent::RunFinished, run)
+        }
+    }
+}
+
+fn hostname() -> String {
+    logging::output(
+        Command::new("hostname")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null()),
+    )
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|h| !h.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok().filter(|h| !h.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PackageResult {
     pub name: String,
     pub status: String,
+    /// Whether the package is a Homebrew cask.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cask: bool,
+    /// Whether this run installed the package and left it installed, even if
+    /// a later step such as a `post_install` hook failed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub installed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<FailureReason>,
+    /// Wall time from the package's first brew command to its outcome.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+    /// How many times brew ran the operation for this package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+    /// Installed version after a successful install or upgrade.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Error lines from brew's output for a failed package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PackageResult {
+    /// Results for the tracked `progress` of `packages`, in the same order.
+    pub fn for_packages(progress: &[PackageProgress], packages: &[BrewPackage]) -> Vec<Self> {
+        progress
+            .iter()
+            .zip(packages)
+            .map(|(progress, package)| Self {
+                cask: package.cask.is_some(),
+                ..Self::from_progress(progress)
+            })
+            .collect()
+    }
+
+    pub fn from_progress(progress: &PackageProgress) -> Self {
+        let failed = progress.failure.is_some() || progress.state_label() == "failed";
+        Self {
+            name: progress.name.clone(),
+            status: progress.state_label().to_string(),
+            cask: false,
+            installed: progress.installed,
+            reason: progress.failure,
+            duration_seconds: progress.duration.map(|d| (d.as_secs_f64() * 100.0).round() / 100.0),
+            attempts: Some(progress.attempts).filter(|a| *a > 0),
+            version: progress.version.clone(),
+            error: progress
+                .error
+                .clone()
+                .or_else(|| Some(progress.message.clone()))
+                .filter(|e| failed && !e.is_empty()),
+        }
+    }
 }
 
-pub async fn post_webhook(url: &str, payload: WebhookPayload) -> Result<(), String> {
+/// Process exit code for a finished run: 0 when nothing failed, the reason's
+/// own code when every failure shares one reason, 1 otherwise.
+pub fn exit_code_for(results: &[PackageResult]) -> i32 {
+    let mut reasons = results
+        .iter()
+        .filter(|r| r.status == "failed")
+        .map(|r| r.reason.unwrap_or(FailureReason::Unknown));
+
+    let Some(first) = reasons.next() else {
+        return 0;
+    };
+
+    if reasons.all(|r| r == first) {
+        first.exit_code()
+    } else {
+        1
+    }
+}
+
+/// Where and how to deliver a payload.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WebhookTarget {
+    pub url: String,
+    /// Shared secret for the `X-Brim-Signature` header; unsigned when `None`.
+    pub secret: Option<String>,
+    /// Extra attempts after the first on network errors and 5xx responses.
+    pub retries: u32,
+    /// Extra request headers, e.g. an API key.
+    pub headers: Vec<(String, String)>,
+    pub format: WebhookFormat,
+    /// Template file whose `{{placeholder}}`s are filled in; replaces `format` when set.
+    pub template: Option<PathBuf>,
+}
+
+impl WebhookTarget {
+    /// The request body for `payload`.
+    pub fn body(&self, payload: &WebhookPayload) -> Result<Vec<u8>, String> {
+        match self.template {
+            Some(ref path) => {
+                let template = fs::read_to_string(path)
+                    .map_err(|e| format!("Could not read webhook template {}: {}", path.display(), e))?;
+                render_template(&template, payload)
+            }
+            None => render(payload, self.format),
+        }
+    }
+}
+
+pub const SIGNATURE_HEADER: &str = "X-Brim-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Brim-Timestamp";
+
+/// Parses a `Name: value` header.
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid webhook header '{}': expected 'Name: value'", header))?;
+    let name = name.trim();
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)) {
+        return Err(format!("Invalid webhook header name '{}'", name));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `{timestamp}.{body}` under `secret`.
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    let hex: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+/// Wait before retry `attempt` (1-based): 1 s, 2 s, 4 s, … capped at 30 s.
+pub fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(2u64.saturating_pow(attempt.saturating_sub(1)).min(30))
+}
+
+/// A delivery that did not succeed, after `attempts` tries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryFailure {
+    pub message: String,
+    pub attempts: u32,
+    /// False when the server rejected the request, so sending it again won't help.
+    pub retryable: bool,
+}
+
+/// Posts an already rendered `body` to `target`, retrying network errors, 5xx,
+/// 408 and 429 responses. Each attempt is signed with a fresh timestamp.
+pub async fn post_body(target: &WebhookTarget, body: &[u8]) -> Result<(), DeliveryFailure> {
+    let failure = |message: String, attempts: u32, retryable: bool| DeliveryFailure {
+        message,
+        attempts,
+        retryable,
+    };
     let client = reqwest::Client::builder()
         .timeout(Duration::from_secs(10))
         .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        .map_err(|e| failure(format!("Failed to create HTTP client: {}", e), 0, true))?;
 
-    client
-        .post(url)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to post webhook: {}", e))?;
+    let mut attempt = 0;
+    loop {
+        let mut request = client
+            .post(&target.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_vec());
+        for (name, value) in &target.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if let Some(ref secret) = target.secret {
+            let timestamp = chrono::Utc::now().timestamp();
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(SIGNATURE_HEADER, signature(secret, timestamp, body));
+        }
+
+        attempt += 1;
+        let started = Instant::now();
+        let result = request.send().await;
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(ref response) => log::debug!(
+                target: "webhook",
+                endpoint = endpoint_host(&target.url).as_str(),
+                attempt = attempt,
+                status = response.status().as_u16(),
+                duration_ms = duration_ms;
+                "Posted webhook"
+            ),
+            Err(ref e) => log::debug!(
+                target: "webhook",
+                endpoint = endpoint_host(&target.url).as_str(),
+                attempt = attempt,
+                error:% = e,
+                duration_ms = duration_ms;
+                "Webhook request failed"
+            ),
+        }
+
+        let error = match result {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) if retryable_status(response.status()) => format!("server responded {}", response.status()),
+            Ok(response) => {
+                return Err(failure(
+                    format!("Webhook rejected: server responded {}", response.status()),
+                    attempt,
+                    false,
+                ))
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if attempt > target.retries {
+            let tries = if attempt == 1 { String::new() } else { format!(" after {} attempts", attempt) };
+            return Err(failure(format!("Failed to post webhook{}: {}", tries, error), attempt, true));
+        }
+        let delay = retry_delay(attempt);
+        log::info!(
+            target: "webhook",
+            endpoint = endpoint_host(&target.url).as_str(),
+            attempt = attempt,
+            delay_ms = delay.as_millis() as u64;
+            "Retrying webhook: {}",
+            error
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// The host part of a webhook URL for logs; chat webhook URLs carry their token in the path.
+pub fn endpoint_host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(String::from))
+        .unwrap_or_else(|| "(invalid url)".to_string())
+}
 
-    Ok(())
+/// 5xx, plus the 4xx codes that mean "try again later" rather than "never".
+fn retryable_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error()
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
 }
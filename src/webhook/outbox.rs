@@ -0,0 +1,195 @@
+use super::{endpoint_host, post_body, DeliveryFailure, Endpoint};
+use crate::utilities::brim_state::state_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// How long an undelivered event is kept before it is dropped, unless configured.
+pub const DEFAULT_OUTBOX_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A webhook delivery that failed and waits in the outbox for another try.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub id: String,
+    pub url: String,
+    pub event: String,
+    pub run_id: String,
+    /// RFC 3339 time the first delivery failed.
+    pub queued_at: String,
+    /// Delivery attempts so far, including retries.
+    pub attempts: u32,
+    pub last_error: String,
+    /// The request body as first rendered; it is signed again on every attempt.
+    pub body: String,
+}
+
+impl OutboxEntry {
+    pub fn new(url: &str, event: &str, run_id: &str, body: &[u8], failure: &DeliveryFailure) -> Self {
+        // Several events can fail within the same microsecond, so a counter keeps ids unique.
+        static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+        let now = chrono::Local::now();
+        Self {
+            id: format!(
+                "{}-{}-{:04}",
+                now.format("%Y%m%d-%H%M%S-%6f"),
+                std::process::id(),
+                SEQUENCE.fetch_add(1, Ordering::Relaxed)
+            ),
+            url: url.to_string(),
+            event: event.to_string(),
+            run_id: run_id.to_string(),
+            queued_at: now.to_rfc3339(),
+            attempts: failure.attempts,
+            last_error: failure.message.clone(),
+            body: String::from_utf8_lossy(body).to_string(),
+        }
+    }
+
+    /// Whether the entry was queued more than `ttl` ago. Unreadable times count as expired.
+    pub fn expired(&self, ttl: Duration, now: chrono::DateTime<chrono::Local>) -> bool {
+        match chrono::DateTime::parse_from_rfc3339(&self.queued_at) {
+            Ok(queued) => now.signed_duration_since(queued).to_std().is_ok_and(|age| age > ttl),
+            Err(_) => true,
+        }
+    }
+}
+
+pub fn outbox_dir() -> PathBuf {
+    state_dir().join("outbox")
+}
+
+pub fn save_entry(dir: &Path, entry: &OutboxEntry) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let json = serde_json::to_string_pretty(entry).map_err(io::Error::other)?;
+    fs::write(dir.join(format!("{}.json", entry.id)), json)
+}
+
+fn remove_entry(dir: &Path, entry: &OutboxEntry) {
+    let _ = fs::remove_file(dir.join(format!("{}.json", entry.id)));
+}
+
+/// Queued entries, oldest first. Unreadable files are skipped.
+pub fn load_entries(dir: &Path) -> Vec<OutboxEntry> {
+    let Ok(files) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut entries: Vec<OutboxEntry> = files
+        .filter_map(Result::ok)
+        .filter(|f| f.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|f| fs::read_to_string(f.path()).ok())
+        .filter_map(|content| serde_json::from_str(&content).ok())
+        .collect();
+
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    entries
+}
+
+#[derive(Debug, Default)]
+pub struct FlushReport {
+    pub delivered: usize,
+    pub expired: usize,
+    /// Entries the server refused outright, with its response. They are dropped.
+    pub rejected: Vec<OutboxEntry>,
+    /// Entries still queued, with the error from this flush.
+    pub remaining: Vec<OutboxEntry>,
+    /// Entries for a URL no configured endpoint has any more. They are not sent
+    /// without that endpoint's secret and headers, and stay queued until they expire.
+    pub unconfigured: Vec<OutboxEntry>,
+}
+
+impl FlushReport {
+    pub fn is_empty(&self) -> bool {
+        self.delivered == 0 && self.expired == 0 && self.rejected.is_empty() && self.remaining.is_empty()
+    }
+}
+
+pub struct FlushOptions {
+    pub ttl: Duration,
+    /// Overrides every endpoint's retries, e.g. 0 for a quick pass at startup.
+    pub retries: Option<u32>,
+    /// Leave the rest queued after the first failure, since the network is likely still down.
+    pub stop_on_failure: bool,
+}
+
+/// Retries every entry in `dir`, oldest first, dropping those older than the TTL
+/// and those the server rejects.
+/// Secrets and headers come from the configured endpoint with the same URL, since
+/// they are never written to disk; entries without one are skipped.
+pub async fn flush_outbox(dir: &Path, endpoints: &[Endpoint], options: &FlushOptions) -> FlushReport {
+    let mut report = FlushReport::default();
+    let now = chrono::Local::now();
+    let mut stopped = false;
+
+    for mut entry in load_entries(dir) {
+        if entry.expired(options.ttl, now) {
+            log::debug!(
+                target: "webhook",
+                id = entry.id.as_str(),
+                queued_at = entry.queued_at.as_str();
+                "Dropping expired outbox entry"
+            );
+            remove_entry(dir, &entry);
+            report.expired += 1;
+            continue;
+        }
+        let Some(endpoint) = endpoints.iter().find(|e| e.target.url == entry.url) else {
+            log::debug!(
+                target: "webhook",
+                id = entry.id.as_str(),
+                endpoint = endpoint_host(&entry.url).as_str();
+                "Skipping outbox entry for an endpoint that is no longer configured"
+            );
+            report.unconfigured.push(entry);
+            continue;
+        };
+        if stopped {
+            report.remaining.push(entry);
+            continue;
+        }
+
+        let mut target = endpoint.target.clone();
+        if let Some(retries) = options.retries {
+            target.retries = retries;
+        }
+
+        log::debug!(
+            target: "webhook",
+            id = entry.id.as_str(),
+            event = entry.event.as_str();
+            "Retrying outbox entry"
+        );
+        match post_body(&target, entry.body.as_bytes()).await {
+            Ok(()) => {
+                remove_entry(dir, &entry);
+                report.delivered += 1;
+            }
+            Err(failure) => {
+                entry.attempts += failure.attempts;
+                entry.last_error = failure.message;
+                if failure.retryable {
+                    let _ = save_entry(dir, &entry);
+                    report.remaining.push(entry);
+                    stopped = options.stop_on_failure;
+                } else {
+                    remove_entry(dir, &entry);
+                    report.rejected.push(entry);
+                }
+            }
+        }
+    }
+
+    log::debug!(
+        target: "webhook",
+        delivered = report.delivered,
+        expired = report.expired,
+        rejected = report.rejected.len(),
+        remaining = report.remaining.len(),
+        unconfigured = report.unconfigured.len();
+        "Flushed outbox"
+    );
+    report
+}